@@ -1,26 +1,29 @@
 use std::sync::{Arc, Mutex, RwLock};
+use serde::{Serialize, Deserialize};
 
 use crate::{
-    environment::{TheSun, WindSpeed, months::Month},
-    simulation::{SimFlo, SimInt},
-    ui_controller::{Cloud, Date, WindDirection},
+    environment::{Cloud, TheSun, WindDirection, WindSpeed, months::{Month, month_ref}},
+    simulation::{SimFlo, SimInt, timer::Date},
     economy::{
-        products::{Product, ProductStock},
+        products::{Product, ProductStock, product_refs},
         industries::Industry,
         economy_types::{Money, EnergyUnit, UpDown, ProductDemand},
         solarpanel::SolarPanel,
+        windturbine::WindTurbine,
+        battery::Battery,
     },
     utils_data::{ReadOnlyRwLock, SlidingWindow},
 };
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TimerStateData {
     pub date: Date,
     pub timestamp: u128,
+    #[serde(with = "month_ref")]
     pub month_data: &'static Month,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EnvStateData {
     pub clouds: Vec<Cloud>,
     pub wind_speed: WindSpeed,
@@ -40,11 +43,13 @@ pub struct MiscStateData {
     pub speed_index: usize,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PovverPlantStateData {
     pub fuel: SimInt,
     pub fuel_capacity: SimInt,
     pub production_capacity: EnergyUnit,
+    // Energy that is being produced for factories right now.
+    pub committed_energy: SimInt,
     pub balance: Money,
     pub is_awaiting_fuel: bool,
     pub is_awaiting_fuel_capacity: bool,
@@ -52,26 +57,50 @@ pub struct PovverPlantStateData {
     pub is_bankrupt: bool,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FactoryStateData {
     pub balance: Money,
     pub available_energy: EnergyUnit,
     pub product_stocks: Vec<ProductStock>,
     pub solarpanels: Vec<SolarPanel>,
+    pub windturbines: Vec<WindTurbine>,
+    pub batteries: Vec<Battery>,
     pub industry: Industry,
+    #[serde(with = "product_refs")]
     pub product_portfolio: Vec<&'static Product>,
     pub id: usize,
     pub is_bankrupt: bool,
     pub is_awaiting_solarpanels: bool,
+    pub is_awaiting_windturbines: bool,
+    pub is_awaiting_batteries: bool,
+    // Energy received since the start, from the Povver Plant, from its own solar panels and wind turbines
+    // and bought from other factories. And the energy it sold to other factories.
+    pub energy_from_pp_total: u64,
+    pub energy_from_solar_total: u64,
+    pub energy_from_wind_total: u64,
+    pub energy_from_factories_total: u64,
+    pub energy_to_factories_total: u64,
 }
 
-#[derive(Debug)]
+impl FactoryStateData {
+    // Energy in the batteries. It doesn't expire with the day like the available energy.
+    pub fn stored_energy(&self) -> SimInt {
+        self.batteries.iter().map(|battery| battery.stored()).sum()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EconomyStateData {
     pub inflation_rate: SimFlo,
     pub inflation_direction: UpDown,
     pub fuel_price: Money,
     pub product_demands: Vec<ProductDemand>,
     pub past_25_product_demands: SlidingWindow<ProductDemand>,
+    // Product units asked for by every demand since the start, and how many of them were sold.
+    pub demanded_units_total: u64,
+    pub sold_units_total: u64,
+    // Price of a unit of energy in the last clearing round of the energy exchange that had any trades.
+    pub exchange_price: Option<SimFlo>,
 }
 
 pub struct HubState {
@@ -94,7 +123,8 @@ pub struct StatePayload {
     pub env: ReadOnlyRwLock<EnvStateData>,
     pub economy: ReadOnlyRwLock<EconomyStateData>,
     pub povver_plant: ReadOnlyRwLock<PovverPlantStateData>,
-    pub factories: ReadOnlyRwLock<Vec<ReadOnlyRwLock<FactoryStateData>>>,
+    // Shared with the hub, so factories added or retired on the way show up here too.
+    pub factories: ReadOnlyRwLock<Vec<Arc<RwLock<FactoryStateData>>>>,
     pub misc: Arc<Mutex<MiscStateData>>,
 }
 
@@ -120,18 +150,12 @@ impl AppState {
 
 impl AppState {
     pub fn get_state_payload(&self) -> Arc<StatePayload> {
-        let factories = ReadOnlyRwLock::new(self.hub.factories.read().unwrap()
-            .iter()
-            .map(|factory| {
-                ReadOnlyRwLock::from(Arc::clone(factory))
-            }).collect());
-
         Arc::new(StatePayload {
             timer: ReadOnlyRwLock::from(Arc::clone(&self.timer)),
             env: ReadOnlyRwLock::from(Arc::clone(&self.env)),
             economy: ReadOnlyRwLock::from(Arc::clone(&self.economy)),
             povver_plant: ReadOnlyRwLock::from(Arc::clone(&self.hub.povver_plant)),
-            factories,
+            factories: ReadOnlyRwLock::from(Arc::clone(&self.hub.factories)),
             misc: Arc::clone(&self.misc),
         })
     }
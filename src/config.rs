@@ -0,0 +1,535 @@
+use std::{
+    fmt,
+    fs,
+    io,
+    path::Path,
+};
+use serde::{Serialize, Deserialize};
+
+use crate::{
+    economy::{
+        economy_constants::*,
+        economy_types::TariffWindow,
+    },
+    simulation::{
+        SimFlo,
+        SimInt,
+        sim_constants::*,
+        calendar::Calendar,
+    },
+    utils_data::tidy_toml_floats,
+};
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Format(toml::de::Error),
+    Override(String),
+    Invalid(Vec<String>),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "config file error: {e}"),
+            Self::Format(e) => write!(f, "malformed config: {e}"),
+            Self::Override(reason) => write!(f, "bad config override: {reason}"),
+            Self::Invalid(problems) => {
+                write!(f, "invalid config:")?;
+                for problem in problems {
+                    write!(f, "\n  - {problem}")?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<io::Error> for ConfigError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for ConfigError {
+    fn from(e: toml::de::Error) -> Self {
+        Self::Format(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EconomyConfig {
+    pub inflation_min: SimFlo,
+    pub inflation_max: SimFlo,
+    pub init_fuel_price: SimFlo,
+    pub fuel_price_min: SimFlo,
+    pub fuel_price_max: SimFlo,
+    // How much the fuel price moves each month, before inflation is applied.
+    pub fuel_price_modifier: SimFlo,
+}
+
+impl Default for EconomyConfig {
+    fn default() -> Self {
+        Self {
+            inflation_min: INFLATION_MIN,
+            inflation_max: INFLATION_MAX,
+            init_fuel_price: FUEL_PRICE_INIT,
+            fuel_price_min: FUEL_PRICE_MIN,
+            fuel_price_max: FUEL_PRICE_MAX,
+            fuel_price_modifier: FUEL_PRICE_MODIFIER,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PovverPlantConfig {
+    pub init_money: SimFlo,
+    pub init_fuel_capacity: SimInt,
+    pub init_production_capacity: SimInt,
+    pub init_fuel_buy_threshold: SimInt,
+    pub profit_margin: SimFlo,
+    pub energy_per_fuel: SimInt,
+    pub fuel_capacity_increase: SimInt,
+    pub fuel_capacity_increase_cost: SimFlo,
+    pub production_capacity_increase: SimInt,
+    pub production_capacity_increase_cost: SimFlo,
+}
+
+impl Default for PovverPlantConfig {
+    fn default() -> Self {
+        Self {
+            init_money: PP_INIT_MONEY,
+            init_fuel_capacity: PP_INIT_FUEL_CAPACITY,
+            init_production_capacity: PP_INIT_PRODUCTION_CAP,
+            init_fuel_buy_threshold: PP_INIT_FUEL_BUY_THRESHOLD,
+            profit_margin: PP_PROFIT_MARGIN,
+            energy_per_fuel: PP_ENERGY_PER_FUEL,
+            fuel_capacity_increase: PP_FUEL_CAPACITY_INCREASE,
+            fuel_capacity_increase_cost: PP_FUEL_CAPACITY_INCREASE_COST,
+            production_capacity_increase: PP_PRODUCTION_CAPACITY_INCREASE,
+            production_capacity_increase_cost: PP_PRODUCTION_CAPACITY_INCREASE_COST,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FactoryConfig {
+    pub init_money: SimFlo,
+    pub max_solar_panels: usize,
+    // Factories only invest in solar panels with the money they have above this.
+    pub solar_panel_reserve: SimFlo,
+    pub max_wind_turbines: usize,
+    // And in wind turbines with the money above this, after the solar panels are paid for.
+    pub wind_turbine_reserve: SimFlo,
+    pub max_batteries: usize,
+    // Batteries come last, and only for factories that have renewables to fill them.
+    pub battery_reserve: SimFlo,
+    // Defaults of the factory strategy. Scenarios can set them per factory.
+    pub profit_margin: SimFlo,
+    pub product_demand_sell_threshold: SimFlo,
+    // Percent under what they paid for energy that factories sell their spare energy to each other for.
+    pub energy_resale_discount: SimFlo,
+}
+
+impl Default for FactoryConfig {
+    fn default() -> Self {
+        Self {
+            init_money: FACTORY_INIT_MONEY,
+            max_solar_panels: FACTORY_MAX_SOLAR_PANELS,
+            solar_panel_reserve: FACTORY_SOLAR_PANEL_RESERVE,
+            max_wind_turbines: FACTORY_MAX_WIND_TURBINES,
+            wind_turbine_reserve: FACTORY_WIND_TURBINE_RESERVE,
+            max_batteries: FACTORY_MAX_BATTERIES,
+            battery_reserve: FACTORY_BATTERY_RESERVE,
+            profit_margin: FACTORY_PROFIT_MARGIN,
+            product_demand_sell_threshold: FACTORY_PRODUCT_DEMAND_SELL_THRESHOLD,
+            energy_resale_discount: FACTORY_ENERGY_RESALE_DISCOUNT,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SolarPanelConfig {
+    pub price: SimFlo,
+    // In months
+    pub max_age: SimInt,
+    // Energy a panel loses for each month of its age.
+    pub age_modifier: SimInt,
+}
+
+impl Default for SolarPanelConfig {
+    fn default() -> Self {
+        Self {
+            price: SOLAR_PANEL_PRICE,
+            max_age: SOLAR_PANEL_MAX_AGE,
+            age_modifier: SOLAR_PANEL_AGE_MODIFIER,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindTurbineConfig {
+    pub price: SimFlo,
+    // In months
+    pub max_age: SimInt,
+    // Energy a turbine loses for each month of its age.
+    pub age_modifier: SimInt,
+    // Energy of an hour at rated speed and above.
+    pub rated_energy: SimInt,
+    // Wind speeds of the power curve. Turbines stand still below cut-in and from cut-out on.
+    pub cut_in_speed: SimInt,
+    pub rated_speed: SimInt,
+    pub cut_out_speed: SimInt,
+}
+
+impl Default for WindTurbineConfig {
+    fn default() -> Self {
+        Self {
+            price: WIND_TURBINE_PRICE,
+            max_age: WIND_TURBINE_MAX_AGE,
+            age_modifier: WIND_TURBINE_AGE_MODIFIER,
+            rated_energy: WIND_TURBINE_RATED_ENERGY,
+            cut_in_speed: WIND_TURBINE_CUT_IN_SPEED,
+            rated_speed: WIND_TURBINE_RATED_SPEED,
+            cut_out_speed: WIND_TURBINE_CUT_OUT_SPEED,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BatteryConfig {
+    pub price: SimFlo,
+    pub capacity: SimInt,
+    // Energy that can go in and come out of a battery in an hour.
+    pub charge_rate: SimInt,
+    pub discharge_rate: SimInt,
+    // Percent of the energy put in that can be drawn again.
+    pub round_trip_efficiency: SimFlo,
+    // Percent of its capacity a battery loses every month.
+    pub degradation: SimFlo,
+}
+
+impl Default for BatteryConfig {
+    fn default() -> Self {
+        Self {
+            price: BATTERY_PRICE,
+            capacity: BATTERY_CAPACITY,
+            charge_rate: BATTERY_CHARGE_RATE,
+            discharge_rate: BATTERY_DISCHARGE_RATE,
+            round_trip_efficiency: BATTERY_ROUND_TRIP_EFFICIENCY,
+            degradation: BATTERY_DEGRADATION,
+        }
+    }
+}
+
+// The energy exchange of the hub. While it's on, energy only changes hands in its clearing rounds,
+// instead of the offers the agents make to each other.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExchangeConfig {
+    pub enabled: bool,
+    // Minutes between two clearing rounds.
+    pub clearing_interval: SimInt,
+    // Percent above what energy cost them lately that factories bid up to.
+    pub bid_markup: SimFlo,
+}
+
+impl Default for ExchangeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: EXCHANGE_ENABLED,
+            clearing_interval: EXCHANGE_CLEARING_INTERVAL,
+            bid_markup: EXCHANGE_BID_MARKUP,
+        }
+    }
+}
+
+// Time-of-use pricing of the Povver Plant. Hours that are neither peak nor off-peak are shoulder hours.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TariffConfig {
+    pub enabled: bool,
+    // Hours of the day, from 0 to 23.
+    pub peak_hours: Vec<SimInt>,
+    pub off_peak_hours: Vec<SimInt>,
+    // What the energy price is multiplied with in each window.
+    pub peak_multiplier: SimFlo,
+    pub shoulder_multiplier: SimFlo,
+    pub off_peak_multiplier: SimFlo,
+    // Percent the price goes up by when all of the Povver Plant's production capacity is committed.
+    pub load_markup: SimFlo,
+}
+
+impl Default for TariffConfig {
+    fn default() -> Self {
+        Self {
+            enabled: TARIFF_ENABLED,
+            peak_hours: TARIFF_PEAK_HOURS.to_vec(),
+            off_peak_hours: TARIFF_OFF_PEAK_HOURS.to_vec(),
+            peak_multiplier: TARIFF_PEAK_MULTIPLIER,
+            shoulder_multiplier: TARIFF_SHOULDER_MULTIPLIER,
+            off_peak_multiplier: TARIFF_OFF_PEAK_MULTIPLIER,
+            load_markup: TARIFF_LOAD_MARKUP,
+        }
+    }
+}
+
+impl TariffConfig {
+    pub fn window(&self, hour: SimInt) -> TariffWindow {
+        if self.peak_hours.contains(&hour) {
+            TariffWindow::Peak
+        } else if self.off_peak_hours.contains(&hour) {
+            TariffWindow::OffPeak
+        } else {
+            TariffWindow::Shoulder
+        }
+    }
+
+    pub fn multiplier(&self, window: TariffWindow) -> SimFlo {
+        match window {
+            TariffWindow::Peak => self.peak_multiplier,
+            TariffWindow::Shoulder => self.shoulder_multiplier,
+            TariffWindow::OffPeak => self.off_peak_multiplier,
+        }
+    }
+
+    // What a unit of energy costs in the hour's window, relative to the plain price,
+    // with load being the committed share of the production capacity from 0 to 1.
+    pub fn price_factor(&self, hour: SimInt, load: SimFlo) -> SimFlo {
+        if !self.enabled {
+            return 1.0;
+        }
+
+        self.multiplier(self.window(hour)) * (1.0 + load.clamp(0.0, 1.0) * self.load_markup / 100.0)
+    }
+
+    // Hours from the given hour until one with a cheaper window starts, if there's any within a day.
+    pub fn hours_until_cheaper(&self, hour: SimInt) -> Option<SimInt> {
+        if !self.enabled {
+            return None;
+        }
+
+        let multiplier = self.multiplier(self.window(hour));
+        (1..24).find(|ahead| self.multiplier(self.window((hour + ahead) % 24)) < multiplier)
+    }
+}
+
+// Every balance knob of the simulation. The built-in defaults come from the constants,
+// a config file overrides them and `section.key=value` overrides go on top of that.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub economy: EconomyConfig,
+    pub povver_plant: PovverPlantConfig,
+    pub factory: FactoryConfig,
+    pub solar_panel: SolarPanelConfig,
+    pub wind_turbine: WindTurbineConfig,
+    pub battery: BatteryConfig,
+    pub exchange: ExchangeConfig,
+    pub tariff: TariffConfig,
+    pub calendar: Calendar,
+}
+
+impl Config {
+    pub fn layered(path: Option<&Path>, overrides: &[String]) -> Result<Self, ConfigError> {
+        let mut value = toml::Value::try_from(Self::default()).unwrap();
+
+        if let Some(path) = path {
+            let file_value: toml::Value = toml::from_str(&fs::read_to_string(path)?)?;
+            merge(&mut value, file_value);
+        }
+
+        for entry in overrides {
+            Self::apply_override(&mut value, entry)?;
+        }
+
+        let config: Self = value.try_into()?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    // Values are TOML, so `factory.profit_margin=25` works just like it does in the file.
+    fn apply_override(value: &mut toml::Value, entry: &str) -> Result<(), ConfigError> {
+        let (key, raw) = entry.split_once('=')
+            .ok_or_else(|| ConfigError::Override(format!("\"{entry}\" is not in the form section.key=value")))?;
+        let new_value = toml::from_str::<toml::Table>(&format!("value = {}", raw.trim()))
+            .map_err(|_| ConfigError::Override(format!("\"{}\" is not a valid value for {key}", raw.trim())))?
+            .remove("value")
+            .unwrap();
+
+        // Every known key is in the tree already since we start from the defaults.
+        let mut target = value;
+        for part in key.trim().split('.') {
+            target = target.get_mut(part)
+                .ok_or_else(|| ConfigError::Override(format!("unknown config key \"{key}\"")))?;
+        }
+        if target.is_table() {
+            return Err(ConfigError::Override(format!("\"{key}\" is a section, not a key")));
+        }
+        *target = new_value;
+
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+
+        let econ = &self.economy;
+        if econ.inflation_min > econ.inflation_max {
+            problems.push(format!("economy: inflation_min {} is above inflation_max {}", econ.inflation_min, econ.inflation_max));
+        }
+        if econ.fuel_price_min <= 0.0 || econ.fuel_price_min > econ.fuel_price_max {
+            problems.push(format!(
+                "economy: fuel prices must be positive with fuel_price_min {} not above fuel_price_max {}",
+                econ.fuel_price_min, econ.fuel_price_max
+            ));
+        }
+        if !(econ.fuel_price_min..=econ.fuel_price_max).contains(&econ.init_fuel_price) {
+            problems.push(format!("economy: init_fuel_price {} is outside of the fuel price range", econ.init_fuel_price));
+        }
+
+        let pp = &self.povver_plant;
+        if pp.energy_per_fuel <= 0 {
+            problems.push(format!("povver_plant: energy_per_fuel must be positive, got {}", pp.energy_per_fuel));
+        }
+        if pp.init_fuel_capacity <= 0 || pp.init_production_capacity <= 0 {
+            problems.push("povver_plant: initial capacities must be positive".to_string());
+        }
+        if pp.fuel_capacity_increase < 0 || pp.production_capacity_increase < 0 {
+            problems.push("povver_plant: capacity increases must not be negative".to_string());
+        }
+        if pp.profit_margin < 0.0 {
+            problems.push(format!("povver_plant: profit_margin must not be negative, got {}", pp.profit_margin));
+        }
+
+        let factory = &self.factory;
+        if factory.profit_margin < 0.0 {
+            problems.push(format!("factory: profit_margin must not be negative, got {}", factory.profit_margin));
+        }
+        if !(0.0..=100.0).contains(&factory.product_demand_sell_threshold) {
+            problems.push(format!(
+                "factory: product_demand_sell_threshold must be between 0 and 100, got {}", factory.product_demand_sell_threshold
+            ));
+        }
+        if !(0.0..=100.0).contains(&factory.energy_resale_discount) {
+            problems.push(format!(
+                "factory: energy_resale_discount must be between 0 and 100, got {}", factory.energy_resale_discount
+            ));
+        }
+
+        let panel = &self.solar_panel;
+        if panel.price <= 0.0 {
+            problems.push(format!("solar_panel: price must be positive, got {}", panel.price));
+        }
+        if panel.max_age <= 0 {
+            problems.push(format!("solar_panel: max_age must be positive, got {}", panel.max_age));
+        }
+
+        let turbine = &self.wind_turbine;
+        if turbine.price <= 0.0 {
+            problems.push(format!("wind_turbine: price must be positive, got {}", turbine.price));
+        }
+        if turbine.max_age <= 0 {
+            problems.push(format!("wind_turbine: max_age must be positive, got {}", turbine.max_age));
+        }
+        if turbine.rated_energy < 0 {
+            problems.push(format!("wind_turbine: rated_energy must not be negative, got {}", turbine.rated_energy));
+        }
+        if !(0 <= turbine.cut_in_speed && turbine.cut_in_speed < turbine.rated_speed && turbine.rated_speed <= turbine.cut_out_speed) {
+            problems.push(format!(
+                "wind_turbine: speeds must go 0 <= cut_in_speed < rated_speed <= cut_out_speed, got {}, {} and {}",
+                turbine.cut_in_speed, turbine.rated_speed, turbine.cut_out_speed
+            ));
+        }
+
+        let battery = &self.battery;
+        if battery.price <= 0.0 {
+            problems.push(format!("battery: price must be positive, got {}", battery.price));
+        }
+        if battery.capacity <= 0 {
+            problems.push(format!("battery: capacity must be positive, got {}", battery.capacity));
+        }
+        if battery.charge_rate <= 0 || battery.discharge_rate <= 0 {
+            problems.push("battery: charge_rate and discharge_rate must be positive".to_string());
+        }
+        if !(battery.round_trip_efficiency > 0.0 && battery.round_trip_efficiency <= 100.0) {
+            problems.push(format!(
+                "battery: round_trip_efficiency must be above 0 and at most 100, got {}", battery.round_trip_efficiency
+            ));
+        }
+        if !(0.0..=100.0).contains(&battery.degradation) {
+            problems.push(format!("battery: degradation must be between 0 and 100, got {}", battery.degradation));
+        }
+
+        let exchange = &self.exchange;
+        if exchange.clearing_interval <= 0 {
+            problems.push(format!("exchange: clearing_interval must be positive, got {}", exchange.clearing_interval));
+        }
+        if !(0.0..=100.0).contains(&exchange.bid_markup) {
+            problems.push(format!("exchange: bid_markup must be between 0 and 100, got {}", exchange.bid_markup));
+        }
+
+        let tariff = &self.tariff;
+        if let Some(hour) = tariff.peak_hours.iter().chain(tariff.off_peak_hours.iter()).find(|hour| !(0..24).contains(*hour)) {
+            problems.push(format!("tariff: hours must be between 0 and 23, got {hour}"));
+        }
+        if let Some(hour) = tariff.peak_hours.iter().find(|hour| tariff.off_peak_hours.contains(hour)) {
+            problems.push(format!("tariff: hour {hour} is both a peak and an off-peak hour"));
+        }
+        if tariff.peak_multiplier <= 0.0 || tariff.shoulder_multiplier <= 0.0 || tariff.off_peak_multiplier <= 0.0 {
+            problems.push("tariff: multipliers must be positive".to_string());
+        }
+        if !(0.0..=100.0).contains(&tariff.load_markup) {
+            problems.push(format!("tariff: load_markup must be between 0 and 100, got {}", tariff.load_markup));
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(problems))
+        }
+    }
+
+    // The built-in value of a `section.key`, or the whole section.
+    pub fn default_value(key: &str) -> Option<toml::Value> {
+        let mut value = toml::Value::try_from(Self::default()).unwrap();
+        for part in key.trim().split('.') {
+            value = value.get_mut(part)?.clone();
+        }
+
+        Some(value)
+    }
+
+    pub fn to_toml(&self) -> String {
+        let mut value = toml::Value::try_from(self).unwrap();
+        tidy_toml_floats(&mut value);
+
+        toml::to_string_pretty(&value).unwrap()
+    }
+}
+
+// Tables are merged key by key, anything else in the overlay replaces what's in the base.
+fn merge(base: &mut toml::Value, overlay: toml::Value) {
+    match (base, overlay) {
+        (toml::Value::Table(base), toml::Value::Table(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        },
+        (base, overlay) => *base = overlay,
+    }
+}
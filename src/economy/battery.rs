@@ -0,0 +1,82 @@
+use serde::{Serialize, Deserialize};
+use crate::{
+    simulation::{
+        SimInt,
+        SimFlo,
+        Percentage,
+        timer::TimerEvent,
+    },
+    config::BatteryConfig,
+    utils_traits::AsFactor,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Battery {
+    capacity: SimInt,
+    stored: SimInt,
+    age: SimInt,
+    // Energy that went in and came out in the current hour, for the rate limits.
+    charged_this_hour: SimInt,
+    discharged_this_hour: SimInt,
+}
+
+impl Battery {
+    pub fn new(config: &BatteryConfig) -> Self {
+        Self {
+            capacity: config.capacity,
+            stored: 0,
+            age: 0,
+            charged_this_hour: 0,
+            discharged_this_hour: 0,
+        }
+    }
+
+    pub fn stored(&self) -> SimInt {
+        self.stored
+    }
+
+    pub fn capacity(&self) -> SimInt {
+        self.capacity
+    }
+
+    // Energy that can still be drawn in this hour.
+    pub fn dischargeable(&self, config: &BatteryConfig) -> SimInt {
+        self.stored.min(config.discharge_rate - self.discharged_this_hour).max(0)
+    }
+
+    // Takes up to the given energy and returns how much it took. What's lost to the
+    // round-trip efficiency is lost on the way in.
+    pub fn charge(&mut self, energy: SimInt, config: &BatteryConfig) -> SimInt {
+        let efficiency = Percentage::new(config.round_trip_efficiency).as_factor();
+        let room = ((self.capacity - self.stored) as SimFlo / efficiency).floor() as SimInt;
+        let taken = energy.min(room).min(config.charge_rate - self.charged_this_hour).max(0);
+
+        self.charged_this_hour += taken;
+        self.stored = (self.stored + (taken as SimFlo * efficiency) as SimInt).min(self.capacity);
+
+        taken
+    }
+
+    // Gives up to the given energy and returns how much it gave.
+    pub fn discharge(&mut self, energy: SimInt, config: &BatteryConfig) -> SimInt {
+        let given = energy.min(self.dischargeable(config)).max(0);
+
+        self.discharged_this_hour += given;
+        self.stored -= given;
+
+        given
+    }
+
+    // Rate limits start over every hour. Capacity fades with every month of age.
+    pub fn new_hour(&mut self, timer_event: &TimerEvent, config: &BatteryConfig) {
+        self.charged_this_hour = 0;
+        self.discharged_this_hour = 0;
+
+        if timer_event.at_least_month() {
+            self.age += 1;
+            let fade = (self.capacity as SimFlo * Percentage::new(config.degradation).as_factor()).ceil() as SimInt;
+            self.capacity = (self.capacity - fade).max(0);
+            self.stored = self.stored.min(self.capacity);
+        }
+    }
+}
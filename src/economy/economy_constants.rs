@@ -1,7 +1,10 @@
 use crate::simulation::SimFlo;
 
+// Built-in defaults of the economy section of the Config.
+
 pub const INFLATION_MAX: SimFlo = 50.0;
 pub const INFLATION_MIN: SimFlo = -10.0;
+pub const FUEL_PRICE_INIT: SimFlo = 200.0;
 pub const FUEL_PRICE_MIN: SimFlo = 100.0;
 pub const FUEL_PRICE_MAX: SimFlo = 1000.0;
 pub const FUEL_PRICE_MODIFIER: SimFlo = 20.00;
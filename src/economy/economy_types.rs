@@ -1,15 +1,27 @@
+#[cfg(feature = "gui")]
 use slint::ToSharedString;
 
+use serde::{Serialize, Deserialize};
 use crate::{
-    economy::products::Product,
+    economy::products::{Product, product_ref},
     simulation::{SimFlo, SimInt, Percentage},
-    ui_controller::{UpDown as UIUpDown, ProductDemand as UIProductDemand },
     utils_traits::{Flippable, AsFactor}
 };
+#[cfg(feature = "gui")]
+use crate::ui_controller::{UpDown as UIUpDown, ProductDemand as UIProductDemand };
 
 
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+// The time-of-use tariff window an hour of the day falls in.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TariffWindow {
+    Peak,
+    #[default]
+    Shoulder,
+    OffPeak,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Money(SimFlo);
 
 impl Money {
@@ -56,11 +68,12 @@ impl Money {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum UpDown {
     Up,
     Down,
 }
+#[cfg(feature = "gui")]
 impl From<UpDown> for UIUpDown {
     fn from(other: UpDown) -> Self {
         match other {
@@ -86,7 +99,7 @@ impl Flippable for UpDown {
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub struct EnergyUnit(SimInt);
 impl EnergyUnit {
     pub const fn new(unit: SimInt) -> Self {
@@ -131,8 +144,9 @@ impl From<EnergyUnit> for SimFlo {
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ProductDemand {
+    #[serde(with = "product_ref")]
     pub product: &'static Product,
     pub percent: Percentage,
     pub age: SimInt,
@@ -157,6 +171,7 @@ impl ProductDemand {
         self.as_units() * self.product.unit_production_cost.energy
     }
 }
+#[cfg(feature = "gui")]
 impl From<&ProductDemand> for UIProductDemand {
     fn from(other: &ProductDemand) -> Self {
         Self {
@@ -3,6 +3,8 @@ use std::{
     sync::{Arc, Mutex},
     time::Duration,
 };
+use rand::Rng;
+use serde::{Serialize, Deserialize};
 use tokio::sync::broadcast as tokio_broadcast;
 
 use crate::{
@@ -11,11 +13,14 @@ use crate::{
         SimInt,
         SimFlo,
         StateAction,
+        ClockMode,
         hub_comms::*,
+        stepper::StepTurn,
+        scenario::FactoryStrategy,
         speed::Speed,
         Percentage,
-        sim_constants::*
     },
+    config::Config,
     economy::{
         economy_types::{Money, ProductDemand},
         products::Product,
@@ -23,14 +28,29 @@ use crate::{
     logger::{LogMessage, Logger, LogLevel::*},
     utils_data::ReadOnlyRwLock,
     utils_traits::AsFactor,
+    utils_random::SimRng,
 };
 use crate::simulation::TickDuration;
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct ProductionRun {
     demand: ProductDemand,
     units: SimInt,
     cost: Money,
     energy_needed: SimInt,
+    // What's left to buy of the energy that doesn't come out of our batteries.
+    energy_to_buy: SimInt,
+}
+
+// What a production run buys of the energy offers it got.
+#[derive(Debug, Default, PartialEq)]
+struct EnergyPurchase {
+    accepted: Vec<FactoryEnergyOffer>,
+    rejected: Vec<FactoryEnergyOffer>,
+    // The Povver Plant's offer cut down to what we buy, and whether we do.
+    pp_offer: Option<(PPEnergyOffer, bool)>,
+    units: SimInt,
+    cost: SimFlo,
 }
 
 pub struct Factory {
@@ -39,27 +59,83 @@ pub struct Factory {
     timer_state_ro: ReadOnlyRwLock<TimerStateData>,
     ui_log_sender: tokio_broadcast::Sender<LogMessage>,
     wakeup_receiver: tokio_broadcast::Receiver<StateAction>,
-    hub_broadcast_receiver: BroadcastDynReceiver,
-    dynamic_sender: BroadcastDynSender,
-    dynamic_receiver: DynamicReceiver,
+    hub_broadcast_receiver: BroadcastInbox,
+    to_hub: Outbox<FactoryHubSignal>,
+    to_pp: Outbox<FactoryPPSignal>,
+    from_hub: Inbox<HubFactorySignal>,
+    from_pp: Inbox<PPFactorySignal>,
     production_runs: Vec<ProductionRun>,
     last_hundred_energy_purchases: Vec<EnergyReceipt>,
+    pp_energy_offer: Option<PPEnergyOffer>,
+    factory_energy_offers: Vec<FactoryEnergyOffer>,
+    is_awaiting_energy_offers: bool,
+    // Our spare energy that other factories haven't answered for yet.
+    energy_offers_made: Vec<FactoryEnergyOffer>,
+    // Energy our production runs still wait for from the energy exchange.
+    exchange_energy_wanted: SimInt,
     product_demand_sell_threshold: Percentage,
     profit_margin: Percentage,
+    rng: SimRng,
     sleeptime: Duration,
+    clock_mode: ClockMode,
+    // Handed over to the thread when it starts, so the turn is gone along with the thread.
+    step_turn: Option<StepTurn>,
+    step_turn_id: usize,
+    config: Arc<Config>,
+}
+
+// What a factory keeps to itself, outside of its FactoryStateData.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FactorySnapshot {
+    production_runs: Vec<ProductionRun>,
+    last_hundred_energy_purchases: Vec<EnergyReceipt>,
+    pp_energy_offer: Option<PPEnergyOffer>,
+    factory_energy_offers: Vec<FactoryEnergyOffer>,
+    is_awaiting_energy_offers: bool,
+    energy_offers_made: Vec<FactoryEnergyOffer>,
+    exchange_energy_wanted: SimInt,
+    product_demand_sell_threshold: Percentage,
+    profit_margin: Percentage,
+    rng: SimRng,
+}
+
+// The state a factory reads and its lines to the hub, the Povver Plant and the UI log.
+pub struct FactoryLinks {
+    pub state_ro: ReadOnlyRwLock<FactoryStateData>,
+    pub econ_state_ro: ReadOnlyRwLock<EconomyStateData>,
+    pub timer_state_ro: ReadOnlyRwLock<TimerStateData>,
+    pub ui_log_sender: tokio_broadcast::Sender<LogMessage>,
+    pub wakeup_receiver: tokio_broadcast::Receiver<StateAction>,
+    pub hub_broadcast_receiver: BroadcastInbox,
+    pub to_hub: Outbox<FactoryHubSignal>,
+    pub to_pp: Outbox<FactoryPPSignal>,
+    pub from_hub: Inbox<HubFactorySignal>,
+    pub from_pp: Inbox<PPFactorySignal>,
 }
 
 impl Factory {
     pub fn new(
-        state_ro: ReadOnlyRwLock<FactoryStateData>,
-        econ_state_ro: ReadOnlyRwLock<EconomyStateData>,
-        timer_state_ro: ReadOnlyRwLock<TimerStateData>,
-        ui_log_sender: tokio_broadcast::Sender<LogMessage>,
-        wakeup_receiver: tokio_broadcast::Receiver<StateAction>,
-        hub_broadcast_receiver: tokio_broadcast::Receiver<DynamicSignal>,
-        dynamic_sender: BroadcastDynSender,
-        dynamic_receiver: DynamicReceiver,
+        links: FactoryLinks,
+        rng: SimRng,
+        strategy: &FactoryStrategy,
+        config: Arc<Config>,
+        clock_mode: ClockMode,
+        step_turn: StepTurn,
     ) -> Self {
+        let FactoryLinks {
+            state_ro,
+            econ_state_ro,
+            timer_state_ro,
+            ui_log_sender,
+            wakeup_receiver,
+            hub_broadcast_receiver,
+            to_hub,
+            to_pp,
+            from_hub,
+            from_pp,
+        } = links;
+        let sleeptime = Self::recalculate_sleeptime(Speed::NORMAL.get_tick_duration());
+
         Self {
             state_ro,
             econ_state_ro,
@@ -67,13 +143,27 @@ impl Factory {
             ui_log_sender,
             wakeup_receiver,
             hub_broadcast_receiver,
-            dynamic_sender,
-            dynamic_receiver,
+            to_hub,
+            to_pp,
+            from_hub,
+            from_pp,
             production_runs: Vec::new(),
             last_hundred_energy_purchases: Vec::new(),
-            product_demand_sell_threshold: Percentage::new(0.0),
-            profit_margin: Percentage::new(20.0),
-            sleeptime: Self::recalculate_sleeptime(Speed::NORMAL.get_tick_duration()),
+            pp_energy_offer: None,
+            factory_energy_offers: Vec::new(),
+            is_awaiting_energy_offers: false,
+            energy_offers_made: Vec::new(),
+            exchange_energy_wanted: 0,
+            product_demand_sell_threshold: Percentage::new(
+                strategy.product_demand_sell_threshold.unwrap_or(config.factory.product_demand_sell_threshold)
+            ),
+            profit_margin: Percentage::new(strategy.profit_margin.unwrap_or(config.factory.profit_margin)),
+            rng,
+            sleeptime,
+            clock_mode,
+            step_turn_id: step_turn.id(),
+            step_turn: Some(step_turn),
+            config,
         }
     }
 }
@@ -81,9 +171,9 @@ impl Factory {
 impl Factory {
     fn recalculate_sleeptime(tick_duration: TickDuration) -> Duration {
         // tick durations are in milliseconds so we multiply with 1k to get micros
-        //TODO: Add a random number to the tail of this calculation so that factory loop
-        // cycles won't overlap in time.
-        let micros = (tick_duration / 2) * 1000 - 100;
+        // A random tail is added so that factory loop cycles won't overlap in time.
+        // It comes from the thread rng as it only affects pacing, not the simulated world.
+        let micros = ((tick_duration / 2) * 1000).saturating_sub(100) + rand::thread_rng().gen_range(0..100);
 
         Duration::from_micros(micros)
     }
@@ -97,7 +187,7 @@ impl Factory {
     }
 
     fn maybe_produce_goods(&mut self) {
-        let (factory_id, balance, available_energy, producable_demands) = {
+        let (factory_id, balance, available_energy, mut drawable_energy, producable_demands) = {
             let econ_state_ro = self.econ_state_ro.read().unwrap();
             let state_ro = self.state_ro.read().unwrap();
             let producable_demands = econ_state_ro
@@ -115,6 +205,7 @@ impl Factory {
                 state_ro.id,
                 state_ro.balance,
                 state_ro.available_energy.val(),
+                state_ro.batteries.iter().map(|battery| battery.dischargeable(&self.config.battery)).sum::<SimInt>(),
                 producable_demands,
             )
         };
@@ -128,7 +219,7 @@ impl Factory {
                 // It probably has gone bankrupt here.
                 if balance.val() < unit_cost_ex_energy {
                     self.log_ui_console(format!("Can't produce even a single unit of {}", product.name), Critical);
-                    self.dynamic_sender.send(Arc::new(FactoryHubSignal::DeclaringBankrupcy)).unwrap();
+                    self.to_hub.send(FactoryHubSignal::DeclaringBankrupcy);
 
                     return;
                 }
@@ -145,23 +236,36 @@ impl Factory {
                 if budget_units > product.demand_info.unit_per_percent {
                     let cost = Money::new(budget_units as SimFlo * unit_cost_ex_energy);
                     let energy_needed = budget_units * (product.unit_production_cost.energy - available_energy);
+                    if energy_needed > drawable_energy && self.waits_for_cheaper_energy(&demand, budget_units) {
+                        continue;
+                    }
+                    // Stored energy comes first. Production starts once it's out of the batteries.
+                    let from_batteries = energy_needed.min(drawable_energy);
                     self.production_runs.push(ProductionRun {
                         demand,
                         units: budget_units,
                         cost,
-                        energy_needed
+                        energy_needed,
+                        energy_to_buy: energy_needed - from_batteries,
                     });
                     if energy_needed > 0 {
-                        let energy_demand = FactoryEnergyDemand {
-                            factory_id,
-                            energy_needed,
-                        };
-
-                        self.dynamic_sender.send(Arc::new(
-                            FactoryHubSignal::EnergyDemand(
-                                energy_demand,
-                            ))
-                        ).unwrap();
+                        if from_batteries > 0 {
+                            drawable_energy -= from_batteries;
+                            self.to_hub.send(FactoryHubSignal::DrawFromBatteries(from_batteries));
+                        }
+
+                        if energy_needed > from_batteries && self.config.exchange.enabled {
+                            // It's bid for in the next clearing round of the exchange.
+                            self.exchange_energy_wanted += energy_needed - from_batteries;
+                        } else if energy_needed > from_batteries {
+                            let energy_demand = FactoryEnergyDemand {
+                                factory_id,
+                                energy_needed: energy_needed - from_batteries,
+                            };
+
+                            self.to_hub.send(FactoryHubSignal::EnergyDemand(energy_demand));
+                            self.is_awaiting_energy_offers = true;
+                        }
                     } else {
                         self.produce_product_demand(demand, budget_units, unit_cost_ex_energy);
                     }
@@ -170,23 +274,232 @@ impl Factory {
         }
     }
 
-    fn evaluate_pp_energy_offer(&mut self, offer: &PPEnergyOffer) {
+    // A run that has to buy energy can wait for a cheaper tariff window, as long as the demand
+    // is still there by the time it starts and its goods are made.
+    fn waits_for_cheaper_energy(&self, demand: &ProductDemand, units: SimInt) -> bool {
+        let (hour, minute) = {
+            let date = &self.timer_state_ro.read().unwrap().date;
+            (date.hour, date.minute)
+        };
+        let Some(hours_ahead) = self.config.tariff.hours_until_cheaper(hour) else {
+            return false;
+        };
+
+        let production_hours = units / demand.product.units_per_minute.max(1) * 3 / 60 + 1;
+        let hours_left = demand.product.demand_info.demand_timeline.deadline - demand.age;
+        if hours_ahead + production_hours >= hours_left {
+            return false;
+        }
+
+        // Only told once an hour, we look again every few minutes.
+        if minute == 0 {
+            self.log_ui_console(format!("Waiting {} hours for cheaper energy to produce {}.", hours_ahead, demand.product.name), Info);
+        }
+
+        true
+    }
+
+    // Buys the energy the last production run still needs from the offers that came in.
+    fn choose_energy_offers(&mut self) {
+        let pp_offer = self.pp_energy_offer.take();
+        let factory_offers = std::mem::take(&mut self.factory_energy_offers);
+
         let balance = self.state_ro.read().unwrap().balance;
 
-        if !self.production_runs.is_empty() {
-            //TODO: A more sophisticated algo to evaluate the price here might be better option.
-            // For now we just accept whatever comes from pp
-            let prun = self.production_runs.last_mut().unwrap();
-            let energy_cost = offer.price_per_unit.val() * offer.units as SimFlo;
-            let remaining_budget = balance.val() - (prun.cost.val() + energy_cost);
-            if remaining_budget.val() > 0.0 {
-                prun.cost.inc(energy_cost.val());
+        let Some(prun) = self.production_runs.last_mut() else {
+            for offer in factory_offers {
+                self.to_hub.send(FactoryHubSignal::RejectFactoryEnergyOffer(offer));
+            }
+            if let Some(offer) = pp_offer {
+                self.to_pp.send(FactoryPPSignal::RejectPPEnergyOffer(offer));
+            }
+            return;
+        };
+
+        let purchase = Self::pick_energy_offers(factory_offers, pp_offer, prun.energy_to_buy, balance.val() - prun.cost.val());
+        prun.cost.inc(purchase.cost);
+        prun.energy_to_buy -= purchase.units;
+
+        for offer in purchase.accepted {
+            self.to_hub.send(FactoryHubSignal::AcceptFactoryEnergyOffer(offer));
+        }
+        for offer in purchase.rejected {
+            self.to_hub.send(FactoryHubSignal::RejectFactoryEnergyOffer(offer));
+        }
+        match purchase.pp_offer {
+            Some((offer, true)) => self.to_pp.send(FactoryPPSignal::AcceptPPEnergyOffer(offer)),
+            Some((offer, false)) => self.to_pp.send(FactoryPPSignal::RejectPPEnergyOffer(offer)),
+            None => (),
+        }
+    }
 
-                self.dynamic_sender.send(Arc::new(FactorySignal::AcceptPPEnergyOffer(*offer))).unwrap();
+    // Takes the cheapest offers for the energy we have to buy, as long as the budget lasts.
+    // Offers from other factories are taken whole, so one that would bring more than we need is turned down.
+    // The Povver Plant offers all we need, but it is only asked for what's left when the others are pricier or fall short.
+    fn pick_energy_offers(
+        mut factory_offers: Vec<FactoryEnergyOffer>,
+        pp_offer: Option<PPEnergyOffer>,
+        mut energy_to_buy: SimInt,
+        budget: SimFlo,
+    ) -> EnergyPurchase {
+        factory_offers.sort_by(|a, b| a.price_per_unit.total_cmp(&b.price_per_unit));
+
+        let mut purchase = EnergyPurchase::default();
+        for offer in factory_offers {
+            let energy_cost = offer.price_per_unit * offer.units as SimFlo;
+            let beats_pp = pp_offer.is_none_or(|pp_offer| offer.price_per_unit < pp_offer.price_per_unit);
+            if offer.units > 0 && offer.units <= energy_to_buy && beats_pp && budget - (purchase.cost + energy_cost) > 0.0 {
+                purchase.cost += energy_cost;
+                purchase.units += offer.units;
+                energy_to_buy -= offer.units;
+                purchase.accepted.push(offer);
             } else {
-                self.dynamic_sender.send(Arc::new(FactorySignal::RejectPPEnergyOffer(*offer))).unwrap();
+                purchase.rejected.push(offer);
+            }
+        }
+
+        purchase.pp_offer = pp_offer.map(|offer| {
+            let offer = PPEnergyOffer { units: offer.units.min(energy_to_buy.max(0)), ..offer };
+            let energy_cost = offer.price_per_unit * offer.units as SimFlo;
+            let accepted = offer.units > 0 && budget - (purchase.cost + energy_cost) > 0.0;
+            if accepted {
+                purchase.cost += energy_cost;
+                purchase.units += offer.units;
             }
+
+            (offer, accepted)
+        });
+
+        purchase
+    }
+
+    // Offers arriving after the hub closed the offers for our demand are answered right away.
+    fn energy_offer_received(&mut self) {
+        if !self.is_awaiting_energy_offers {
+            self.choose_energy_offers();
+        }
+    }
+
+    // What we paid for a unit of energy lately. Without any purchases it's what the Povver Plant's fuel costs for a unit.
+    fn energy_price_paid(&self) -> SimFlo {
+        let (units, total_price) = self.last_hundred_energy_purchases
+            .iter()
+            .rev()
+            .take(100)
+            .fold((0, 0.0), |(units, total_price), receipt| (units + receipt.units, total_price + receipt.total_price));
+
+        if units > 0 {
+            total_price / units as SimFlo
+        } else {
+            self.fuel_cost_per_energy_unit()
+        }
+    }
+
+    fn fuel_cost_per_energy_unit(&self) -> SimFlo {
+        self.econ_state_ro.read().unwrap().fuel_price.val() / self.config.povver_plant.energy_per_fuel as SimFlo
+    }
+
+    fn energy_resale_price(&self) -> SimFlo {
+        let paid_per_unit = self.energy_price_paid();
+
+        paid_per_unit - paid_per_unit * Percentage::new(self.config.factory.energy_resale_discount).as_factor()
+    }
+
+    // The most we pay for a unit on the energy exchange. What energy cost us lately or what its fuel costs now,
+    // whichever is more, with the bid markup on top.
+    fn energy_bid_price(&self) -> SimFlo {
+        let reference = self.energy_price_paid().max(self.fuel_cost_per_energy_unit());
+
+        reference + reference * Percentage::new(self.config.exchange.bid_markup).as_factor()
+    }
+
+    // Energy our production runs wait for is bid for, as much of it as we can pay for.
+    // Without any, our spare energy is up for sale.
+    fn maybe_exchange_order(&mut self) {
+        if self.exchange_energy_wanted > 0 && !self.production_runs.is_empty() {
+            let balance = self.state_ro.read().unwrap().balance.val();
+            let committed = self.production_runs.iter().map(|run| run.cost.val()).sum::<SimFlo>();
+            let price_per_unit = self.energy_bid_price();
+            let affordable = ((balance - committed) / price_per_unit).floor() as SimInt;
+
+            let units = self.exchange_energy_wanted.min(affordable);
+            if units > 0 {
+                self.log_ui_console(format!("Bidding for {} units of energy at up to {} per EU.", units, price_per_unit), Info);
+                self.to_hub.send(FactoryHubSignal::ExchangeBid(units, price_per_unit));
+            }
+
+            return;
+        }
+
+        let spare = self.spare_energy();
+        if spare > 0 {
+            let price_per_unit = self.energy_resale_price();
+            self.log_ui_console(format!("Asking {} per EU for {} units of spare energy.", price_per_unit, spare), Info);
+            self.to_hub.send(FactoryHubSignal::ExchangeAsk(spare, price_per_unit));
+        }
+    }
+
+    fn exchange_bid_filled(&mut self, receipt: &EnergyReceipt) {
+        self.exchange_energy_wanted = (self.exchange_energy_wanted - receipt.units).max(0);
+        if let Some(prun) = self.production_runs.last_mut() {
+            prun.cost.inc(receipt.total_price);
+        }
+        self.log_ui_console(format!("Bought {} units of energy on the exchange for {} per EU.", receipt.units, receipt.price_per_unit), Info);
+    }
+
+    // Available energy that isn't spoken for by our own production runs or offers to other factories.
+    fn spare_energy(&self) -> SimInt {
+        let available_energy = self.state_ro.read().unwrap().available_energy.val();
+        let reserved = self.production_runs
+            .iter()
+            .map(|run| run.units * run.demand.product.unit_production_cost.energy)
+            .sum::<SimInt>();
+        let offered = self.energy_offers_made.iter().map(|offer| offer.units).sum::<SimInt>();
+
+        available_energy - reserved - offered
+    }
+
+    // Spare energy would expire at the end of the day anyway, so it goes to the factory in need.
+    fn maybe_offer_spare_energy(&mut self, demand: &FactoryEnergyDemand) {
+        let my_id = self.state_ro.read().unwrap().id;
+
+        let spare = self.spare_energy();
+        if spare <= 0 {
+            return;
+        }
+
+        let offer = FactoryEnergyOffer {
+            price_per_unit: self.energy_resale_price(),
+            units: spare.min(demand.energy_needed),
+            from_factory_id: my_id,
+            to_factory_id: demand.factory_id,
+        };
+
+        self.log_ui_console(
+            format!("Offering {} units of spare energy to factory No. {} for {} per EU.", offer.units, offer.to_factory_id, offer.price_per_unit),
+            Info
+        );
+        self.energy_offers_made.push(offer);
+        self.to_hub.send(FactoryHubSignal::EnergyOffer(offer));
+    }
+
+    // Spare energy goes into the batteries every hour, as much as their charge rates let it,
+    // so it's still there after the day is over.
+    fn maybe_charge_batteries(&self) {
+        let has_room = self.state_ro.read().unwrap().batteries.iter().any(|battery| battery.stored() < battery.capacity());
+        let spare = self.spare_energy();
+        if has_room && spare > 0 {
+            self.to_hub.send(FactoryHubSignal::ChargeBatteries(spare));
+        }
+    }
+
+    fn energy_sold(&mut self, receipt: &EnergyReceipt) {
+        if let Some(index) = self.energy_offers_made.iter().position(|offer| {
+            offer.to_factory_id == receipt.factory_id && offer.units == receipt.units
+        }) {
+            self.energy_offers_made.remove(index);
         }
+        self.log_ui_console(format!("Sold {} units of energy to factory No. {} for {}.", receipt.units, receipt.factory_id, receipt.total_price), Info);
     }
 
     fn energy_received(&mut self) {
@@ -208,9 +521,7 @@ impl Factory {
 
     fn produce_product_demand(&mut self, demand: ProductDemand, units: SimInt, unit_cost: SimFlo) {
         self.log_ui_console(format!("Producing {} units of {} for a demand of {} units.", units, demand.product.name, demand.units), Info);
-        self.dynamic_sender.send(
-            Arc::new(FactoryHubSignal::ProducingProductDemand(demand, units, unit_cost))
-        ).unwrap();
+        self.to_hub.send(FactoryHubSignal::ProducingProductDemand(demand, units, unit_cost));
     }
 
     fn maybe_sell_goods(&self) {
@@ -221,7 +532,7 @@ impl Factory {
                 demand.product == stock.product && demand.percent.val() > self.product_demand_sell_threshold.val()
             ).is_some() {
                 let unit_price = stock.unit_production_cost.val() + stock.unit_production_cost.val() * self.profit_margin.as_factor();
-                self.dynamic_sender.send(Arc::new(FactoryHubSignal::SellingProduct(stock_index, unit_price.into()))).unwrap();
+                self.to_hub.send(FactoryHubSignal::SellingProduct(stock_index, unit_price));
             }
         })
     }
@@ -230,36 +541,122 @@ impl Factory {
         let demand_index = self.production_runs.iter().position(|run| run.demand == receipt.demand);
         if let Some(remove_index) = demand_index {
             self.production_runs.remove(remove_index);
+            if self.production_runs.is_empty() {
+                self.exchange_energy_wanted = 0;
+            }
             self.maybe_sell_goods();
         }
     }
 
     fn maybe_buy_renewables(&self) {
         //TODO: More detailed algo for renewable buying
-        //TODO: Add wind turbines here
-        let (current_solarpanels_count, balance, is_awaiting_solarpanels) = {
+        let (
+            current_solarpanels_count,
+            current_windturbines_count,
+            current_batteries_count,
+            balance,
+            is_awaiting_solarpanels,
+            is_awaiting_windturbines,
+            is_awaiting_batteries,
+        ) = {
             let state = self.state_ro.read().unwrap();
             (
                 state.solarpanels.len(),
+                state.windturbines.len(),
+                state.batteries.len(),
                 state.balance,
                 state.is_awaiting_solarpanels,
+                state.is_awaiting_windturbines,
+                state.is_awaiting_batteries,
             )
         };
 
-        if balance.val() >= 50000.0 && !is_awaiting_solarpanels {
-            let budget = balance.val() - 50000.0;
-            let max_solar_panels = (budget / SOLAR_PANEL_PRICE) as usize;
+        // What's spent on solar panels in this round doesn't go to wind turbines, and so on.
+        let mut spent = 0.0;
+
+        let reserve = self.config.factory.solar_panel_reserve;
+        if balance.val() >= reserve && !is_awaiting_solarpanels {
+            let budget = balance.val() - reserve;
+            let max_solar_panels = (budget / self.config.solar_panel.price) as usize;
 
             if max_solar_panels > 0 {
-                let can_buy_count = FACTORY_MAX_SOLAR_PANELS - current_solarpanels_count;
+                let can_buy_count = self.config.factory.max_solar_panels.saturating_sub(current_solarpanels_count);
                 let amount = if max_solar_panels <= can_buy_count { max_solar_panels } else { can_buy_count };
 
-                self.dynamic_sender.send(Arc::new(FactoryHubSignal::BuyingSolarPanels(amount))).unwrap();
+                self.to_hub.send(FactoryHubSignal::BuyingSolarPanels(amount));
+                spent += amount as SimFlo * self.config.solar_panel.price;
+            }
+        }
+
+        let reserve = self.config.factory.wind_turbine_reserve;
+        let left = balance.val() - spent;
+        if left >= reserve && !is_awaiting_windturbines {
+            let budget = left - reserve;
+            let max_wind_turbines = (budget / self.config.wind_turbine.price) as usize;
+            let amount = max_wind_turbines.min(self.config.factory.max_wind_turbines.saturating_sub(current_windturbines_count));
+
+            if amount > 0 {
+                self.to_hub.send(FactoryHubSignal::BuyingWindTurbines(amount));
+                spent += amount as SimFlo * self.config.wind_turbine.price;
+            }
+        }
+
+        let has_renewables = current_solarpanels_count + current_windturbines_count > 0;
+        let reserve = self.config.factory.battery_reserve;
+        let left = balance.val() - spent;
+        if has_renewables && left >= reserve && !is_awaiting_batteries {
+            let budget = left - reserve;
+            let max_batteries = (budget / self.config.battery.price) as usize;
+            let amount = max_batteries.min(self.config.factory.max_batteries.saturating_sub(current_batteries_count));
+
+            if amount > 0 {
+                self.to_hub.send(FactoryHubSignal::BuyingBatteries(amount));
             }
         }
     }
 }
 
+impl Factory {
+    // Factories that join a running simulation start with the speed it's running at.
+    pub fn set_tick_duration(&mut self, tick_duration: TickDuration) {
+        self.sleeptime = Self::recalculate_sleeptime(tick_duration);
+    }
+
+    pub fn step_turn_id(&self) -> usize {
+        self.step_turn_id
+    }
+}
+
+impl Factory {
+    pub fn snapshot(&self) -> FactorySnapshot {
+        FactorySnapshot {
+            production_runs: self.production_runs.clone(),
+            last_hundred_energy_purchases: self.last_hundred_energy_purchases.clone(),
+            pp_energy_offer: self.pp_energy_offer,
+            factory_energy_offers: self.factory_energy_offers.clone(),
+            is_awaiting_energy_offers: self.is_awaiting_energy_offers,
+            energy_offers_made: self.energy_offers_made.clone(),
+            exchange_energy_wanted: self.exchange_energy_wanted,
+            product_demand_sell_threshold: self.product_demand_sell_threshold,
+            profit_margin: self.profit_margin,
+            rng: self.rng.clone(),
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: FactorySnapshot) {
+        self.production_runs = snapshot.production_runs;
+        self.last_hundred_energy_purchases = snapshot.last_hundred_energy_purchases;
+        self.pp_energy_offer = snapshot.pp_energy_offer;
+        self.factory_energy_offers = snapshot.factory_energy_offers;
+        self.is_awaiting_energy_offers = snapshot.is_awaiting_energy_offers;
+        self.energy_offers_made = snapshot.energy_offers_made;
+        self.exchange_energy_wanted = snapshot.exchange_energy_wanted;
+        self.product_demand_sell_threshold = snapshot.product_demand_sell_threshold;
+        self.profit_margin = snapshot.profit_margin;
+        self.rng = snapshot.rng;
+    }
+}
+
 impl Factory {
     pub fn start(me: Arc<Mutex<Self>>) -> thread::JoinHandle<()> {
         let (
@@ -267,70 +664,103 @@ impl Factory {
             state_ro,
             mut wakeup_receiver,
             mut hub_broadcast_receiver,
-            dynamic_receiver
+            from_hub,
+            from_pp,
+            step_turn,
         ) = {
-            let me_lock = me.lock().unwrap();
+            let mut me_lock = me.lock().unwrap();
+            let step_turn = me_lock.step_turn.take().expect("A factory is only started once");
             (
                 me_lock.state_ro.read().unwrap().id,
                 ReadOnlyRwLock::clone(&me_lock.state_ro),
                 me_lock.wakeup_receiver.resubscribe(),
                 me_lock.hub_broadcast_receiver.resubscribe(),
-                me_lock.dynamic_receiver.clone(),
+                me_lock.from_hub.clone(),
+                me_lock.from_pp.clone(),
+                step_turn,
             )
         };
 
         thread::Builder::new().name("POVVER_F_".to_string() + &my_id.to_string()).spawn(move || {
-            'outer: loop {
-                if let Ok(signal) = hub_broadcast_receiver.try_recv() {
-                    let signal_any = signal.as_any();
-                    match signal_any {
-                        s if s.is::<FactoryEnergyDemand>() => {
-                            if let Some(demand) = signal_any.downcast_ref::<FactoryEnergyDemand>() {
-                                if demand.factory_id != my_id {
-                                    //TODO
-                                    //me.lock().unwrap().log_console(format!("Got message: {:?} is from another guy :)", signal), Critical);
-                                    // MAYBE SELL SOME LEFTOVER ENERGY TO THE FACTORY IN NEED
-                                } else {
-                                    //TODO
-                                    //me.lock().unwrap().log_console(format!("Got message: {:?} is from me haha :)", signal), Critical);
-                                }
+            // One pass over the inboxes. Returns the number of handled messages and whether to quit.
+            let mut poll = || -> (usize, bool) {
+                let mut handled = 0;
+
+                if let Ok(message) = hub_broadcast_receiver.try_recv() {
+                    handled += 1;
+                    match message.signal {
+                        HubBroadcastSignal::FactoryNeedsEnergy(demand) => {
+                            if demand.factory_id != my_id && !state_ro.read().unwrap().is_bankrupt {
+                                me.lock().unwrap().maybe_offer_spare_energy(&demand);
+                            }
+                        },
+                        HubBroadcastSignal::ExchangeOpen => {
+                            if !state_ro.read().unwrap().is_bankrupt {
+                                me.lock().unwrap().maybe_exchange_order();
                             }
                         },
-                        _ => ()
                     }
                 }
 
-                if let Ok(signal) = dynamic_receiver.try_recv() {
-                    let signal_any = signal.as_any();
-                    match signal_any {
-                        s if s.is::<PPEnergyOffer>() => {
-                            if let Some(offer) = signal_any.downcast_ref::<PPEnergyOffer>() {
-                                me.lock().unwrap().log_ui_console(format!("Got energy offer from PP: {} units.", offer.units), Info);
-                                me.lock().unwrap().evaluate_pp_energy_offer(offer);
-                            }
-                        },
-                        s if s.is::<HubFactorySignal>() => {
-                            if let Some(signal_from_hub) = signal_any.downcast_ref::<HubFactorySignal>() {
-                                match signal_from_hub {
-                                    HubFactorySignal::EnergyTransfered(receipt) => {
-                                        me.lock().unwrap().log_ui_console(format!("{} units of energy received.", receipt.units), Info);
-                                        me.lock().unwrap().last_hundred_energy_purchases.push(receipt.clone());
-                                        me.lock().unwrap().energy_received();
-                                    }
-                                    HubFactorySignal::ProductionComplete(receipt) => {
-                                        me.lock().unwrap().production_complete(&receipt);
-                                    }
-                                    HubFactorySignal::RenewableEnergyProduced => {
-                                        me.lock().unwrap().maybe_produce_goods();
-                                    }
-                                }
-                            }
+                if let Ok(message) = from_pp.try_recv() {
+                    handled += 1;
+                    match message.signal {
+                        PPFactorySignal::EnergyOffer(offer) => {
+                            let mut me_lock = me.lock().unwrap();
+                            me_lock.log_ui_console(format!("Got energy offer from PP: {} units.", offer.units), Info);
+                            me_lock.pp_energy_offer = Some(offer);
+                            me_lock.energy_offer_received();
                         },
-                        _ => ()
+                    }
+                }
+
+                if let Ok(message) = from_hub.try_recv() {
+                    handled += 1;
+                    match message.signal {
+                        HubFactorySignal::EnergyTransfered(receipt) => {
+                            me.lock().unwrap().log_ui_console(format!("{} units of energy received.", receipt.units), Info);
+                            me.lock().unwrap().last_hundred_energy_purchases.push(receipt);
+                            me.lock().unwrap().energy_received();
+                        }
+                        HubFactorySignal::EnergyOffer(offer) => {
+                            let mut me_lock = me.lock().unwrap();
+                            me_lock.log_ui_console(format!("Got energy offer from factory No. {}: {} units.", offer.from_factory_id, offer.units), Info);
+                            me_lock.factory_energy_offers.push(offer);
+                            me_lock.energy_offer_received();
+                        }
+                        HubFactorySignal::EnergyOffersClosed => {
+                            let mut me_lock = me.lock().unwrap();
+                            me_lock.is_awaiting_energy_offers = false;
+                            me_lock.choose_energy_offers();
+                        }
+                        HubFactorySignal::EnergyOfferRejected(offer) => {
+                            me.lock().unwrap().energy_offers_made.retain(|made| *made != offer);
+                        }
+                        HubFactorySignal::EnergySold(receipt) => {
+                            me.lock().unwrap().energy_sold(&receipt);
+                        }
+                        HubFactorySignal::ExchangeBidFilled(receipt) => {
+                            me.lock().unwrap().exchange_bid_filled(&receipt);
+                        }
+                        HubFactorySignal::BatteriesDischarged(energy) => {
+                            me.lock().unwrap().log_ui_console(format!("{} units of energy drawn from the batteries.", energy), Info);
+                            me.lock().unwrap().energy_received();
+                        }
+                        HubFactorySignal::ProductionComplete(receipt) => {
+                            me.lock().unwrap().production_complete(&receipt);
+                        }
+                        HubFactorySignal::RenewableEnergyProduced => {
+                            me.lock().unwrap().maybe_produce_goods();
+                        }
+                        HubFactorySignal::Retired => {
+                            me.lock().unwrap().log_ui_console("We're closing down. It was a good run.".to_string(), Warning);
+                            return (handled, true);
+                        }
                     }
                 }
 
                 if let Ok(action) = wakeup_receiver.try_recv() {
+                    handled += 1;
                     match action {
                         StateAction::Timer(event) => {
                             if state_ro.read().unwrap().is_bankrupt == true {
@@ -348,20 +778,43 @@ impl Factory {
                                 }
                                 if event.at_least_hour() {
                                     me.lock().unwrap().maybe_buy_renewables();
+                                    me.lock().unwrap().maybe_charge_batteries();
+                                }
+                                if event.at_least_day() {
+                                    // The hub expires our energy along with whatever we offered of it.
+                                    me.lock().unwrap().energy_offers_made.clear();
                                 }
                             }
                         }
                         StateAction::SpeedChange(td) => {
-                            me.lock().unwrap().sleeptime = Self::recalculate_sleeptime(td);
+                            let mut me_lock = me.lock().unwrap();
+                            me_lock.sleeptime = Self::recalculate_sleeptime(td);
+                        }
+                        StateAction::ClockModeChange(clock_mode) => {
+                            me.lock().unwrap().clock_mode = clock_mode;
                         }
                         StateAction::Quit => {
-                            break 'outer;
+                            return (handled, true);
                         }
                         _ => ()
                     }
                 }
 
-                thread::sleep(me.lock().unwrap().sleeptime);
+                (handled, false)
+            };
+
+            loop {
+                let clock_mode = me.lock().unwrap().clock_mode;
+                if clock_mode == ClockMode::Stepped {
+                    if step_turn.take(&mut poll) {
+                        break;
+                    }
+                } else {
+                    if poll().1 {
+                        break;
+                    }
+                    thread::sleep(me.lock().unwrap().sleeptime);
+                }
             }
         }).unwrap()
     }
@@ -378,3 +831,60 @@ impl Logger for Factory {
         &self.ui_log_sender
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn factory_offer(from_factory_id: usize, price_per_unit: SimFlo, units: SimInt) -> FactoryEnergyOffer {
+        FactoryEnergyOffer { price_per_unit, units, from_factory_id, to_factory_id: 1 }
+    }
+
+    fn pp_offer(price_per_unit: SimFlo, units: SimInt) -> PPEnergyOffer {
+        PPEnergyOffer { price_per_unit, units, to_factory_id: 1, ..PPEnergyOffer::default() }
+    }
+
+    #[test]
+    fn offers_that_would_overfill_the_run_are_turned_down() {
+        let purchase = Factory::pick_energy_offers(
+            vec![factory_offer(2, 2.0, 80), factory_offer(3, 1.0, 100)],
+            None,
+            100,
+            1000.0,
+        );
+
+        assert_eq!(purchase.accepted, vec![factory_offer(3, 1.0, 100)]);
+        assert_eq!(purchase.rejected, vec![factory_offer(2, 2.0, 80)]);
+        assert_eq!(purchase.units, 100);
+        assert_eq!(purchase.cost, 100.0);
+    }
+
+    #[test]
+    fn the_povver_plant_covers_only_what_factories_dont() {
+        let purchase = Factory::pick_energy_offers(
+            vec![factory_offer(2, 1.0, 60), factory_offer(3, 2.0, 60)],
+            Some(pp_offer(3.0, 100)),
+            100,
+            1000.0,
+        );
+
+        assert_eq!(purchase.accepted, vec![factory_offer(2, 1.0, 60)]);
+        assert_eq!(purchase.rejected, vec![factory_offer(3, 2.0, 60)]);
+        assert_eq!(purchase.pp_offer, Some((pp_offer(3.0, 40), true)));
+        assert_eq!(purchase.units, 100);
+        assert_eq!(purchase.cost, 60.0 + 120.0);
+    }
+
+    #[test]
+    fn nothing_is_bought_beyond_the_budget_or_the_need() {
+        let purchase = Factory::pick_energy_offers(vec![factory_offer(2, 1.0, 50)], Some(pp_offer(3.0, 100)), 100, 100.0);
+        assert_eq!(purchase.accepted, vec![factory_offer(2, 1.0, 50)]);
+        assert_eq!(purchase.pp_offer, Some((pp_offer(3.0, 50), false)));
+        assert_eq!(purchase.units, 50);
+
+        // Offers coming in after the run got all its energy are turned down.
+        let purchase = Factory::pick_energy_offers(vec![factory_offer(2, 1.0, 50)], None, 0, 1000.0);
+        assert!(purchase.accepted.is_empty());
+        assert_eq!(purchase.cost, 0.0);
+    }
+}
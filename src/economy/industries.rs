@@ -1,3 +1,5 @@
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
 #[derive(Debug, Clone)]
 pub struct Industry {
     pub name: &'static str,
@@ -54,7 +56,6 @@ impl PartialEq for Industry {
 }
 
 // This might be usefaul if we present a menu to the user for factory creation
-#[allow(unused)]
 pub const INDUSTRIES: &[Industry] = &[
     Industry::SEMICONDUCTORS,
     Industry::SOFTWARE,
@@ -67,3 +68,25 @@ pub const INDUSTRIES: &[Industry] = &[
     Industry::E_YAY,
     Industry::UNIVERSITY,
 ];
+
+impl Industry {
+    pub fn by_name(name: &str) -> Option<&'static Self> {
+        INDUSTRIES.iter().find(|industry| industry.name == name)
+    }
+}
+
+// Industries are static data too. Snapshots only keep their names.
+impl Serialize for Industry {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name)
+    }
+}
+
+impl<'de> Deserialize<'de> for Industry {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Industry::by_name(&name)
+            .cloned()
+            .ok_or_else(|| D::Error::custom(format!("unknown industry \"{name}\"")))
+    }
+}
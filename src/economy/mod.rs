@@ -1,54 +1,60 @@
 use std::{
     sync::{Arc, RwLock}
 };
-use rand::{prelude::ThreadRng, random, Rng};
+use rand::Rng;
 
 pub mod industries;
 pub mod products;
+pub mod product_catalog;
 
 pub mod economy_types;
 use economy_types::*;
 
 pub mod economy_constants;
-use economy_constants::*;
 
 pub mod factory;
 pub mod povver_plant;
 pub mod solarpanel;
+pub mod windturbine;
+pub mod battery;
 
 use crate::{
-    utils_random::{one_chance_in_many, random_inc_dec_clamp_signed, random_inc_dec_clamp_unsigned},
+    utils_random::{one_chance_in_many, random_inc_dec_clamp_signed, random_inc_dec_clamp_unsigned, SimRng},
     utils_traits::{Flippable, AsFactor},
     utils_data::SlidingWindow,
     app_state::EconomyStateData,
-    economy::products::PRODUCTS,
+    config::Config,
+    economy::products::Product,
     simulation::{SimFlo, Percentage},
 };
 
 #[derive(Debug)]
 pub struct Economy {
     state: Arc<RwLock<EconomyStateData>>,
-    rng: ThreadRng,
+    rng: SimRng,
+    config: Arc<Config>,
 }
 
 // Constructor
 impl Economy {
-    pub fn new() -> (Self, Arc<RwLock<EconomyStateData>>) {
-        let mut rng = rand::thread_rng();
-
-        let inflation_direction = if random() { UpDown::Up } else { UpDown::Down };
+    pub fn new(mut rng: SimRng, config: Arc<Config>) -> (Self, Arc<RwLock<EconomyStateData>>) {
+        let inflation_direction = if rng.gen_bool(0.5) { UpDown::Up } else { UpDown::Down };
         let state = Arc::new(RwLock::new(EconomyStateData {
             inflation_rate: rng.gen_range(2.0..10.0),
             inflation_direction,
-            fuel_price: Money::new(200.0),
+            fuel_price: Money::new(config.economy.init_fuel_price),
             product_demands: Vec::new(),
             past_25_product_demands: SlidingWindow::new(25),
+            demanded_units_total: 0,
+            sold_units_total: 0,
+            exchange_price: None,
         }));
 
         (
             Self {
                 state: Arc::clone(&state),
                 rng,
+                config,
             },
             state,
         )
@@ -56,6 +62,15 @@ impl Economy {
 }
 
 impl Economy {
+    pub fn clone_rng(&self) -> SimRng {
+        self.rng.clone()
+    }
+
+    pub fn restore(&mut self, state: EconomyStateData, rng: SimRng) {
+        *self.state.write().unwrap() = state;
+        self.rng = rng;
+    }
+
     pub fn update_macroeconomics(&mut self) {
         let mut inflation_direction = self.state.read().unwrap().inflation_direction.clone();
 
@@ -73,24 +88,25 @@ impl Economy {
                 inflation_rate,
                 inflation_low_end,
                 inflation_high_end,
-                INFLATION_MIN,
-                INFLATION_MAX,
+                self.config.economy.inflation_min,
+                self.config.economy.inflation_max,
             );
             self.state.write().unwrap().inflation_rate = inflation_rate;
 
             inflation_rate
         };
 
-        let fuel_price_low_end = FUEL_PRICE_MODIFIER - (inflation_rate.as_factor() * FUEL_PRICE_MODIFIER);
-        let fuel_price_high_end = FUEL_PRICE_MODIFIER + (inflation_rate.as_factor() * FUEL_PRICE_MODIFIER);
+        let fuel_price_modifier = self.config.economy.fuel_price_modifier;
+        let fuel_price_low_end = fuel_price_modifier - (inflation_rate.as_factor() * fuel_price_modifier);
+        let fuel_price_high_end = fuel_price_modifier + (inflation_rate.as_factor() * fuel_price_modifier);
         let fuel_price = self.state.read().unwrap().fuel_price;
         self.state.write().unwrap().fuel_price.set(random_inc_dec_clamp_signed(
             &mut self.rng,
             fuel_price.val(),
             fuel_price_low_end,
             fuel_price_high_end,
-            FUEL_PRICE_MIN,
-            FUEL_PRICE_MAX,
+            self.config.economy.fuel_price_min,
+            self.config.economy.fuel_price_max,
         ));
     }
 
@@ -102,7 +118,7 @@ impl Economy {
             -1.0
         };
 
-        for product in PRODUCTS {
+        for product in Product::all() {
             let min_percent = product.demand_info.min_percentage;
             if inflation_hundred < min_percent && inflation_hundred != -1.0 {
                 let mut bonus = 0.0;
@@ -133,9 +149,7 @@ impl Economy {
 
                 // Let's create a new demand for this product
                 // With bonus added.
-                self.state.write().unwrap().product_demands.push(
-                    ProductDemand::new(product, Percentage::new(min_percent + bonus))
-                );
+                self.add_product_demand(ProductDemand::new(product, Percentage::new(min_percent + bonus)));
             // If inflation is negative (deflation) we still have a
             // chance for a new demand with minimum percentage.
             // In deflationary times, consumers expect product prices to
@@ -154,14 +168,18 @@ impl Economy {
                 // pregnancy tests will always have a demand, albeit the lowest percentage
                 // in deflationary times.
                 if one_chance_in_many(&mut self.rng, chance - (min_percent.as_factor() * chance as SimFlo) as u32) {
-                    self.state.write().unwrap().product_demands.push(
-                        ProductDemand::new(product, Percentage::new(min_percent))
-                    )
+                    self.add_product_demand(ProductDemand::new(product, Percentage::new(min_percent)));
                 }
             }
         }
     }
 
+    fn add_product_demand(&self, demand: ProductDemand) {
+        let mut state = self.state.write().unwrap();
+        state.demanded_units_total += demand.units.max(0) as u64;
+        state.product_demands.push(demand);
+    }
+
     pub fn update_product_demands(&self) {
         let mut old_demands = Vec::new();
         let mut demands = self.state.read().unwrap().product_demands.clone();
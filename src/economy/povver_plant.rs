@@ -1,23 +1,30 @@
 use std::{
+    collections::BTreeMap,
     thread,
     sync::{Arc, Mutex},
 };
 use std::time::Duration;
+use rand::Rng;
+use serde::{Serialize, Deserialize};
 use tokio::sync::broadcast as tokio_broadcast;
 
 use crate::{
     app_state::{EconomyStateData, PovverPlantStateData, TimerStateData},
     utils_data::{SlidingWindow, ReadOnlyRwLock},
     utils_traits::AsFactor,
+    utils_random::SimRng,
     simulation::{
         StateAction,
+        ClockMode,
         Percentage,
         hub_comms::*,
+        stepper::StepTurn,
         SimInt,
         SimFlo,
-        sim_constants::*,
         speed::Speed,
     },
+    config::Config,
+    economy::economy_types::TariffWindow,
     logger::{
         Logger,
         LogLevel::{Info, Warning, Critical, Error},
@@ -38,32 +45,74 @@ pub struct PovverPlant {
     timer_state_ro: ReadOnlyRwLock<TimerStateData>,
     ui_log_sender: tokio_broadcast::Sender<LogMessage>,
     wakeup_receiver: tokio_broadcast::Receiver<StateAction>,
-    dynamic_channel: DynamicChannel,
-    hub_broadcast_receiver: BroadcastDynReceiver,
-    to_factory_senders: Vec<DynamicSender>,
-    from_factory_receivers: Vec<BroadcastDynReceiver>,
+    hub_broadcast_receiver: BroadcastInbox,
+    to_hub: Outbox<PPHubSignal>,
+    from_hub: Inbox<HubPPSignal>,
+    // Lines to and from the factories by factory id.
+    to_factories: BTreeMap<usize, Outbox<PPFactorySignal>>,
+    from_factories: BTreeMap<usize, Inbox<FactoryPPSignal>>,
+    rng: SimRng,
     sleeptime: Duration,
+    clock_mode: ClockMode,
+    // Handed over to the thread when it starts, so the turn is gone along with the thread.
+    step_turn: Option<StepTurn>,
+    config: Arc<Config>,
+}
+
+// What the Povver Plant keeps to itself, outside of its PovverPlantStateData.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PovverPlantSnapshot {
+    profit_margin: Percentage,
+    fuel_buy_threshold: SimInt,
+    fuel_price_paid_per_unit_average: SimFlo,
+    total_fuel_expenditure: SimFlo,
+    pending_energy_offers: Vec<PPEnergyOffer>,
+    last_hundred_sales: SlidingWindow<EnergyReceipt>,
+    rng: SimRng,
+}
+
+// The state the Povver Plant reads and its lines to the hub, the factories and the UI log.
+pub struct PPLinks {
+    pub state_ro: ReadOnlyRwLock<PovverPlantStateData>,
+    pub econ_state_ro: ReadOnlyRwLock<EconomyStateData>,
+    pub timer_state_ro: ReadOnlyRwLock<TimerStateData>,
+    pub ui_log_sender: tokio_broadcast::Sender<LogMessage>,
+    pub wakeup_receiver: tokio_broadcast::Receiver<StateAction>,
+    pub hub_broadcast_receiver: BroadcastInbox,
+    pub to_hub: Outbox<PPHubSignal>,
+    pub from_hub: Inbox<HubPPSignal>,
+    pub to_factories: BTreeMap<usize, Outbox<PPFactorySignal>>,
+    pub from_factories: BTreeMap<usize, Inbox<FactoryPPSignal>>,
 }
 
 impl PovverPlant {
     pub fn new(
-        state_ro: ReadOnlyRwLock<PovverPlantStateData>,
-        econ_state_ro: ReadOnlyRwLock<EconomyStateData>,
-        timer_state_ro: ReadOnlyRwLock<TimerStateData>,
-        ui_log_sender: tokio_broadcast::Sender<LogMessage>,
-        wakeup_receiver: tokio_broadcast::Receiver<StateAction>,
-        dynamic_channel: DynamicChannel,
-        hub_broadcast_receiver: tokio_broadcast::Receiver<DynamicSignal>,
-        to_factory_senders: Vec<DynamicSender>,
-        from_factory_receivers: Vec<BroadcastDynReceiver>,
+        links: PPLinks,
+        rng: SimRng,
+        config: Arc<Config>,
+        clock_mode: ClockMode,
+        step_turn: StepTurn,
     ) -> Self {
+        let PPLinks {
+            state_ro,
+            econ_state_ro,
+            timer_state_ro,
+            ui_log_sender,
+            wakeup_receiver,
+            hub_broadcast_receiver,
+            to_hub,
+            from_hub,
+            to_factories,
+            from_factories,
+        } = links;
         let fuel_price = econ_state_ro.read().unwrap().fuel_price;
         let fuel_price_paid_per_unit_average = fuel_price.val();
         let total_fuel_expenditure = fuel_price.val() * state_ro.read().unwrap().fuel as SimFlo;
+        let sleeptime = Self::recalculate_sleeptime(Speed::NORMAL.get_tick_duration());
 
         Self {
-            profit_margin: Percentage::new(50.0),
-            fuel_buy_threshold: PP_INIT_FUEL_BUY_THRESHOLD,
+            profit_margin: Percentage::new(config.povver_plant.profit_margin),
+            fuel_buy_threshold: config.povver_plant.init_fuel_buy_threshold,
             fuel_price_paid_per_unit_average,
             total_fuel_expenditure,
             pending_energy_offers: Vec::new(),
@@ -73,11 +122,16 @@ impl PovverPlant {
             timer_state_ro,
             ui_log_sender,
             wakeup_receiver,
-            dynamic_channel,
             hub_broadcast_receiver,
-            to_factory_senders,
-            from_factory_receivers,
-            sleeptime: Self::recalculate_sleeptime(Speed::NORMAL.get_tick_duration()),
+            to_hub,
+            from_hub,
+            to_factories,
+            from_factories,
+            rng,
+            sleeptime,
+            clock_mode,
+            step_turn: Some(step_turn),
+            config,
         }
     }
 }
@@ -85,23 +139,13 @@ impl PovverPlant {
 impl PovverPlant {
     fn recalculate_sleeptime(tick_duration: TickDuration) -> Duration {
         // tick durations are in milliseconds so we multiply with 1k to get micros
-        let micros = (tick_duration / 2) * 1000 - 250;
+        // A random tail is added so that our loop cycles won't overlap with the factories.
+        // It comes from the thread rng as it only affects pacing, not the simulated world.
+        let micros = ((tick_duration / 2) * 1000).saturating_sub(250) + rand::thread_rng().gen_range(0..100);
 
         Duration::from_micros(micros)
     }
 
-    fn get_dynamic_sender(&self) -> &DynamicSender {
-        &self.dynamic_channel.0
-    }
-
-    fn get_dynamic_receiver(&self) -> &DynamicReceiver {
-        &self.dynamic_channel.1
-    }
-
-    fn get_to_factory_sender_by_id(&self, factory_id: usize) -> &DynamicSender {
-        &self.to_factory_senders[factory_id]
-    }
-
     fn check_buy_fuel(&mut self) {
         let (is_awaiting_fuel, fuel) = {
             let state = self.state_ro.read().unwrap();
@@ -129,11 +173,11 @@ impl PovverPlant {
 
                         self.log_ui_console(format!("Buying fuel for amount {amount}"), Info);
 
-                        self.get_dynamic_sender().send(Arc::new(PPHubSignal::BuyFuel(amount))).unwrap();
+                        self.to_hub.send(PPHubSignal::BuyFuel(amount));
                     } else {
                         self.log_ui_console("Can't even buy new fuel. Let's declare bankruptcy and take a holiday.".to_string(), Critical);
 
-                        self.get_dynamic_sender().send(Arc::new(PPHubSignal::DeclaringBankrupcy)).unwrap();
+                        self.to_hub.send(PPHubSignal::DeclaringBankrupcy);
                     }
                 } else {
                     self.log_ui_console("Awaiting new fuel. Fuel level is critical!".to_string(), Critical);
@@ -156,6 +200,20 @@ impl PovverPlant {
         self.fuel_price_paid_per_unit_average = self.total_fuel_expenditure / self.state_ro.read().unwrap().fuel as SimFlo;
     }
 
+    // The time-of-use window of this hour and what it does to our prices. The busier we are with energy
+    // we're producing or have offered, the more the rest of our capacity costs.
+    fn tariff(&self) -> (TariffWindow, SimFlo) {
+        let hour = self.timer_state_ro.read().unwrap().date.hour;
+        let (committed_energy, production_capacity) = {
+            let state = self.state_ro.read().unwrap();
+            (state.committed_energy, state.production_capacity.val())
+        };
+        let offered_energy = self.pending_energy_offers.iter().map(|offer| offer.units).sum::<SimInt>();
+        let load = (committed_energy + offered_energy) as SimFlo / production_capacity.max(1) as SimFlo;
+
+        (self.config.tariff.window(hour), self.config.tariff.price_factor(hour, load))
+    }
+
     fn maybe_new_energy_offer(&mut self, demand: &FactoryEnergyDemand) {
         if let Some(_) = self.pending_energy_offers.iter().position(|of| of.to_factory_id == demand.factory_id) {
             self.log_console(format!("Energy demand from factory No. {} is already pending.", demand.factory_id), Info);
@@ -170,9 +228,10 @@ impl PovverPlant {
             )
         };
 
-        let fuel_needed = energy_needed / PP_ENERGY_PER_FUEL;
+        let energy_per_fuel = self.config.povver_plant.energy_per_fuel;
+        let fuel_needed = energy_needed / energy_per_fuel;
 
-        let producable = (fuel * PP_ENERGY_PER_FUEL).clamp(0, production_capacity.val());
+        let producable = self.producable_energy();
 
         // We have ZERO energy production potential.
         // What are we gonna do?
@@ -183,10 +242,12 @@ impl PovverPlant {
             return;
         }
 
-        let mut price_per_unit = self.fuel_price_paid_per_unit_average / PP_ENERGY_PER_FUEL as SimFlo;
+        let (tariff_window, tariff_factor) = self.tariff();
+        let mut price_per_unit = self.fuel_price_paid_per_unit_average / energy_per_fuel as SimFlo * tariff_factor;
 
         let mut offer = PPEnergyOffer {
             to_factory_id: demand.factory_id,
+            tariff_window,
             ..PPEnergyOffer::default()
         };
 
@@ -238,12 +299,43 @@ impl PovverPlant {
 
         self.pending_energy_offers.push(offer);
 
-        self.get_to_factory_sender_by_id(offer.to_factory_id).send(Arc::new(offer)).unwrap();
+        match self.to_factories.get(&offer.to_factory_id) {
+            Some(outbox) => outbox.send(PPFactorySignal::EnergyOffer(offer)),
+            None => self.log_console(format!("Factory No. {} is gone. Energy offer canceled.", offer.to_factory_id), Warning),
+        }
 
         self.maybe_update_fuel_buy_threshold(fuel_needed);
         self.maybe_upgrade_fuel_capacity();
     }
 
+    // What our fuel and production capacity allow for, less the energy we're already producing.
+    fn producable_energy(&self) -> SimInt {
+        let state = self.state_ro.read().unwrap();
+        let producable = (state.fuel * self.config.povver_plant.energy_per_fuel).clamp(0, state.production_capacity.val());
+
+        (producable - state.committed_energy).max(0)
+    }
+
+    // Everything we can produce that isn't promised to a factory yet goes on the energy exchange
+    // with our full margin on top.
+    fn maybe_exchange_ask(&mut self) {
+        let offered_energy = self.pending_energy_offers.iter().map(|offer| offer.units).sum::<SimInt>();
+        let producable = (self.producable_energy() - offered_energy).max(0);
+        let energy_per_fuel = self.config.povver_plant.energy_per_fuel;
+
+        if producable == 0 {
+            self.check_buy_fuel();
+
+            return;
+        }
+
+        let (_, tariff_factor) = self.tariff();
+        let mut price_per_unit = self.fuel_price_paid_per_unit_average / energy_per_fuel as SimFlo * tariff_factor;
+        price_per_unit += price_per_unit * self.profit_margin.as_factor();
+
+        self.to_hub.send(PPHubSignal::ExchangeAsk(producable, price_per_unit));
+    }
+
     fn maybe_update_fuel_buy_threshold(&mut self, fuel_needed: SimInt) {
         if fuel_needed >= self.fuel_buy_threshold {
             self.fuel_buy_threshold = fuel_needed.clamp(0, self.state_ro.read().unwrap().fuel_capacity);
@@ -260,18 +352,18 @@ impl PovverPlant {
             )
         };
 
-        if self.fuel_buy_threshold == fuel_capacity && balance / 4.0 >= PP_FUEL_CAPACITY_INCREASE_COST && !is_awaiting_fuel_capacity
+        if self.fuel_buy_threshold == fuel_capacity && balance / 4.0 >= self.config.povver_plant.fuel_capacity_increase_cost && !is_awaiting_fuel_capacity
         {
-            self.get_dynamic_sender().send(Arc::new(PPHubSignal::IncreaseFuelCapacity)).unwrap();
+            self.to_hub.send(PPHubSignal::IncreaseFuelCapacity);
         }
     }
 
     fn maybe_upgrade_production_capacity(&self) {
         let balance = self.state_ro.read().unwrap().balance;
-        if balance.val() <= PP_PRODUCTION_CAPACITY_INCREASE_COST.val() / 2.0 &&
+        if balance.val() <= self.config.povver_plant.production_capacity_increase_cost / 2.0 &&
             !self.state_ro.read().unwrap().is_awaiting_production_capacity
         {
-            self.get_dynamic_sender().send(Arc::new(PPHubSignal::IncreaseProductionCapacity)).unwrap();
+            self.to_hub.send(PPHubSignal::IncreaseProductionCapacity);
         }
     }
 
@@ -279,8 +371,10 @@ impl PovverPlant {
         let index = self.pending_energy_offers.iter().position(|of| of.to_factory_id == offer.to_factory_id);
         if let Some(index) = index {
             let plucked_offer = self.pending_energy_offers.remove(index);
+            // The factory may order less than we offered when others cover part of its need, never more.
+            let order = PPEnergyOffer { units: offer.units.min(plucked_offer.units), ..plucked_offer };
 
-            self.get_dynamic_sender().send(Arc::new(PPHubSignal::ProduceEnergy(plucked_offer))).unwrap();
+            self.to_hub.send(PPHubSignal::ProduceEnergy(order));
             self.log_ui_console(format!("Energy to factory No. {} is coming right up!", offer.to_factory_id), Info);
         } else {
             self.log_console(format!("Energy offer to process: {:?} could not be found in pending offers: {:?}", offer, self.pending_energy_offers), Error);
@@ -293,6 +387,44 @@ impl PovverPlant {
     }
 }
 
+impl PovverPlant {
+    pub fn connect_factory(&mut self, factory_id: usize, to_factory: Outbox<PPFactorySignal>, from_factory: Inbox<FactoryPPSignal>) {
+        self.to_factories.insert(factory_id, to_factory);
+        self.from_factories.insert(factory_id, from_factory);
+    }
+
+    // Offers to a retired factory would never be answered, so they go too.
+    pub fn disconnect_factory(&mut self, factory_id: usize) {
+        self.to_factories.remove(&factory_id);
+        self.from_factories.remove(&factory_id);
+        self.pending_energy_offers.retain(|offer| offer.to_factory_id != factory_id);
+    }
+}
+
+impl PovverPlant {
+    pub fn snapshot(&self) -> PovverPlantSnapshot {
+        PovverPlantSnapshot {
+            profit_margin: self.profit_margin,
+            fuel_buy_threshold: self.fuel_buy_threshold,
+            fuel_price_paid_per_unit_average: self.fuel_price_paid_per_unit_average,
+            total_fuel_expenditure: self.total_fuel_expenditure,
+            pending_energy_offers: self.pending_energy_offers.clone(),
+            last_hundred_sales: self.last_hundred_sales.clone(),
+            rng: self.rng.clone(),
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: PovverPlantSnapshot) {
+        self.profit_margin = snapshot.profit_margin;
+        self.fuel_buy_threshold = snapshot.fuel_buy_threshold;
+        self.fuel_price_paid_per_unit_average = snapshot.fuel_price_paid_per_unit_average;
+        self.total_fuel_expenditure = snapshot.total_fuel_expenditure;
+        self.pending_energy_offers = snapshot.pending_energy_offers;
+        self.last_hundred_sales = snapshot.last_hundred_sales;
+        self.rng = snapshot.rng;
+    }
+}
+
 impl PovverPlant {
     pub fn start(
         me: Arc<Mutex<Self>>,
@@ -300,82 +432,76 @@ impl PovverPlant {
         let (
             state_ro,
             mut wakeup_receiver,
-            dynamic_receiver,
+            from_hub,
             mut hub_broadcast_receiver,
-            mut from_factory_dyn_receivers
+            step_turn,
         ) = {
-            let me_lock = me.lock().unwrap();
+            let mut me_lock = me.lock().unwrap();
             (
                 ReadOnlyRwLock::clone(&me_lock.state_ro),
                 me_lock.wakeup_receiver.resubscribe(),
-                me_lock.get_dynamic_receiver().clone(),
+                me_lock.from_hub.clone(),
                 me_lock.hub_broadcast_receiver.resubscribe(),
-                me_lock.from_factory_receivers.iter().map(|r| r.resubscribe()).collect::<Vec<BroadcastDynReceiver>>(),
+                me_lock.step_turn.take().expect("The Povver Plant is only started once"),
             )
         };
 
         thread::Builder::new().name("POVVER_PLANT".to_string()).spawn(move || {
-            'outer: loop {
-                if let Ok(signal) = hub_broadcast_receiver.try_recv() {
-                    let signal_any = signal.as_any();
-                    match signal_any {
-                        s if s.is::<FactoryEnergyDemand>() => {
-                            if let Some(demand) = signal_any.downcast_ref::<FactoryEnergyDemand>() {
-                                me.lock().unwrap().maybe_new_energy_offer(&demand);
+            // One pass over the inboxes. Returns the number of handled messages and whether to quit.
+            let mut poll = || -> (usize, bool) {
+                let mut handled = 0;
+
+                if let Ok(message) = hub_broadcast_receiver.try_recv() {
+                    handled += 1;
+                    match message.signal {
+                        HubBroadcastSignal::FactoryNeedsEnergy(demand) => {
+                            me.lock().unwrap().maybe_new_energy_offer(&demand);
+                        }
+                        HubBroadcastSignal::ExchangeOpen => {
+                            if !state_ro.read().unwrap().is_bankrupt {
+                                me.lock().unwrap().maybe_exchange_ask();
                             }
                         }
-                        _ => ()
                     }
                 }
-                if let Ok(signal) = dynamic_receiver.try_recv() {
-                    let signal_any = signal.as_any();
-                    match signal_any {
-                        s if s.is::<HubPPSignal>() => {
-                            if let Some(signal_from_hub) = signal_any.downcast_ref::<HubPPSignal>() {
-                                match signal_from_hub {
-                                    HubPPSignal::FuelTransfered(receipt) => {
-                                        me.lock().unwrap().update_price_paid_per_fuel_average(receipt);
-                                    }
-                                    HubPPSignal::EnergyTransfered(receipt) => {
-                                        me.lock().unwrap().last_hundred_sales.add(receipt.clone());
-                                        //TODO
-                                        // Energy transfered. Let's do something about it!
-                                    },
-                                    HubPPSignal::FuelCapacityIncreased => {
-                                        //TODO
-                                        // Fuel capacity increased. Let's do something about it!
-                                    },
-                                    HubPPSignal::ProductionCapacityIncreased => {
-                                        //TODO
-                                        // Production capacity increased. Let's do something about it!
-                                    },
-                                }
-                            }
+                if let Ok(message) = from_hub.try_recv() {
+                    handled += 1;
+                    match message.signal {
+                        HubPPSignal::FuelTransfered(receipt) => {
+                            me.lock().unwrap().update_price_paid_per_fuel_average(&receipt);
                         }
-                        _ => ()
+                        HubPPSignal::EnergyTransfered(receipt) => {
+                            me.lock().unwrap().last_hundred_sales.add(receipt);
+                            //TODO
+                            // Energy transfered. Let's do something about it!
+                        },
+                        HubPPSignal::FuelCapacityIncreased => {
+                            //TODO
+                            // Fuel capacity increased. Let's do something about it!
+                        },
+                        HubPPSignal::ProductionCapacityIncreased => {
+                            //TODO
+                            // Production capacity increased. Let's do something about it!
+                        },
                     }
                 }
-                from_factory_dyn_receivers.iter_mut().for_each(|receiver| {
-                    while let Ok(signal) = receiver.try_recv() {
-                        let signal_any = signal.as_any();
-                        match signal_any {
-                            s if s.is::<FactorySignal>() => {
-                                if let Some(signal_from_factory) = signal_any.downcast_ref::<FactorySignal>() {
-                                    match signal_from_factory {
-                                        FactorySignal::AcceptPPEnergyOffer(offer) => {
-                                            me.lock().unwrap().process_factory_order(offer);
-                                        }
-                                        FactorySignal::RejectPPEnergyOffer(offer) => {
-                                            me.lock().unwrap().remove_pending_offer(offer);
-                                        }
-                                    }
-                                }
+                // Factories come and go, so we pick up their inboxes on every pass.
+                let from_factories = me.lock().unwrap().from_factories.values().cloned().collect::<Vec<_>>();
+                for inbox in from_factories.iter() {
+                    while let Ok(message) = inbox.try_recv() {
+                        handled += 1;
+                        match message.signal {
+                            FactoryPPSignal::AcceptPPEnergyOffer(offer) => {
+                                me.lock().unwrap().process_factory_order(&offer);
+                            }
+                            FactoryPPSignal::RejectPPEnergyOffer(offer) => {
+                                me.lock().unwrap().remove_pending_offer(&offer);
                             }
-                            _ => ()
                         }
                     }
-                });
+                }
                 if let Ok(action) = wakeup_receiver.try_recv() {
+                    handled += 1;
                     match action {
                         StateAction::Timer(event) => {
                             if state_ro.read().unwrap().is_bankrupt == true {
@@ -389,17 +515,35 @@ impl PovverPlant {
                             }
                         }
                         StateAction::SpeedChange(td) => {
-                            me.lock().unwrap().sleeptime = Self::recalculate_sleeptime(td);
+                            let mut me_lock = me.lock().unwrap();
+                            me_lock.sleeptime = Self::recalculate_sleeptime(td);
+                        }
+                        StateAction::ClockModeChange(clock_mode) => {
+                            me.lock().unwrap().clock_mode = clock_mode;
                         }
                         StateAction::Quit => {
                             me.lock().unwrap().log_console("Quit signal received.".to_string(), Warning);
-                            break 'outer;
+                            return (handled, true);
                         }
                         _ => ()
                     }
                 }
-                
-                thread::sleep(me.lock().unwrap().sleeptime);
+
+                (handled, false)
+            };
+
+            loop {
+                let clock_mode = me.lock().unwrap().clock_mode;
+                if clock_mode == ClockMode::Stepped {
+                    if step_turn.take(&mut poll) {
+                        break;
+                    }
+                } else {
+                    if poll().1 {
+                        break;
+                    }
+                    thread::sleep(me.lock().unwrap().sleeptime);
+                }
             }
         }).unwrap()
     }
@@ -0,0 +1,326 @@
+use std::{
+    fmt,
+    fs,
+    io,
+    path::Path,
+    sync::OnceLock,
+};
+use serde::{Serialize, Deserialize};
+
+use crate::{
+    economy::{
+        industries::Industry,
+        products::{Product, UnitProductionCost, ProductDemandInfo, PRODUCTS},
+    },
+    simulation::{SimFlo, SimInt},
+    utils_data::tidy_toml_floats,
+};
+
+// Products loaded from a catalog file. The built-in PRODUCTS are used until one is installed.
+static CATALOG: OnceLock<&'static [Product]> = OnceLock::new();
+
+#[derive(Debug)]
+pub enum CatalogError {
+    Io(io::Error),
+    Format(toml::de::Error),
+    Invalid(Vec<String>),
+    AlreadyInstalled,
+}
+
+impl fmt::Display for CatalogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "product catalog file error: {e}"),
+            Self::Format(e) => write!(f, "malformed product catalog: {e}"),
+            Self::Invalid(problems) => {
+                write!(f, "invalid product catalog:")?;
+                for problem in problems {
+                    write!(f, "\n  - {problem}")?;
+                }
+                Ok(())
+            },
+            Self::AlreadyInstalled => write!(f, "a product catalog is already installed"),
+        }
+    }
+}
+
+impl std::error::Error for CatalogError {}
+
+impl From<io::Error> for CatalogError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for CatalogError {
+    fn from(e: toml::de::Error) -> Self {
+        Self::Format(e)
+    }
+}
+
+// A product as written in the catalog file.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProductEntry {
+    name: String,
+    description: String,
+    industry: Industry,
+    units_per_minute: SimInt,
+    rnd_cost: SimFlo,
+    unit_production_cost: UnitProductionCost,
+    demand_info: ProductDemandInfo,
+}
+
+impl From<&Product> for ProductEntry {
+    fn from(product: &Product) -> Self {
+        Self {
+            name: product.name.to_string(),
+            description: product.description.to_string(),
+            industry: product.industry.clone(),
+            units_per_minute: product.units_per_minute,
+            rnd_cost: product.rnd_cost,
+            unit_production_cost: product.unit_production_cost.clone(),
+            demand_info: product.demand_info.clone(),
+        }
+    }
+}
+
+impl ProductEntry {
+    // Products live as long as the program does, the same as the built-in ones.
+    fn into_product(self) -> Product {
+        Product {
+            name: Box::leak(self.name.into_boxed_str()),
+            description: Box::leak(self.description.into_boxed_str()),
+            unit_production_cost: self.unit_production_cost,
+            units_per_minute: self.units_per_minute,
+            rnd_cost: self.rnd_cost,
+            industry: self.industry,
+            demand_info: self.demand_info,
+        }
+    }
+
+    fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let at = format!("product \"{}\"", self.name);
+
+        if self.name.trim().is_empty() {
+            problems.push("a product has no name".to_string());
+        }
+        if self.units_per_minute <= 0 {
+            problems.push(format!("{at}: units_per_minute must be positive, got {}", self.units_per_minute));
+        }
+        if !self.rnd_cost.is_finite() || self.rnd_cost < 0.0 {
+            problems.push(format!("{at}: rnd_cost must not be negative, got {}", self.rnd_cost));
+        }
+
+        let cost = &self.unit_production_cost;
+        if cost.energy < 0 {
+            problems.push(format!("{at}: unit_production_cost.energy must not be negative, got {}", cost.energy));
+        }
+        for (field, value) in [
+            ("labor", cost.labor),
+            ("raw_materials", cost.raw_materials),
+            ("equipment_maintenance", cost.equipment_maintenance),
+            ("packaging", cost.packaging),
+        ] {
+            if !value.is_finite() || value < 0.0 {
+                problems.push(format!("{at}: unit_production_cost.{field} must not be negative, got {value}"));
+            }
+        }
+
+        let demand = &self.demand_info;
+        if !(0.0..=100.0).contains(&demand.min_percentage) || !(0.0..=100.0).contains(&demand.max_percentage) {
+            problems.push(format!("{at}: demand_info percentages must be between 0 and 100"));
+        } else if demand.min_percentage > demand.max_percentage {
+            problems.push(format!(
+                "{at}: demand_info.min_percentage {} is above max_percentage {}", demand.min_percentage, demand.max_percentage
+            ));
+        }
+        if demand.unit_per_percent <= 0 {
+            problems.push(format!("{at}: demand_info.unit_per_percent must be positive, got {}", demand.unit_per_percent));
+        }
+
+        // Demands go through these stages in order as they age.
+        let timeline = &demand.demand_timeline;
+        let stages = [
+            ("inc_quarter", timeline.inc_quarter),
+            ("dec_quarter", timeline.dec_quarter),
+            ("dec_half", timeline.dec_half),
+            ("dec_three_quarters", timeline.dec_three_quarters),
+            ("deadline", timeline.deadline),
+        ];
+        if stages[0].1 < 0 {
+            problems.push(format!("{at}: demand_timeline.inc_quarter must not be negative, got {}", stages[0].1));
+        }
+        for pair in stages.windows(2) {
+            let ((prev_name, prev), (name, value)) = (pair[0], pair[1]);
+            if value < prev {
+                problems.push(format!("{at}: demand_timeline.{name} {value} comes before {prev_name} {prev}"));
+            }
+        }
+
+        problems
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CatalogFile {
+    products: Vec<ProductEntry>,
+}
+
+// Every product of the simulation.
+pub fn products() -> &'static [Product] {
+    CATALOG.get().copied().unwrap_or(PRODUCTS)
+}
+
+// Reads and validates a catalog file.
+pub fn load(path: &Path) -> Result<Vec<Product>, CatalogError> {
+    let catalog: CatalogFile = toml::from_str(&fs::read_to_string(path)?)?;
+
+    let mut problems = Vec::new();
+    if catalog.products.is_empty() {
+        problems.push("there must be at least one product".to_string());
+    }
+    for (i, entry) in catalog.products.iter().enumerate() {
+        if catalog.products[..i].iter().any(|other| other.name == entry.name) {
+            problems.push(format!("product \"{}\" is listed more than once", entry.name));
+        }
+        problems.extend(entry.problems());
+    }
+    if !problems.is_empty() {
+        return Err(CatalogError::Invalid(problems));
+    }
+
+    Ok(catalog.products.into_iter().map(ProductEntry::into_product).collect())
+}
+
+// Replaces the built-in products for the rest of the program. Must happen before anything
+// refers to a product, like scenarios and snapshots do, and can only be done once.
+pub fn install(path: &Path) -> Result<(), CatalogError> {
+    let products: &'static [Product] = Box::leak(load(path)?.into_boxed_slice());
+    CATALOG.set(products).map_err(|_| CatalogError::AlreadyInstalled)
+}
+
+// Writes the products in the catalog file format, as a starting point for tuning them.
+pub fn export(path: &Path) -> Result<(), CatalogError> {
+    let catalog = CatalogFile {
+        products: products().iter().map(ProductEntry::from).collect(),
+    };
+    let mut value = toml::Value::try_from(&catalog).map_err(io::Error::other)?;
+    tidy_toml_floats(&mut value);
+    let text = toml::to_string_pretty(&value).map_err(io::Error::other)?;
+    fs::write(path, text)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The first two built-in products as a catalog file, changed to taste.
+    fn catalog(name: &str, change: impl FnOnce(&mut toml::Value)) -> Result<Vec<Product>, CatalogError> {
+        let catalog = CatalogFile {
+            products: PRODUCTS[..2].iter().map(ProductEntry::from).collect(),
+        };
+        let mut value = toml::Value::try_from(&catalog).unwrap();
+        change(&mut value);
+
+        load_text(name, &toml::to_string(&value).unwrap())
+    }
+
+    fn load_text(name: &str, text: &str) -> Result<Vec<Product>, CatalogError> {
+        let path = std::env::temp_dir().join(format!("povver-catalog-{name}.toml"));
+        fs::write(&path, text).unwrap();
+        let products = load(&path);
+        fs::remove_file(&path).unwrap();
+
+        products
+    }
+
+    fn invalid(result: Result<Vec<Product>, CatalogError>) -> Vec<String> {
+        match result {
+            Err(CatalogError::Invalid(problems)) => problems,
+            other => panic!("expected an invalid catalog, got {other:?}"),
+        }
+    }
+
+    fn product(value: &mut toml::Value, index: usize) -> &mut toml::Table {
+        value["products"][index].as_table_mut().unwrap()
+    }
+
+    #[test]
+    fn built_in_catalog_file_loads() {
+        let products = load(Path::new("data/products.toml")).unwrap();
+        assert_eq!(products.len(), PRODUCTS.len());
+
+        let products = catalog("unchanged", |_| ()).unwrap();
+        assert_eq!(products[1].name, PRODUCTS[1].name);
+    }
+
+    #[test]
+    fn malformed_file_is_a_format_error() {
+        let result = load_text("malformed", "[[products]\nname = \"Paper\"");
+        assert!(matches!(result, Err(CatalogError::Format(_))), "{result:?}");
+
+        let result = catalog("missing-field", |value| {
+            product(value, 0).remove("rnd_cost");
+        });
+        assert!(matches!(&result, Err(CatalogError::Format(e)) if e.to_string().contains("missing field `rnd_cost`")), "{result:?}");
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        let result = catalog("unknown-key", |value| {
+            product(value, 0).insert("colour".to_string(), "blue".into());
+        });
+        assert!(matches!(&result, Err(CatalogError::Format(e)) if e.to_string().contains("unknown field `colour`")), "{result:?}");
+
+        let result = catalog("unknown-nested-key", |value| {
+            product(value, 1)["demand_info"].as_table_mut().unwrap().insert("season".to_string(), 1.into());
+        });
+        assert!(matches!(&result, Err(CatalogError::Format(e)) if e.to_string().contains("unknown field `season`")), "{result:?}");
+    }
+
+    #[test]
+    fn every_problem_is_reported_at_once() {
+        let first = PRODUCTS[0].name;
+        let problems = invalid(catalog("out-of-range", |value| {
+            let entry = product(value, 0);
+            entry.insert("units_per_minute".to_string(), 0.into());
+            entry.insert("rnd_cost".to_string(), (-1.0).into());
+            entry["unit_production_cost"]["labor"] = (-2.0).into();
+            entry["demand_info"]["min_percentage"] = 60.0.into();
+            entry["demand_info"]["max_percentage"] = 40.0.into();
+            entry["demand_info"]["demand_timeline"]["deadline"] = (-1).into();
+
+            product(value, 1).insert("name".to_string(), first.into());
+        }));
+
+        let at = format!("product \"{first}\"");
+        let expected = [
+            format!("{at}: units_per_minute must be positive, got 0"),
+            format!("{at}: rnd_cost must not be negative, got -1"),
+            format!("{at}: unit_production_cost.labor must not be negative, got -2"),
+            format!("{at}: demand_info.min_percentage 60 is above max_percentage 40"),
+            format!("{at}: demand_timeline.deadline -1 comes before "),
+            format!("{at} is listed more than once"),
+        ];
+        for start in &expected {
+            assert!(problems.iter().any(|problem| problem.starts_with(start.as_str())), "no \"{start}\" in {problems:#?}");
+        }
+        assert_eq!(problems.len(), expected.len(), "{problems:#?}");
+    }
+
+    #[test]
+    fn percentages_must_be_in_range() {
+        let problems = invalid(catalog("percentages", |value| {
+            product(value, 1)["demand_info"]["max_percentage"] = 150.0.into();
+        }));
+        assert_eq!(problems, vec![format!("product \"{}\": demand_info percentages must be between 0 and 100", PRODUCTS[1].name)]);
+
+        let problems = invalid(load_text("empty", "products = []"));
+        assert_eq!(problems, vec!["there must be at least one product".to_string()]);
+    }
+}
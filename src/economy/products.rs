@@ -1,11 +1,14 @@
+use serde::{Serialize, Deserialize};
 use crate::{
     economy::{
         industries::Industry,
+        product_catalog,
     },
     simulation::{SimFlo, SimInt},
 };
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct UnitProductionCost {
     pub energy: SimInt,
     pub labor: SimFlo,
@@ -14,7 +17,8 @@ pub struct UnitProductionCost {
     pub packaging: SimFlo,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct ProductDemandTimeline {
     pub inc_quarter: SimInt,
     pub dec_quarter: SimInt,
@@ -23,7 +27,8 @@ pub struct ProductDemandTimeline {
     pub deadline: SimInt,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct ProductDemandInfo {
     pub min_percentage: SimFlo,
     pub max_percentage: SimFlo,
@@ -576,8 +581,17 @@ impl Product {
 }
 
 impl Product {
-    pub fn by_industry(industry: &Industry) -> Vec<&Self> {
-        Vec::from(PRODUCTS.iter().filter(|&product| &product.industry == industry).collect::<Vec<_>>())
+    // Every product of the active catalog.
+    pub fn all() -> &'static [Self] {
+        product_catalog::products()
+    }
+
+    pub fn by_industry(industry: &Industry) -> Vec<&'static Self> {
+        Self::all().iter().filter(|&product| &product.industry == industry).collect::<Vec<_>>()
+    }
+
+    pub fn by_name(name: &str) -> Option<&'static Self> {
+        Self::all().iter().find(|product| product.name == name)
     }
 
     pub fn get_unit_cost_excl_energy(&self) -> SimFlo {
@@ -587,13 +601,15 @@ impl Product {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ProductStock {
+    #[serde(with = "product_ref")]
     pub product: &'static Product,
     pub units: SimInt,
     pub unit_production_cost: SimFlo,
 }
 
+// The built-in catalog. A catalog file can replace it, see product_catalog.
 pub const PRODUCTS: &[Product] = &[
     Product::INTEGRATED_CIRCUIT,
     Product::MICROCHIP,
@@ -615,4 +631,39 @@ pub const PRODUCTS: &[Product] = &[
     Product::ASSISTANT_INTRUDER,
     Product::GRADUATE,
     Product::STUDENT_DEBT,
-];
\ No newline at end of file
+];
+
+// Products are static data, so snapshots refer to them by their names which are unique.
+pub mod product_ref {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+    use super::Product;
+
+    pub fn serialize<S: Serializer>(product: &&'static Product, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(product.name)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<&'static Product, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Product::by_name(&name).ok_or_else(|| D::Error::custom(format!("unknown product \"{name}\"")))
+    }
+}
+
+pub mod product_refs {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer, ser::SerializeSeq};
+    use super::Product;
+
+    pub fn serialize<S: Serializer>(products: &[&'static Product], serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(products.len()))?;
+        for product in products {
+            seq.serialize_element(product.name)?;
+        }
+        seq.end()
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<&'static Product>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|name| Product::by_name(name).ok_or_else(|| D::Error::custom(format!("unknown product \"{name}\""))))
+            .collect()
+    }
+}
@@ -1,14 +1,15 @@
+use serde::{Serialize, Deserialize};
 use crate::{
     simulation::{
         SimInt,
         timer::TimerEvent,
-        sim_constants::*
     },
+    config::SolarPanelConfig,
     utils_traits::AsFactor,
     environment::SunBrightness,
 };
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SolarPanel {
     age: SimInt,
     is_defunct: bool,
@@ -22,10 +23,10 @@ impl SolarPanel {
         }
     }
 
-    pub fn produce_energy(&mut self, timer_event: &TimerEvent, sunshine: SunBrightness) -> SimInt {
+    pub fn produce_energy(&mut self, timer_event: &TimerEvent, sunshine: SunBrightness, config: &SolarPanelConfig) -> SimInt {
         if timer_event.at_least_month() {
             self.age += 1;
-            if self.age >= SOLAR_PANEL_MAX_AGE {
+            if self.age >= config.max_age {
                 self.is_defunct = true;
             }
         }
@@ -35,7 +36,7 @@ impl SolarPanel {
         }
 
         let mut energy = (sunshine.val() / 2.0).ceil() as SimInt;
-        energy -= self.age * SOLAR_PANEL_AGE_MODIFIER;
+        energy -= self.age * config.age_modifier;
 
         energy
     }
@@ -0,0 +1,59 @@
+use serde::{Serialize, Deserialize};
+use crate::{
+    simulation::{
+        SimInt,
+        SimFlo,
+        timer::TimerEvent,
+    },
+    config::WindTurbineConfig,
+    environment::WindSpeed,
+};
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct WindTurbine {
+    age: SimInt,
+    is_defunct: bool,
+}
+
+impl WindTurbine {
+    pub fn new() -> Self {
+        Self {
+            age: 0,
+            is_defunct: false,
+        }
+    }
+
+    pub fn produce_energy(&mut self, timer_event: &TimerEvent, wind_speed: &WindSpeed, config: &WindTurbineConfig) -> SimInt {
+        if timer_event.at_least_month() {
+            self.age += 1;
+            if self.age >= config.max_age {
+                self.is_defunct = true;
+            }
+        }
+
+        if self.is_defunct {
+            return 0;
+        }
+
+        let mut energy = Self::power_curve(wind_speed.val(), config);
+        if energy > 0 {
+            energy -= self.age * config.age_modifier;
+        }
+
+        energy.max(0)
+    }
+
+    // Nothing below the cut-in speed. Output grows with the cube of the wind speed up to the rated speed
+    // and stays at the rated energy from there. The turbine stops at cut-out speed so typhoons don't wreck it.
+    fn power_curve(wind_speed: SimInt, config: &WindTurbineConfig) -> SimInt {
+        match wind_speed {
+            ws if ws < config.cut_in_speed || ws >= config.cut_out_speed => 0,
+            ws if ws >= config.rated_speed => config.rated_energy,
+            ws => {
+                let ratio = (ws - config.cut_in_speed) as SimFlo / (config.rated_speed - config.cut_in_speed) as SimFlo;
+
+                (config.rated_energy as SimFlo * ratio.powi(3)).ceil() as SimInt
+            }
+        }
+    }
+}
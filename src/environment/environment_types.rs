@@ -1,14 +1,64 @@
 use num_traits::FromPrimitive;
+use serde::{Serialize, Deserialize};
 use super::WINDSPEED_MAX;
 use crate::{
     simulation::{SimFlo, SimInt},
-    ui_controller::{CloudSize, SunData, SunStage, WindDirection, WindSpeedLevel},
     utils_traits::{Flippable, AsFactor, HundredPercentable},
 };
+#[cfg(feature = "gui")]
+use crate::ui_controller::{
+    Cloud as UICloud,
+    CloudSize as UICloudSize,
+    SunData,
+    SunStage as UISunStage,
+    WindDirection as UIWindDirection,
+    WindSpeedLevel as UIWindSpeedLevel,
+};
 
 pub const CLOUD_SIZES: &[CloudSize] = &[CloudSize::Small, CloudSize::Medium, CloudSize::Big];
 
-#[derive(Debug, Default, Copy, Clone)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SunStage {
+    #[default]
+    Set,
+    Weak,
+    Normal,
+    Bright,
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum CloudSize {
+    #[default]
+    Small,
+    Medium,
+    Big,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cloud {
+    pub size: CloudSize,
+    pub position: SimInt,
+    pub image_index: SimInt,
+    pub image_rotated: bool,
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum WindDirection {
+    #[default]
+    Ltr,
+    Rtl,
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum WindSpeedLevel {
+    #[default]
+    Faint,
+    Mild,
+    Strong,
+    Typhoon,
+}
+
+#[derive(Debug, Default, Copy, Clone, Serialize, Deserialize)]
 pub struct TheSun {
     pub position: SimInt,
     pub brightness: SunBrightness,
@@ -16,18 +66,76 @@ pub struct TheSun {
     pub stage: SunStage,
 }
 
+#[cfg(feature = "gui")]
 impl From<&TheSun> for SunData {
     fn from(the_sun: &TheSun) -> Self {
         SunData {
             position: the_sun.position,
             brightness: the_sun.brightness.val(),
             brightness_reduction: the_sun.brightness_reduction,
-            stage: the_sun.stage,
+            stage: the_sun.stage.into(),
+        }
+    }
+}
+
+#[cfg(feature = "gui")]
+impl From<SunStage> for UISunStage {
+    fn from(stage: SunStage) -> Self {
+        match stage {
+            SunStage::Set => Self::Set,
+            SunStage::Weak => Self::Weak,
+            SunStage::Normal => Self::Normal,
+            SunStage::Bright => Self::Bright,
+        }
+    }
+}
+
+#[cfg(feature = "gui")]
+impl From<CloudSize> for UICloudSize {
+    fn from(size: CloudSize) -> Self {
+        match size {
+            CloudSize::Small => Self::Small,
+            CloudSize::Medium => Self::Medium,
+            CloudSize::Big => Self::Big,
+        }
+    }
+}
+
+#[cfg(feature = "gui")]
+impl From<&Cloud> for UICloud {
+    fn from(cloud: &Cloud) -> Self {
+        Self {
+            size: cloud.size.into(),
+            position: cloud.position,
+            image_index: cloud.image_index,
+            image_rotated: cloud.image_rotated,
+        }
+    }
+}
+
+#[cfg(feature = "gui")]
+impl From<WindDirection> for UIWindDirection {
+    fn from(direction: WindDirection) -> Self {
+        match direction {
+            WindDirection::Ltr => Self::Ltr,
+            WindDirection::Rtl => Self::Rtl,
+        }
+    }
+}
+
+#[cfg(feature = "gui")]
+impl From<WindSpeedLevel> for UIWindSpeedLevel {
+    fn from(level: WindSpeedLevel) -> Self {
+        match level {
+            WindSpeedLevel::Faint => Self::Faint,
+            WindSpeedLevel::Mild => Self::Mild,
+            WindSpeedLevel::Strong => Self::Strong,
+            WindSpeedLevel::Typhoon => Self::Typhoon,
         }
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct SunBrightness(SimFlo);
 impl SunBrightness {
     pub const NONE: Self = Self(0.0);
@@ -68,7 +176,7 @@ impl PartialOrd<SimFlo> for SunBrightness {
     }
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct WindSpeed(SimInt);
 impl WindSpeed {
     pub fn val(&self) -> SimInt {
@@ -1,5 +1,5 @@
 use std::sync::{Arc, RwLock};
-use rand::{random, rngs::ThreadRng, seq::SliceRandom, thread_rng, Rng};
+use rand::{seq::SliceRandom, Rng};
 
 mod environment_types;
 pub mod months;
@@ -9,8 +9,7 @@ use months::Month;
 use crate::{
     app_state::{EnvStateData, TimerStateData},
     simulation::{SimFlo, SimInt},
-    ui_controller::{Cloud, CloudSize, SunStage, WindDirection, WindSpeedLevel},
-    utils_random::{one_chance_in_many, random_inc_dec_clamp_signed},
+    utils_random::{one_chance_in_many, random_inc_dec_clamp_signed, SimRng},
     utils_traits::*,
 };
 
@@ -23,14 +22,12 @@ const SUN_POS_MAX: SimInt = 15;
 pub struct Environment {
     env_state: Arc<RwLock<EnvStateData>>,
     timer_state: Arc<RwLock<TimerStateData>>,
-    rng: ThreadRng,
+    rng: SimRng,
 }
 
 // Constructor
 impl Environment {
-    pub fn new(timer_state: Arc<RwLock<TimerStateData>>) -> (Self, Arc<RwLock<EnvStateData>>) {
-        let mut rng = thread_rng();
-
+    pub fn new(timer_state: Arc<RwLock<TimerStateData>>, mut rng: SimRng) -> (Self, Arc<RwLock<EnvStateData>>) {
         let mut clouds = Vec::with_capacity(CLOUDS_MAX as usize);
         let mut wind_speed = WindSpeed::default();
 
@@ -49,7 +46,7 @@ impl Environment {
                     size,
                     position,
                     image_index,
-                    image_rotated: random(),
+                    image_rotated: rng.gen_bool(0.5),
                 });
             }
 
@@ -58,7 +55,7 @@ impl Environment {
             );
         };
 
-        let wind_direction = if random() {
+        let wind_direction = if rng.gen_bool(0.5) {
             WindDirection::Ltr
         } else {
             WindDirection::Rtl
@@ -186,15 +183,13 @@ impl Environment {
     // natural-like cloud migrations that follow each other
     // and form clusters of clouds.
     fn maybe_new_cloud(
-        &self,
+        rng: &mut SimRng,
         clouds: &[Cloud],
         wind_speed: &WindSpeed,
         tail_pos: SimInt,
         sibling_pos: SimInt,
         cloud_forming_factor: SimFlo,
     ) -> Option<Cloud> {
-        let mut rng = thread_rng();
-
         let tail_clouds_count = clouds
             .iter()
             .filter(|cloud| cloud.position == tail_pos)
@@ -220,10 +215,10 @@ impl Environment {
 
             if rng.gen_range(0..=wind_speed_rnd_ceiling) <= probability as SimInt {
                 Some(Cloud {
-                    size: *CLOUD_SIZES.choose(&mut rng).unwrap(),
+                    size: *CLOUD_SIZES.choose(rng).unwrap(),
                     position: tail_pos,
                     image_index: rng.gen_range(0..4),
-                    image_rotated: random(),
+                    image_rotated: rng.gen_bool(0.5),
                 })
             } else {
                 None
@@ -235,12 +230,12 @@ impl Environment {
                 WindSpeedLevel::Strong => 20,
                 WindSpeedLevel::Typhoon => 10,
             } as SimFlo * cloud_forming_factor) as u32;
-            if one_chance_in_many(&mut rng, wind_speed_rnd_how_many) {
+            if one_chance_in_many(rng, wind_speed_rnd_how_many) {
                 Some(Cloud {
-                    size: *CLOUD_SIZES.choose(&mut rng).unwrap(),
+                    size: *CLOUD_SIZES.choose(rng).unwrap(),
                     position: tail_pos,
                     image_index: rng.gen_range(0..4),
-                    image_rotated: random(),
+                    image_rotated: rng.gen_bool(0.5),
                 })
             } else {
                 None
@@ -304,7 +299,7 @@ impl Environment {
             let sibling_pos =
                 if wind_direction == WindDirection::Rtl { CLOUD_POS_MAX - 1 } else { 1 };
 
-            if let Some(cloud) = self.maybe_new_cloud(env.clouds.as_slice(), &wind_speed, tail_pos, sibling_pos, cloud_forming_factor) {
+            if let Some(cloud) = Self::maybe_new_cloud(&mut self.rng, env.clouds.as_slice(), &wind_speed, tail_pos, sibling_pos, cloud_forming_factor) {
                 println!("PUSHING IN A NEW CLOUD: {:?}", cloud);
                 env.clouds.push(cloud);
             }
@@ -314,6 +309,15 @@ impl Environment {
 
 // Public API
 impl Environment {
+    pub fn clone_rng(&self) -> SimRng {
+        self.rng.clone()
+    }
+
+    pub fn restore(&mut self, state: EnvStateData, rng: SimRng) {
+        *self.env_state.write().unwrap() = state;
+        self.rng = rng;
+    }
+
     pub fn update(&mut self) {
         // We don't change stuff too often to prevent erratic changes
         // so the changes are done on an hourly basis.
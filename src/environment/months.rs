@@ -23,6 +23,24 @@ pub fn get_month_data(number: usize) -> &'static Month {
     &MONTHS[number - 1]
 }
 
+// Snapshots keep the month number only.
+pub mod month_ref {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+    use crate::simulation::SimInt;
+    use super::{Month, MONTHS};
+
+    pub fn serialize<S: Serializer>(month: &&'static Month, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i32(month.number)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<&'static Month, D::Error> {
+        let number = SimInt::deserialize(deserializer)?;
+        MONTHS.iter()
+            .find(|month| month.number == number)
+            .ok_or_else(|| D::Error::custom(format!("no such month {number}")))
+    }
+}
+
 pub const MONTHS: [Month; 12] = [
     Month {
         number: 1,
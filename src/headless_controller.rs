@@ -0,0 +1,148 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    sync::Arc,
+    thread,
+};
+use tokio::sync::broadcast::{self as tokio_broadcast, error::{RecvError, TryRecvError}};
+
+use crate::{
+    app_state::StatePayload,
+    simulation::{
+        StateAction,
+        LogOutput,
+    },
+    logger::LogMessage,
+};
+
+// Headless counterpart of the UIController. It listens to the same state actions
+// and log messages as the UI but writes progress reports and logs to stdout or a file.
+pub struct HeadlessController {
+    log_output: LogOutput,
+    seed: u64,
+    // Effective config in TOML
+    config: String,
+}
+
+impl HeadlessController {
+    pub fn new(log_output: LogOutput, seed: u64, config: String) -> Self {
+        Self {
+            log_output,
+            seed,
+            config,
+        }
+    }
+}
+
+impl HeadlessController {
+    fn open_writer(log_output: &LogOutput) -> Box<dyn Write + Send> {
+        match log_output {
+            LogOutput::Stdout => Box::new(io::stdout()),
+            LogOutput::File(path) => match File::create(path) {
+                Ok(file) => Box::new(BufWriter::new(file)),
+                Err(e) => {
+                    eprintln!("HEADLESS: Could not create log file {}: {e}. Falling back to stdout.", path.display());
+                    Box::new(io::stdout())
+                }
+            },
+        }
+    }
+
+    fn write_progress(out: &mut dyn Write, state: &StatePayload) -> io::Result<()> {
+        writeln!(out, "---------- {} ----------", state.timer.read().unwrap().date)?;
+
+        {
+            let econ = state.economy.read().unwrap();
+            writeln!(
+                out,
+                "ECONOMY: inflation {:.2}%, fuel price {:.2}, active demands {}",
+                econ.inflation_rate, econ.fuel_price.val(), econ.product_demands.len()
+            )?;
+        }
+
+        {
+            let pp = state.povver_plant.read().unwrap();
+            writeln!(
+                out,
+                "POVVER PLANT: balance {:.2}, fuel {}/{}, production capacity {}{}",
+                pp.balance.val(), pp.fuel, pp.fuel_capacity, pp.production_capacity.val(),
+                if pp.is_bankrupt { ", BANKRUPT" } else { "" }
+            )?;
+        }
+
+        for factory in state.factories.read().unwrap().iter() {
+            let fs = factory.read().unwrap();
+            writeln!(
+                out,
+                "FACTORY No. {} ({}): balance {:.2}, energy {}, stored {}, stocks {}, solar panels {}, wind turbines {}, batteries {}{}",
+                fs.id, fs.industry.name, fs.balance.val(), fs.available_energy.val(), fs.stored_energy(),
+                fs.product_stocks.len(), fs.solarpanels.len(), fs.windturbines.len(), fs.batteries.len(),
+                if fs.is_bankrupt { ", BANKRUPT" } else { "" }
+            )?;
+        }
+
+        out.flush()
+    }
+}
+
+impl HeadlessController {
+    pub fn run(
+        &self,
+        mut wakeup_receiver: tokio_broadcast::Receiver<StateAction>,
+        mut log_receiver: tokio_broadcast::Receiver<LogMessage>,
+        state: Arc<StatePayload>,
+    ) -> thread::JoinHandle<()> {
+        let log_output = self.log_output.clone();
+        let seed = self.seed;
+        let config = self.config.clone();
+
+        thread::Builder::new().name("POVVER_HEADLESS".to_string()).spawn(move || {
+            let mut out = Self::open_writer(&log_output);
+            // Logger already echoes every UI message to the console,
+            // so we only copy them over when writing to a file.
+            let copy_logs = matches!(log_output, LogOutput::File(_));
+
+            // The seed and the config go on top so that the run can be reproduced from its output.
+            if let Err(e) = writeln!(out, "POVVER seed: {seed}\nPOVVER config:\n{config}") {
+                eprintln!("HEADLESS: Could not write the seed and the config: {e}");
+            }
+
+            loop {
+                let action = match wakeup_receiver.blocking_recv() {
+                    Ok(action) => action,
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                };
+
+                loop {
+                    match log_receiver.try_recv() {
+                        Ok(message) if copy_logs => {
+                            if let Err(e) = writeln!(out, "{}", message.message) {
+                                eprintln!("HEADLESS: Could not write log message: {e}");
+                            }
+                        },
+                        Err(TryRecvError::Lagged(skipped)) if copy_logs => {
+                            let _ = writeln!(out, "HEADLESS: {skipped} log messages were skipped.");
+                        },
+                        Ok(_) | Err(TryRecvError::Lagged(_)) => (),
+                        Err(_) => break,
+                    }
+                }
+
+                match action {
+                    StateAction::Timer(event) if event.at_least_day() => {
+                        if let Err(e) = Self::write_progress(out.as_mut(), &state) {
+                            eprintln!("HEADLESS: Could not write progress report: {e}");
+                        }
+                    },
+                    StateAction::Quit => {
+                        break;
+                    },
+                    _ => ()
+                }
+            }
+
+            let _ = out.flush();
+        }).unwrap()
+    }
+}
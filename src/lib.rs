@@ -0,0 +1,68 @@
+/*
+POVVER
+Copyright (C) 2025 Barış Ürüm <barisurum.works@gmail.com>
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License version 3 as published by
+the Free Software Foundation.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License version 3
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! POVVER is an agent-based simulator of simplified manufacturing economics
+//! and energy distribution between industries.
+//!
+//! The [`Simulation`] ties together the timer, the environment, the economy
+//! and "The Hub", which runs the factories and the Povver Plant as actors on
+//! their own threads. A simulation can be run in real time with [`Simulation::run`],
+//! or driven by the caller:
+//!
+//! ```no_run
+//! use povver::{Simulation, SimOptions, RunMode};
+//!
+//! let mut sim = Simulation::new(SimOptions {
+//!     run_mode: RunMode::Headless,
+//!     ..SimOptions::default()
+//! }).expect("the built-in config and scenario are valid");
+//! let mut actions = sim.subscribe_state_actions();
+//!
+//! sim.start();
+//! for _ in 0..60 {
+//!     sim.step();
+//! }
+//!
+//! let state = sim.state();
+//! println!("PP balance: {}", state.povver_plant.read().unwrap().balance.val());
+//! while let Ok(action) = actions.try_recv() {
+//!     println!("{action:?}");
+//! }
+//!
+//! sim.shutdown();
+//! ```
+//!
+//! With [`ClockMode::Stepped`] every [`Simulation::step`] advances exactly one
+//! simulated minute and returns only after the hub, the Povver Plant and all
+//! factories have handled it, no matter how long that takes in wall time.
+//! Together with a fixed seed this makes runs fully reproducible.
+
+pub mod app_state;
+pub mod economy;
+pub mod environment;
+#[cfg(feature = "gui")]
+pub mod ui_controller;
+pub mod headless_controller;
+pub mod utils_random;
+pub mod utils_traits;
+pub mod utils_data;
+pub mod logger;
+pub mod config;
+
+pub mod simulation;
+pub use simulation::{Simulation, SimOptions, RunMode, RunError, SetupError, LogOutput, ClockMode, StateAction, UIFlag};
+pub use config::Config;
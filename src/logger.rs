@@ -1,9 +1,7 @@
 use tokio::sync::broadcast as tokio_broadcast;
-use slint::SharedString;
-use crate::{
-    simulation::hub_comms::MessageEntity,
-    ui_controller::{LogMessage as UILogMessage, LogLevel as UILogLevel, MessageSource as UIMessageSource},
-};
+use crate::simulation::hub_comms::MessageEntity;
+#[cfg(feature = "gui")]
+use crate::ui_controller::{LogMessage as UILogMessage, LogLevel as UILogLevel, MessageSource as UIMessageSource};
 
 #[derive(Debug, Clone)]
 pub enum LogLevel {
@@ -13,6 +11,7 @@ pub enum LogLevel {
     Error,
 }
 
+#[cfg(feature = "gui")]
 impl From<LogLevel> for UILogLevel {
     fn from(ll: LogLevel) -> Self {
         match ll {
@@ -24,6 +23,7 @@ impl From<LogLevel> for UILogLevel {
     }
 }
 
+#[cfg(feature = "gui")]
 impl From<MessageEntity> for UIMessageSource {
     fn from(source: MessageEntity) -> Self {
         match source {
@@ -37,9 +37,10 @@ impl From<MessageEntity> for UIMessageSource {
 #[derive(Debug, Clone)]
 pub struct LogMessage {
     pub source: MessageEntity,
-    pub message: SharedString,
+    pub message: String,
     pub log_level: LogLevel,
 }
+#[cfg(feature = "gui")]
 impl From<LogMessage> for UILogMessage {
     fn from(lm: LogMessage) -> Self {
         let mut factory_id = -1;
@@ -50,7 +51,7 @@ impl From<LogMessage> for UILogMessage {
         UILogMessage {
             source: lm.source.into(),
             level: lm.log_level.into(),
-            message: lm.message,
+            message: lm.message.into(),
             factory_id
         }
     }
@@ -75,7 +76,7 @@ pub trait Logger {
         self.get_log_sender().send(
             LogMessage {
                 source: self.get_message_source(),
-                message: SharedString::from(msg),
+                message: msg,
                 log_level: level,
             }
         ).unwrap();
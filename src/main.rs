@@ -15,20 +15,616 @@ You should have received a copy of the GNU General Public License version 3
 along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
-mod app_state;
-mod economy;
-mod environment;
-mod ui_controller;
-mod utils_random;
-mod utils_traits;
-mod utils_data;
-mod logger;
-
-mod simulation;
-use simulation::Simulation;
-
-fn main() {
-    let mut sim = Simulation::new();
-
-    sim.run();
+use std::{
+    env,
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, BufRead, IsTerminal, Write},
+    path::{Path, PathBuf},
+    process::{Command, ExitCode, Stdio},
+    sync::{Mutex, atomic::{AtomicUsize, Ordering}},
+    thread,
+};
+use clap::{CommandFactory, Parser, ValueEnum, error::ErrorKind};
+
+use povver::{
+    Simulation, SimOptions, SetupError, RunMode, LogOutput,
+    simulation::{
+        autosave::{self, AutosaveOptions},
+        metrics::{self, MetricsOptions, MetricsInterval, MetricsFormat},
+        batch::{BatchSummary, FailedRun, RunOutcome},
+        sweep::{Sweep, SweepSummary},
+        scenario::Scenario,
+        snapshot::{Snapshot, SnapshotError},
+        timer::Date,
+    },
+    economy::product_catalog,
+    config::Config,
+};
+
+// The run crashed or its results couldn't be written. Clap exits with 2 on bad arguments.
+const EXIT_RUN_FAILED: u8 = 1;
+// A config, scenario, product catalog or snapshot couldn't be used.
+const EXIT_BAD_INPUT: u8 = 3;
+
+const EXIT_CODES_HELP: &str = "\
+Exit codes:
+  0  The run finished
+  1  The run crashed, or its results could not be written
+  2  Bad command line arguments
+  3  A config, scenario, product catalog or snapshot could not be used";
+
+// Same order as SPEEDS_ARRAY
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Speed {
+    Slowest,
+    Slow,
+    Slower,
+    Normal,
+    Faster,
+    Fast,
+    Fastest,
+    Max,
+}
+
+/// A simulator of simplified manufacturing economics and energy distribution between industries.
+#[derive(Debug, Parser)]
+#[command(name = "povver", version, after_help = EXIT_CODES_HELP)]
+struct Cli {
+    /// Run without the desktop UI, printing daily progress reports instead
+    #[arg(long)]
+    headless: bool,
+
+    /// Master seed of every random number stream. A random one is picked and printed if not given
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Scenario file with the factories, the Povver Plant and the start date
+    #[arg(long, value_name = "PATH")]
+    scenario: Option<PathBuf>,
+
+    /// Start date in place of the scenario's, like 2025-07-01 or "2025-07-01 12:00"
+    #[arg(long, value_name = "DATE")]
+    start_date: Option<Date>,
+
+    /// Speed to start with. At max speed the simulation goes as fast as the agents can keep up
+    #[arg(long, value_enum, default_value_t = Speed::Slowest)]
+    speed: Speed,
+
+    /// Same as --speed max
+    #[arg(long, conflicts_with = "speed")]
+    max_speed: bool,
+
+    /// Jump over the minutes where nothing happens. Only at max speed, and the results stay the same
+    #[arg(long, requires = "max_speed")]
+    skip_quiet_minutes: bool,
+
+    /// Quit after this many simulated days. Runs until quit if not given
+    #[arg(long, value_name = "DAYS", value_parser = clap::value_parser!(u32).range(1..))]
+    days: Option<u32>,
+
+    /// Directory for the log file and other run outputs. Created if missing
+    #[arg(long, value_name = "DIR")]
+    out_dir: Option<PathBuf>,
+
+    /// Log file of headless runs. Defaults to povver.log in --out-dir, or stdout without one
+    #[arg(long, value_name = "PATH")]
+    log_file: Option<PathBuf>,
+
+    /// Balance config file. Only the keys it changes are needed
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Overrides a single config value on top of the config file. Can be repeated
+    #[arg(long = "set", value_name = "SECTION.KEY=VALUE")]
+    overrides: Vec<String>,
+
+    /// Product catalog file in place of the built-in products
+    #[arg(long, value_name = "PATH")]
+    products: Option<PathBuf>,
+
+    /// Writes the product catalog in use to a file and exits
+    #[arg(long, value_name = "PATH")]
+    export_products: Option<PathBuf>,
+
+    /// Continues the world saved in a snapshot. It brings its own seed, scenario and date
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["seed", "scenario", "start_date", "resume"])]
+    load: Option<PathBuf>,
+
+    /// Saves the world to a snapshot when the run ends
+    #[arg(long, value_name = "PATH")]
+    save: Option<PathBuf>,
+
+    /// Resumes from the latest autosave without asking
+    #[arg(long)]
+    resume: bool,
+
+    /// Simulated days between autosaves. 0 turns autosaving off
+    #[arg(long, value_name = "DAYS", default_value_t = AutosaveOptions::default().every_days)]
+    autosave_days: u32,
+
+    /// Directory of the rotating autosave files [default: autosaves]
+    #[arg(long, value_name = "DIR")]
+    autosave_dir: Option<PathBuf>,
+
+    /// Records metrics over time into this file. Relative paths go in --out-dir
+    #[arg(long, value_name = "PATH")]
+    metrics: Option<PathBuf>,
+
+    /// How often metrics are sampled: hourly or daily
+    #[arg(long, value_name = "INTERVAL", default_value = "hourly", requires = "metrics")]
+    metrics_interval: MetricsInterval,
+
+    /// Metrics file format: csv or jsonl. Goes by the file extension if not given
+    #[arg(long, value_name = "FORMAT", requires = "metrics")]
+    metrics_format: Option<MetricsFormat>,
+
+    /// Writes every clearing round of the energy exchange into this JSON Lines file. Relative paths go in --out-dir
+    #[arg(long, value_name = "PATH")]
+    exchange_log: Option<PathBuf>,
+
+    /// Runs this many headless simulations for --days with consecutive seeds from --seed and sums up how they went.
+    /// Every run gets its own directory in --out-dir, which is "batch" if not given
+    #[arg(
+        long, value_name = "RUNS", value_parser = clap::value_parser!(u32).range(1..),
+        requires = "days", conflicts_with_all = ["load", "save", "resume", "metrics"]
+    )]
+    batch: Option<u32>,
+
+    /// Runs every point of the parameter sweep in a file for --days, with several seeds each, and tabulates how they went.
+    /// Every run gets its own directory in --out-dir, which is "sweep" if not given
+    #[arg(
+        long, value_name = "PATH",
+        requires = "days", conflicts_with_all = ["load", "save", "resume", "metrics", "batch"]
+    )]
+    sweep: Option<PathBuf>,
+
+    /// Simulations of a batch or a sweep that run at the same time. Defaults to the number of CPU cores
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    jobs: Option<u32>,
+
+    // Set on the runs of a batch or a sweep, which keep their console output short.
+    #[arg(long, hide = true)]
+    batch_run: bool,
+}
+
+// Asks on the terminal whether to continue from the autosave. Without a terminal to ask on,
+// it only tells how to resume and starts a new world.
+fn offer_resume(path: &Path, snapshot: &Snapshot, resume: bool) -> bool {
+    println!("POVVER: Found an autosave from {} in {}", snapshot.timer.date, path.display());
+    if resume {
+        return true;
+    }
+    if !io::stdin().is_terminal() {
+        println!("POVVER: Starting a new world. Pass --resume to continue from the autosave.");
+        return false;
+    }
+
+    print!("POVVER: Resume from it? [y/N] ");
+    io::stdout().flush().unwrap();
+    let mut answer = String::new();
+    if io::stdin().lock().read_line(&mut answer).is_err() {
+        return false;
+    }
+
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+// Everything but the run itself. Errors come with the exit code to leave with.
+fn sim_options(cli: &Cli) -> Result<SimOptions, (u8, String)> {
+    let bad_input = |message: String| (EXIT_BAD_INPUT, message);
+
+    let mut options = SimOptions {
+        seed: cli.seed,
+        speed_index: if cli.max_speed { Speed::Max as usize } else { cli.speed as usize },
+        run_days: cli.days,
+        skip_quiet_minutes: cli.skip_quiet_minutes,
+        autosave: (cli.autosave_days > 0).then(|| AutosaveOptions {
+            dir: cli.autosave_dir.clone().unwrap_or(AutosaveOptions::default().dir),
+            every_days: cli.autosave_days,
+            ..AutosaveOptions::default()
+        }),
+        ..SimOptions::default()
+    };
+    if cli.headless {
+        options.run_mode = RunMode::Headless;
+    }
+
+    if let Some(dir) = &cli.out_dir {
+        fs::create_dir_all(dir)
+            .map_err(|e| (EXIT_RUN_FAILED, format!("Could not create the output directory {}: {e}", dir.display())))?;
+    }
+    let log_file = cli.log_file.clone().or_else(|| cli.out_dir.as_ref().map(|dir| dir.join("povver.log")));
+    if let Some(path) = log_file {
+        options.log_output = LogOutput::File(path);
+    }
+    options.metrics = cli.metrics.as_ref().map(|path| {
+        let path = match &cli.out_dir {
+            Some(dir) if path.is_relative() => dir.join(path),
+            _ => path.clone(),
+        };
+        MetricsOptions {
+            interval: cli.metrics_interval,
+            format: cli.metrics_format.unwrap_or_else(|| MetricsFormat::from_path(&path)),
+            path: Some(path),
+        }
+    });
+    options.exchange_log = cli.exchange_log.as_ref().map(|path| match &cli.out_dir {
+        Some(dir) if path.is_relative() => dir.join(path),
+        _ => path.clone(),
+    });
+
+    // Built-in defaults, then the config file, then the --set overrides.
+    options.config = Config::layered(cli.config.as_deref(), &cli.overrides)
+        .map_err(|e| bad_input(format!("Could not set up the config: {e}")))?;
+
+    options.scenario = match &cli.scenario {
+        Some(path) => Scenario::load(path, &options.config)
+            .map_err(|e| bad_input(format!("Could not use the scenario {}: {e}", path.display())))?,
+        None => Scenario::from_config(&options.config),
+    };
+    if let Some(date) = &cli.start_date {
+        options.scenario.start_date = date.clone();
+        options.scenario.validate(&options.config)
+            .map_err(|e| bad_input(format!("Could not start on {date}: {e}")))?;
+    }
+
+    Ok(options)
+}
+
+// Arguments every run of a batch gets. The seed and the output directory come on top for each run.
+fn batch_run_args(cli: &Cli) -> Vec<OsString> {
+    let mut args: Vec<OsString> = [
+        "--batch-run", "--headless", "--max-speed", "--skip-quiet-minutes", "--autosave-days", "0",
+        "--metrics", "metrics.jsonl", "--metrics-interval", "daily",
+    ].map(OsString::from).into();
+    args.extend(["--days".into(), cli.days.unwrap().to_string().into()]);
+
+    let mut pass = |flag: &str, value: OsString| args.extend([flag.into(), value]);
+    if let Some(path) = &cli.config {
+        pass("--config", path.into());
+    }
+    for entry in &cli.overrides {
+        pass("--set", entry.into());
+    }
+    if let Some(path) = &cli.scenario {
+        pass("--scenario", path.into());
+    }
+    if let Some(date) = &cli.start_date {
+        pass("--start-date", date.to_string().into());
+    }
+    if let Some(path) = &cli.products {
+        pass("--products", path.into());
+    }
+
+    args
+}
+
+// One simulation of a batch or a sweep, with the config overrides of its sweep point.
+struct BatchRun {
+    dir: PathBuf,
+    seed: u64,
+    overrides: Vec<String>,
+}
+
+// A run of a batch in a child process of this very program. Its console output goes to a file
+// in its directory, next to its log, its metrics and its final snapshot.
+fn batch_run(program: &Path, args: &[OsString], run: &BatchRun) -> Result<RunOutcome, String> {
+    let dir = &run.dir;
+    fs::create_dir_all(dir).map_err(|e| format!("could not create {}: {e}", dir.display()))?;
+    let console_path = dir.join("console.log");
+    let console = File::create(&console_path).map_err(|e| format!("could not create {}: {e}", console_path.display()))?;
+    let snapshot_path = dir.join("final.json");
+
+    let mut command = Command::new(program);
+    command.args(args);
+    for entry in &run.overrides {
+        command.arg("--set").arg(entry);
+    }
+    let status = command
+        .arg("--seed").arg(run.seed.to_string())
+        .arg("--out-dir").arg(dir)
+        .arg("--save").arg(&snapshot_path)
+        .stdin(Stdio::null())
+        .stdout(console.try_clone().map_err(|e| e.to_string())?)
+        .stderr(console)
+        .status()
+        .map_err(|e| format!("could not start: {e}"))?;
+    if !status.success() {
+        return Err(format!("{status}, see {}", console_path.display()));
+    }
+
+    let snapshot = Snapshot::load(&snapshot_path).map_err(|e| e.to_string())?;
+    let samples = metrics::read_json_lines(&dir.join("metrics.jsonl"))
+        .map_err(|e| format!("could not read the metrics: {e}"))?;
+
+    Ok(RunOutcome::new(&snapshot, &samples))
+}
+
+// Every run is a process of its own, so their console output doesn't get mixed up
+// and a crashing run doesn't take the others down with it. Outcomes come in the order of the runs.
+fn run_in_parallel(cli: &Cli, runs: &[BatchRun]) -> Result<Vec<Result<RunOutcome, String>>, String> {
+    let program = env::current_exe().map_err(|e| format!("Could not find the program to run with: {e}"))?;
+    let jobs = cli.jobs
+        .map(|jobs| jobs as usize)
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |cores| cores.get()))
+        .min(runs.len());
+    println!("POVVER: Running {} simulations of {} days, {jobs} at a time", runs.len(), cli.days.unwrap());
+
+    let args = batch_run_args(cli);
+    let next_run = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            scope.spawn(|| loop {
+                let index = next_run.fetch_add(1, Ordering::Relaxed);
+                let Some(run) = runs.get(index) else {
+                    break;
+                };
+
+                let result = batch_run(&program, &args, run);
+                match &result {
+                    Ok(_) => println!("POVVER: Run in {} with seed {} finished", run.dir.display(), run.seed),
+                    Err(e) => eprintln!("POVVER: Run in {} with seed {} failed: {e}", run.dir.display(), run.seed),
+                }
+                results.lock().unwrap().push((index, result));
+            });
+        }
+    });
+
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(index, _)| *index);
+
+    Ok(results.into_iter().map(|(_, result)| result).collect())
+}
+
+fn create_out_dir(cli: &Cli, default: &str) -> Result<PathBuf, String> {
+    let out_dir = cli.out_dir.clone().unwrap_or_else(|| PathBuf::from(default));
+    fs::create_dir_all(&out_dir)
+        .map_err(|e| format!("Could not create the output directory {}: {e}", out_dir.display()))?;
+
+    Ok(out_dir)
+}
+
+// Writes the table of every run next to the run directories.
+fn write_summary(path: &Path, csv: String) -> bool {
+    match fs::write(path, csv) {
+        Ok(()) => {
+            println!("POVVER: Outcomes of every run written to {}", path.display());
+            true
+        },
+        Err(e) => {
+            eprintln!("POVVER: Could not write {}: {e}", path.display());
+            false
+        }
+    }
+}
+
+fn run_batch(cli: &Cli, runs: u32) -> ExitCode {
+    let out_dir = match create_out_dir(cli, "batch") {
+        Ok(out_dir) => out_dir,
+        Err(message) => {
+            eprintln!("POVVER: {message}");
+            return ExitCode::from(EXIT_RUN_FAILED);
+        }
+    };
+
+    let base_seed = cli.seed.unwrap_or_else(rand::random);
+    println!("POVVER: Batch of {runs} runs with seeds from {base_seed} in {}", out_dir.display());
+    let runs = (0..runs)
+        .map(|run| BatchRun {
+            dir: out_dir.join(format!("run-{run}")),
+            seed: base_seed.wrapping_add(run as u64),
+            overrides: Vec::new(),
+        })
+        .collect::<Vec<_>>();
+
+    let results = match run_in_parallel(cli, &runs) {
+        Ok(results) => results,
+        Err(message) => {
+            eprintln!("POVVER: {message}");
+            return ExitCode::from(EXIT_RUN_FAILED);
+        }
+    };
+    let mut summary = BatchSummary::default();
+    for (run, result) in runs.iter().zip(results) {
+        match result {
+            Ok(outcome) => summary.outcomes.push(outcome),
+            Err(reason) => summary.failed.push(FailedRun { seed: run.seed, reason }),
+        }
+    }
+
+    let written = write_summary(&out_dir.join("summary.csv"), summary.to_csv());
+    println!("\n{}", summary.table());
+
+    if written && summary.failed.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(EXIT_RUN_FAILED)
+    }
+}
+
+// Every point of the sweep runs with the same seeds.
+fn run_sweep(cli: &Cli, path: &Path) -> ExitCode {
+    let sweep = match Sweep::load(path) {
+        Ok(sweep) => sweep,
+        Err(e) => {
+            eprintln!("POVVER: Could not use the sweep {}: {e}", path.display());
+            return ExitCode::from(EXIT_BAD_INPUT);
+        }
+    };
+    let base_seed = cli.seed.unwrap_or_else(rand::random);
+    let points = sweep.points(base_seed);
+
+    // Every point has to make a valid config on top of the config file and the --set overrides.
+    for (index, point) in points.iter().enumerate() {
+        let overrides = cli.overrides.iter().cloned().chain(point.overrides()).collect::<Vec<_>>();
+        if let Err(e) = Config::layered(cli.config.as_deref(), &overrides) {
+            eprintln!("POVVER: Point {index} of the sweep ({}) has no valid config: {e}", point.overrides().join(", "));
+            return ExitCode::from(EXIT_BAD_INPUT);
+        }
+    }
+
+    let out_dir = match create_out_dir(cli, "sweep") {
+        Ok(out_dir) => out_dir,
+        Err(message) => {
+            eprintln!("POVVER: {message}");
+            return ExitCode::from(EXIT_RUN_FAILED);
+        }
+    };
+    println!(
+        "POVVER: Sweep of {} points with {} seeds each from {base_seed} in {}",
+        points.len(), sweep.seeds_per_point, out_dir.display()
+    );
+
+    let mut runs = Vec::new();
+    let mut run_points = Vec::new();
+    for (index, point) in points.iter().enumerate() {
+        for run in 0..sweep.seeds_per_point {
+            runs.push(BatchRun {
+                dir: out_dir.join(format!("point-{index}")).join(format!("run-{run}")),
+                seed: base_seed.wrapping_add(run as u64),
+                overrides: point.overrides(),
+            });
+            run_points.push(index);
+        }
+    }
+
+    let results = match run_in_parallel(cli, &runs) {
+        Ok(results) => results,
+        Err(message) => {
+            eprintln!("POVVER: {message}");
+            return ExitCode::from(EXIT_RUN_FAILED);
+        }
+    };
+    let mut summary = SweepSummary { points, ..SweepSummary::default() };
+    for ((run, point), result) in runs.iter().zip(run_points).zip(results) {
+        match result {
+            Ok(outcome) => summary.outcomes.push((point, outcome)),
+            Err(reason) => summary.failed.push((point, FailedRun { seed: run.seed, reason })),
+        }
+    }
+
+    let written = write_summary(&out_dir.join("sweep.csv"), summary.to_csv());
+    println!("\n{}", summary.table());
+
+    if written && summary.failed.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(EXIT_RUN_FAILED)
+    }
+}
+
+// Results that have nowhere to go fail the run before it starts. Anything else is bad input.
+fn setup_failed(e: &SetupError) -> ExitCode {
+    match e {
+        SetupError::Output(..) => ExitCode::from(EXIT_RUN_FAILED),
+        SetupError::Config(_) | SetupError::Scenario(_) => ExitCode::from(EXIT_BAD_INPUT),
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    // Clap can't tell a 0 from other days, so this one is up to us.
+    if cli.autosave_days == 0 && (cli.resume || cli.autosave_dir.is_some()) {
+        Cli::command().error(
+            ErrorKind::ArgumentConflict,
+            "--resume and --autosave-dir need autosaves, which --autosave-days 0 turns off",
+        ).exit();
+    }
+
+    // The product catalog goes first. Scenarios and snapshots refer to its products.
+    if let Some(path) = &cli.products
+        && let Err(e) = product_catalog::install(path)
+    {
+        eprintln!("POVVER: Could not use the product catalog {}: {e}", path.display());
+        return ExitCode::from(EXIT_BAD_INPUT);
+    }
+    if let Some(path) = &cli.export_products {
+        return match product_catalog::export(path) {
+            Ok(()) => {
+                println!("POVVER: Product catalog written to {}", path.display());
+                ExitCode::SUCCESS
+            },
+            Err(e) => {
+                eprintln!("POVVER: Could not write the product catalog to {}: {e}", path.display());
+                ExitCode::from(EXIT_RUN_FAILED)
+            },
+        };
+    }
+
+    let options = match sim_options(&cli) {
+        Ok(options) => options,
+        Err((code, message)) => {
+            eprintln!("POVVER: {message}");
+            return ExitCode::from(code);
+        }
+    };
+    if let Some(runs) = cli.batch {
+        return run_batch(&cli, runs);
+    }
+    if let Some(path) = &cli.sweep {
+        return run_sweep(&cli, path);
+    }
+
+    let mut sim = match &cli.load {
+        Some(path) => match Simulation::load(options, path) {
+            Ok(sim) => sim,
+            Err(SnapshotError::Setup(e)) => {
+                eprintln!("POVVER: {e}");
+                return setup_failed(&e);
+            }
+            Err(e) => {
+                eprintln!("POVVER: Could not load {}: {e}", path.display());
+                return ExitCode::from(EXIT_BAD_INPUT);
+            }
+        },
+        None => {
+            let latest = options.autosave.as_ref().and_then(|autosave| autosave::latest(&autosave.dir));
+            let sim = match latest {
+                Some((path, snapshot)) if offer_resume(&path, &snapshot, cli.resume) => {
+                    match Simulation::from_snapshot(options.clone(), snapshot) {
+                        Ok(sim) => Ok(sim),
+                        Err(e) => {
+                            eprintln!("POVVER: Could not resume from {}: {e}", path.display());
+                            Simulation::new(options)
+                        }
+                    }
+                },
+                _ => Simulation::new(options),
+            };
+            match sim {
+                Ok(sim) => sim,
+                Err(e) => {
+                    eprintln!("POVVER: {e}");
+                    return setup_failed(&e);
+                }
+            }
+        },
+    };
+
+    // The effective config goes along with the seed, so that the run is documented.
+    if !cli.batch_run {
+        println!("POVVER: Config is\n{}", sim.config().to_toml());
+        println!("POVVER: Master seed is {}", sim.seed());
+    }
+
+    let mut exit_code = ExitCode::SUCCESS;
+    if let Err(e) = sim.run() {
+        eprintln!("POVVER: The run failed, {e}");
+        exit_code = ExitCode::from(EXIT_RUN_FAILED);
+    }
+
+    if let Some(path) = &cli.save {
+        match sim.save(path) {
+            Ok(()) => println!("POVVER: World saved to {}", path.display()),
+            Err(e) => {
+                eprintln!("POVVER: Could not save to {}: {e}", path.display());
+                exit_code = ExitCode::from(EXIT_RUN_FAILED);
+            }
+        }
+    }
+
+    exit_code
 }
@@ -0,0 +1,210 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::simulation::snapshot::{Snapshot, SnapshotError};
+
+const AUTOSAVE_PREFIX: &str = "autosave-";
+const AUTOSAVE_EXTENSION: &str = "json";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AutosaveOptions {
+    // Directory the autosave files are kept in. It's created if it doesn't exist.
+    pub dir: PathBuf,
+    // Simulated days between two autosaves.
+    pub every_days: u32,
+    // Number of files to rotate through. The oldest one is overwritten first.
+    pub slots: usize,
+}
+
+impl Default for AutosaveOptions {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("autosaves"),
+            every_days: 7,
+            slots: 3,
+        }
+    }
+}
+
+// Keeps count of the days and writes snapshots into the rotating autosave files.
+#[derive(Debug)]
+pub struct Autosave {
+    options: AutosaveOptions,
+    days_since_save: u32,
+}
+
+impl Autosave {
+    pub fn new(options: AutosaveOptions) -> Self {
+        Self {
+            options,
+            days_since_save: 0,
+        }
+    }
+
+    // Called on every day change. Returns true when it's time for an autosave.
+    pub fn day_passed(&mut self) -> bool {
+        self.days_since_save += 1;
+        if self.days_since_save >= self.options.every_days.max(1) {
+            self.days_since_save = 0;
+            return true;
+        }
+
+        false
+    }
+
+    // Writes into a temporary file first and renames it over the slot afterwards,
+    // so a crash in the middle of a save never leaves a half written autosave behind.
+    pub fn save(&self, snapshot: &Snapshot) -> Result<PathBuf, SnapshotError> {
+        fs::create_dir_all(&self.options.dir)?;
+
+        let path = self.next_slot();
+        let tmp_path = path.with_extension(format!("{AUTOSAVE_EXTENSION}.tmp"));
+        snapshot.save(&tmp_path)?;
+        fs::rename(&tmp_path, &path)?;
+
+        Ok(path)
+    }
+
+    fn slot_path(&self, slot: usize) -> PathBuf {
+        self.options.dir.join(format!("{AUTOSAVE_PREFIX}{slot}.{AUTOSAVE_EXTENSION}"))
+    }
+
+    // An unused slot if there is one, otherwise the one written longest ago.
+    fn next_slot(&self) -> PathBuf {
+        (0..self.options.slots.max(1))
+            .map(|slot| self.slot_path(slot))
+            .min_by_key(|path| modified_at(path))
+            .unwrap()
+    }
+}
+
+// Missing files count as the oldest.
+fn modified_at(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+// The most recently written autosave in the directory that loads fine.
+// Corrupt, partial or outdated autosaves are reported and skipped.
+pub fn latest(dir: &Path) -> Option<(PathBuf, Snapshot)> {
+    let mut paths = fs::read_dir(dir).ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension().is_some_and(|ext| ext == AUTOSAVE_EXTENSION) &&
+                path.file_name().is_some_and(|name| name.to_string_lossy().starts_with(AUTOSAVE_PREFIX))
+        })
+        .collect::<Vec<PathBuf>>();
+    // Newest first
+    paths.sort_by_key(|path| std::cmp::Reverse(modified_at(path)));
+
+    paths.into_iter().find_map(|path| match Snapshot::load(&path) {
+        Ok(snapshot) => Some((path, snapshot)),
+        Err(e) => {
+            eprintln!("AUTOSAVE: Skipping {}: {e}", path.display());
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, time::Duration};
+    use super::*;
+    use crate::simulation::{Simulation, SimOptions, RunMode, ClockMode, LogOutput};
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("povver-autosave-{name}"));
+        let _ = fs::remove_dir_all(&dir);
+
+        dir
+    }
+
+    fn snapshot(name: &str, seed: u64) -> Snapshot {
+        let sim = Simulation::new(SimOptions {
+            run_mode: RunMode::Headless,
+            log_output: LogOutput::File(std::env::temp_dir().join(format!("povver-autosave-{name}.log"))),
+            seed: Some(seed),
+            clock_mode: ClockMode::Stepped,
+            ..SimOptions::default()
+        }).unwrap();
+
+        sim.snapshot().unwrap()
+    }
+
+    // File times can be too close to tell apart, so every file gets its own minute.
+    fn set_age(path: &Path, minutes_ago: u64) {
+        let modified = SystemTime::now() - Duration::from_secs(minutes_ago * 60);
+        File::options().write(true).open(path).unwrap().set_modified(modified).unwrap();
+    }
+
+    fn files_in(dir: &Path) -> Vec<String> {
+        let mut names = fs::read_dir(dir).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        names.sort();
+
+        names
+    }
+
+    #[test]
+    fn saves_every_few_days() {
+        let mut autosave = Autosave::new(AutosaveOptions { every_days: 3, ..AutosaveOptions::default() });
+        let due = (0..7).map(|_| autosave.day_passed()).collect::<Vec<_>>();
+
+        assert_eq!(due, vec![false, false, true, false, false, true, false]);
+    }
+
+    #[test]
+    fn oldest_slot_is_overwritten() {
+        let dir = test_dir("slots");
+        let autosave = Autosave::new(AutosaveOptions { dir: dir.clone(), every_days: 1, slots: 2 });
+
+        for (seed, minutes_ago) in [(1, 30), (2, 20)] {
+            let path = autosave.save(&snapshot("slots", seed)).unwrap();
+            set_age(&path, minutes_ago);
+        }
+        // Both slots are taken, so the third save replaces the first one.
+        let path = autosave.save(&snapshot("slots", 3)).unwrap();
+        assert_eq!(path, dir.join("autosave-0.json"));
+
+        assert_eq!(files_in(&dir), vec!["autosave-0.json", "autosave-1.json"]);
+        let (latest_path, latest) = latest(&dir).unwrap();
+        assert_eq!((latest_path, latest.seed), (path, 3));
+        assert_eq!(Snapshot::load(&dir.join("autosave-1.json")).unwrap().seed, 2);
+    }
+
+    #[test]
+    fn corrupt_newest_autosave_is_skipped() {
+        let dir = test_dir("corrupt");
+        let autosave = Autosave::new(AutosaveOptions { dir: dir.clone(), every_days: 1, slots: 4 });
+
+        let good = autosave.save(&snapshot("corrupt", 7)).unwrap();
+        set_age(&good, 40);
+
+        let half_written = dir.join("autosave-1.json");
+        fs::write(&half_written, r#"{"version": 10, "seed": 7, "timer": {"#).unwrap();
+        set_age(&half_written, 10);
+
+        let outdated = dir.join("autosave-2.json");
+        fs::write(&outdated, r#"{"version": 1}"#).unwrap();
+        set_age(&outdated, 20);
+
+        // Other files in the directory are none of our business.
+        fs::write(dir.join("notes.json"), "not a snapshot").unwrap();
+
+        let (path, snapshot) = latest(&dir).unwrap();
+        assert_eq!((path, snapshot.seed), (good, 7));
+    }
+
+    #[test]
+    fn nothing_to_resume_without_a_good_autosave() {
+        let dir = test_dir("none");
+        assert!(latest(&dir).is_none());
+
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("autosave-0.json"), "garbage").unwrap();
+        assert!(latest(&dir).is_none());
+    }
+}
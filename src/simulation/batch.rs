@@ -0,0 +1,146 @@
+use std::fmt::Write;
+use serde::Serialize;
+
+use crate::simulation::{
+    SimFlo,
+    metrics::MetricsSample,
+    snapshot::Snapshot,
+};
+
+// What a single run of a batch came to, read from its final snapshot and its metrics.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RunOutcome {
+    pub seed: u64,
+    pub factories: usize,
+    pub bankrupt_factories: usize,
+    pub pp_bankrupt: bool,
+    pub pp_balance: SimFlo,
+    pub factory_balance_mean: SimFlo,
+    // Percent of the factories' energy that came from their own renewables.
+    pub renewable_share: SimFlo,
+    // Averaged over the metrics samples. The final fuel price without any.
+    pub avg_fuel_price: SimFlo,
+    // Percent of the demanded product units that were sold.
+    pub demand_fulfilment: SimFlo,
+}
+
+impl RunOutcome {
+    pub fn new(snapshot: &Snapshot, samples: &[MetricsSample]) -> Self {
+        let factories = snapshot.factories.iter().map(|entry| &entry.state).collect::<Vec<_>>();
+
+        let factory_balance_mean = if factories.is_empty() {
+            0.0
+        } else {
+            factories.iter().map(|factory| factory.balance.val()).sum::<SimFlo>() / factories.len() as SimFlo
+        };
+
+        let renewable_energy = factories.iter().map(|factory| factory.energy_from_solar_total + factory.energy_from_wind_total).sum::<u64>();
+        let pp_energy = factories.iter().map(|factory| factory.energy_from_pp_total).sum::<u64>();
+        let renewable_share = percent_of(renewable_energy, renewable_energy + pp_energy);
+
+        let avg_fuel_price = if samples.is_empty() {
+            snapshot.economy.fuel_price.val()
+        } else {
+            samples.iter().map(|sample| sample.fuel_price).sum::<SimFlo>() / samples.len() as SimFlo
+        };
+
+        Self {
+            seed: snapshot.seed,
+            factories: factories.len(),
+            bankrupt_factories: factories.iter().filter(|factory| factory.is_bankrupt).count(),
+            pp_bankrupt: snapshot.povver_plant.is_bankrupt,
+            pp_balance: snapshot.povver_plant.balance.val(),
+            factory_balance_mean,
+            renewable_share,
+            avg_fuel_price,
+            demand_fulfilment: percent_of(snapshot.economy.sold_units_total, snapshot.economy.demanded_units_total),
+        }
+    }
+
+    pub const CSV_HEADER: &str = "seed,factories,bankrupt_factories,pp_bankrupt,pp_balance,factory_balance_mean,renewable_share,avg_fuel_price,demand_fulfilment";
+
+    pub fn csv_row(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{},{}",
+            self.seed, self.factories, self.bankrupt_factories, self.pp_bankrupt as u8, self.pp_balance,
+            self.factory_balance_mean, self.renewable_share, self.avg_fuel_price, self.demand_fulfilment
+        )
+    }
+}
+
+// Picks one of the outcomes of a run for the summary table.
+pub type OutcomeValue = fn(&RunOutcome) -> f64;
+
+fn percent_of(part: u64, whole: u64) -> SimFlo {
+    if whole == 0 {
+        0.0
+    } else {
+        (part as f64 / whole as f64 * 100.0) as SimFlo
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FailedRun {
+    pub seed: u64,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BatchSummary {
+    pub outcomes: Vec<RunOutcome>,
+    pub failed: Vec<FailedRun>,
+}
+
+impl BatchSummary {
+    // One row per finished run.
+    pub fn to_csv(&self) -> String {
+        let mut csv = format!("{}\n", RunOutcome::CSV_HEADER);
+        for outcome in &self.outcomes {
+            csv += &outcome.csv_row();
+            csv.push('\n');
+        }
+
+        csv
+    }
+
+    // The spread of every outcome across the runs.
+    pub fn table(&self) -> String {
+        let columns: [(&str, OutcomeValue); 7] = [
+            ("bankrupt factories", |outcome| outcome.bankrupt_factories as f64),
+            ("pp bankrupt (share)", |outcome| outcome.pp_bankrupt as u8 as f64),
+            ("pp balance", |outcome| outcome.pp_balance as f64),
+            ("factory balance mean", |outcome| outcome.factory_balance_mean as f64),
+            ("renewable share %", |outcome| outcome.renewable_share as f64),
+            ("avg fuel price", |outcome| outcome.avg_fuel_price as f64),
+            ("demand fulfilment %", |outcome| outcome.demand_fulfilment as f64),
+        ];
+
+        let mut table = format!("{} runs finished, {} failed\n", self.outcomes.len(), self.failed.len());
+        let _ = writeln!(table, "{:<22}{:>14}{:>14}{:>14}{:>14}{:>14}", "", "mean", "std dev", "min", "median", "max");
+        for (name, value) in columns {
+            let mut values = self.outcomes.iter().map(value).collect::<Vec<_>>();
+            if values.is_empty() {
+                continue;
+            }
+            values.sort_by(|a, b| a.total_cmp(b));
+
+            let mean = values.iter().sum::<f64>() / values.len() as f64;
+            let variance = values.iter().map(|value| (value - mean).powi(2)).sum::<f64>() / values.len() as f64;
+            let median = if values.len() % 2 == 0 {
+                (values[values.len() / 2 - 1] + values[values.len() / 2]) / 2.0
+            } else {
+                values[values.len() / 2]
+            };
+
+            let _ = writeln!(
+                table, "{:<22}{:>14.2}{:>14.2}{:>14.2}{:>14.2}{:>14.2}",
+                name, mean, variance.sqrt(), values[0], median, values[values.len() - 1]
+            );
+        }
+        for failed in &self.failed {
+            let _ = writeln!(table, "Run with seed {} failed: {}", failed.seed, failed.reason);
+        }
+
+        table
+    }
+}
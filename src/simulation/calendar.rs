@@ -0,0 +1,238 @@
+use std::fmt;
+use serde::{Serialize, Deserialize};
+
+use crate::simulation::{
+    SimInt,
+    sim_constants::CALENDAR_LEAP_YEARS,
+    timer::Date,
+};
+
+const MINUTES_PER_DAY: u128 = 24 * 60;
+const MONTH_DAYS: [SimInt; 12] = [31, 28, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl fmt::Display for Weekday {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+// Gregorian calendar counted from 0000-01-01 00:00. Without leap years every February has 28 days.
+// Timestamps are minutes since then, the same ones the timer ticks and the hub jobs are due on.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Calendar {
+    pub leap_years: bool,
+}
+
+impl Default for Calendar {
+    fn default() -> Self {
+        Self {
+            leap_years: CALENDAR_LEAP_YEARS,
+        }
+    }
+}
+
+impl Calendar {
+    pub fn is_leap_year(&self, year: SimInt) -> bool {
+        self.leap_years && year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+    }
+
+    pub fn days_in_year(&self, year: SimInt) -> SimInt {
+        if self.is_leap_year(year) { 366 } else { 365 }
+    }
+
+    pub fn days_in_month(&self, year: SimInt, month: SimInt) -> SimInt {
+        if month == 2 && self.is_leap_year(year) {
+            29
+        } else {
+            MONTH_DAYS[(month - 1) as usize]
+        }
+    }
+
+    fn days_before_year(&self, year: SimInt) -> u128 {
+        let year = year as u128;
+        // Year 0 is a leap year too
+        let leap_days = if self.leap_years && year > 0 {
+            (year - 1) / 4 - (year - 1) / 100 + (year - 1) / 400 + 1
+        } else {
+            0
+        };
+
+        year * 365 + leap_days
+    }
+
+    fn days_before(&self, date: &Date) -> u128 {
+        let days_before_month = (1..date.month)
+            .map(|month| self.days_in_month(date.year, month) as u128)
+            .sum::<u128>();
+
+        self.days_before_year(date.year) + days_before_month + (date.day - 1) as u128
+    }
+
+    // Everything that is wrong with the date, so it can be reported at once.
+    pub fn check(&self, date: &Date) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if !(0..60).contains(&date.minute) {
+            problems.push(format!("minute {} is not between 0 and 59", date.minute));
+        }
+        if !(0..24).contains(&date.hour) {
+            problems.push(format!("hour {} is not between 0 and 23", date.hour));
+        }
+        if !(1..=12).contains(&date.month) {
+            problems.push(format!("month {} is not between 1 and 12", date.month));
+        } else if date.year >= 0 {
+            let days = self.days_in_month(date.year, date.month);
+            if !(1..=days).contains(&date.day) {
+                problems.push(format!("day {} is not between 1 and {days} in {:04}-{:02}", date.day, date.year, date.month));
+            }
+        }
+        if date.year < 0 {
+            problems.push(format!("year {} is negative", date.year));
+        }
+
+        problems
+    }
+
+    // The date must pass `check`.
+    pub fn to_timestamp(&self, date: &Date) -> u128 {
+        self.days_before(date) * MINUTES_PER_DAY + (date.hour * 60 + date.minute) as u128
+    }
+
+    pub fn from_timestamp(&self, timestamp: u128) -> Date {
+        let mut days = timestamp / MINUTES_PER_DAY;
+        let minute_of_day = (timestamp % MINUTES_PER_DAY) as SimInt;
+
+        // No year is longer than 366 days, so this is never past the right one.
+        let mut year = (days / 366) as SimInt;
+        while self.days_before_year(year + 1) <= days {
+            year += 1;
+        }
+        days -= self.days_before_year(year);
+
+        let mut month = 1;
+        while days >= self.days_in_month(year, month) as u128 {
+            days -= self.days_in_month(year, month) as u128;
+            month += 1;
+        }
+
+        Date {
+            minute: minute_of_day % 60,
+            hour: minute_of_day / 60,
+            day: days as SimInt + 1,
+            month,
+            year,
+        }
+    }
+
+    // Negative minutes go back in time. It stops at the start of the calendar.
+    pub fn add_minutes(&self, date: &Date, minutes: i128) -> Date {
+        let timestamp = (self.to_timestamp(date) as i128 + minutes).max(0);
+
+        self.from_timestamp(timestamp as u128)
+    }
+
+    pub fn add_days(&self, date: &Date, days: i64) -> Date {
+        self.add_minutes(date, days as i128 * MINUTES_PER_DAY as i128)
+    }
+
+    // Minutes from `from` to `to`, negative if `to` is earlier.
+    pub fn minutes_between(&self, from: &Date, to: &Date) -> i128 {
+        self.to_timestamp(to) as i128 - self.to_timestamp(from) as i128
+    }
+
+    // Whole days from `from` to `to`, counting calendar days regardless of the time of day.
+    pub fn days_between(&self, from: &Date, to: &Date) -> i64 {
+        (self.days_before(to) as i128 - self.days_before(from) as i128) as i64
+    }
+
+    pub fn weekday(&self, date: &Date) -> Weekday {
+        // 0000-01-01 is a Saturday in the Gregorian calendar.
+        match (self.days_before(date) + 5) % 7 {
+            0 => Weekday::Monday,
+            1 => Weekday::Tuesday,
+            2 => Weekday::Wednesday,
+            3 => Weekday::Thursday,
+            4 => Weekday::Friday,
+            5 => Weekday::Saturday,
+            _ => Weekday::Sunday,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> Date {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn timestamps_round_trip() {
+        let calendar = Calendar::default();
+        for s in ["0000-01-01", "1900-02-28 23:59", "1900-03-01", "2000-01-01", "2024-02-29 12:30", "2024-12-31 23:59", "2025-07-01"] {
+            assert_eq!(calendar.from_timestamp(calendar.to_timestamp(&date(s))), date(s), "{s}");
+        }
+    }
+
+    #[test]
+    fn leap_years() {
+        let calendar = Calendar::default();
+        assert!(calendar.is_leap_year(2024));
+        assert!(calendar.is_leap_year(2000));
+        assert!(!calendar.is_leap_year(1900));
+        assert!(!calendar.is_leap_year(2025));
+        assert_eq!(calendar.days_in_month(2024, 2), 29);
+        assert_eq!(calendar.days_in_month(1900, 2), 28);
+        assert!(calendar.check(&date("2024-02-29")).is_empty());
+        assert!(!calendar.check(&date("1900-02-29")).is_empty());
+
+        let no_leaps = Calendar { leap_years: false };
+        assert_eq!(no_leaps.days_in_month(2024, 2), 28);
+        assert_eq!(no_leaps.days_in_year(2000), 365);
+        assert_eq!(no_leaps.add_days(&date("2024-02-28"), 1), date("2024-03-01"));
+    }
+
+    #[test]
+    fn weekdays() {
+        let calendar = Calendar::default();
+        assert_eq!(calendar.weekday(&date("2024-02-29")), Weekday::Thursday);
+        assert_eq!(calendar.weekday(&date("1900-03-01")), Weekday::Thursday);
+        assert_eq!(calendar.weekday(&date("2000-01-01")), Weekday::Saturday);
+        assert_eq!(calendar.weekday(&date("2024-12-31")), Weekday::Tuesday);
+        assert_eq!(calendar.weekday(&date("2025-01-01")), Weekday::Wednesday);
+    }
+
+    #[test]
+    fn december_rolls_over_into_january() {
+        let calendar = Calendar::default();
+        assert_eq!(calendar.add_minutes(&date("2024-12-31 23:59"), 1), date("2025-01-01"));
+        assert_eq!(calendar.add_days(&date("2024-12-31 08:15"), 1), date("2025-01-01 08:15"));
+        assert_eq!(calendar.add_days(&date("2025-01-01"), -1), date("2024-12-31"));
+    }
+
+    #[test]
+    fn adding_and_counting_days() {
+        let calendar = Calendar::default();
+        assert_eq!(calendar.add_days(&date("2024-02-28"), 1), date("2024-02-29"));
+        assert_eq!(calendar.add_days(&date("1900-02-28"), 1), date("1900-03-01"));
+        assert_eq!(calendar.add_days(&date("2024-02-29"), 365), date("2025-02-28"));
+        assert_eq!(calendar.days_between(&date("2024-02-28"), &date("2025-03-01")), 367);
+        assert_eq!(calendar.days_between(&date("1900-02-28 23:00"), &date("1900-03-01 01:00")), 1);
+        assert_eq!(calendar.days_between(&date("2025-03-01"), &date("2024-02-28")), -367);
+        assert_eq!(calendar.minutes_between(&date("2024-12-31 23:00"), &date("2025-01-01 01:00")), 120);
+        assert_eq!(calendar.add_days(&date("0000-01-01"), -1), date("0000-01-01"));
+    }
+}
@@ -0,0 +1,275 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+};
+use serde::{Serialize, Deserialize};
+
+use crate::simulation::{SimFlo, SimInt};
+
+// Who is behind an order. Only factories bid, the Povver Plant and factories with spare energy ask.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Trader {
+    PovverPlant,
+    Factory(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EnergyOrder {
+    pub trader: Trader,
+    // The most a buyer pays, or the least a seller takes, for a unit of energy.
+    pub price_per_unit: SimFlo,
+    pub units: SimInt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EnergyTrade {
+    pub buyer: Trader,
+    pub seller: Trader,
+    pub units: SimInt,
+}
+
+// A clearing round with the order book as it was. Every trade of the round goes for the clearing price,
+// which is None if no bid met an ask.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClearingRound {
+    pub timestamp: u128,
+    pub date: String,
+    pub bids: Vec<EnergyOrder>,
+    pub asks: Vec<EnergyOrder>,
+    pub clearing_price: Option<SimFlo>,
+    pub trades: Vec<EnergyTrade>,
+}
+
+impl ClearingRound {
+    pub fn volume(&self) -> SimInt {
+        self.trades.iter().map(|trade| trade.units).sum()
+    }
+}
+
+// Orders that came in since the last clearing round.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EnergyExchange {
+    bids: Vec<EnergyOrder>,
+    asks: Vec<EnergyOrder>,
+}
+
+impl EnergyExchange {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // A trader has one order in a round. A new one takes the place of the old,
+    // so nobody ends up trading with themselves.
+    pub fn bid(&mut self, order: EnergyOrder) {
+        self.withdraw(order.trader);
+        self.bids.push(order);
+    }
+
+    pub fn ask(&mut self, order: EnergyOrder) {
+        self.withdraw(order.trader);
+        self.asks.push(order);
+    }
+
+    pub fn withdraw(&mut self, trader: Trader) {
+        self.bids.retain(|order| order.trader != trader);
+        self.asks.retain(|order| order.trader != trader);
+    }
+
+    // Matches the highest bids with the lowest asks for as long as the bid pays at least what the ask wants.
+    // The clearing price is halfway between the last bid and ask that were matched, so it suits every trade.
+    // Orders that came in first go first at the same price. The book is empty afterwards.
+    pub fn clear(&mut self, timestamp: u128, date: String) -> ClearingRound {
+        let bids = std::mem::take(&mut self.bids);
+        let asks = std::mem::take(&mut self.asks);
+
+        let mut bid_queue = bids.iter().filter(|order| order.units > 0).copied().collect::<Vec<_>>();
+        bid_queue.sort_by(|a, b| b.price_per_unit.total_cmp(&a.price_per_unit));
+        let mut ask_queue = asks.iter().filter(|order| order.units > 0).copied().collect::<Vec<_>>();
+        ask_queue.sort_by(|a, b| a.price_per_unit.total_cmp(&b.price_per_unit));
+
+        let mut trades = Vec::new();
+        let mut marginal_prices = None;
+        let (mut b, mut a) = (0, 0);
+        while b < bid_queue.len() && a < ask_queue.len() && bid_queue[b].price_per_unit >= ask_queue[a].price_per_unit {
+            let (bid, ask) = (&mut bid_queue[b], &mut ask_queue[a]);
+            let units = bid.units.min(ask.units);
+            trades.push(EnergyTrade {
+                buyer: bid.trader,
+                seller: ask.trader,
+                units,
+            });
+            marginal_prices = Some((bid.price_per_unit, ask.price_per_unit));
+
+            bid.units -= units;
+            ask.units -= units;
+            if bid.units == 0 {
+                b += 1;
+            }
+            if ask.units == 0 {
+                a += 1;
+            }
+        }
+
+        ClearingRound {
+            timestamp,
+            date,
+            bids,
+            asks,
+            clearing_price: marginal_prices.map(|(bid_price, ask_price)| (bid_price + ask_price) / 2.0),
+            trades,
+        }
+    }
+}
+
+// As many clearing rounds as a year has hours are kept in memory, the log file gets them all.
+const KEPT_ROUNDS: usize = 24 * 366;
+
+// Keeps the latest clearing rounds. With a path every round is also written out as JSON Lines as it comes.
+pub struct ExchangeRecorder {
+    path: Option<PathBuf>,
+    rounds: Vec<ClearingRound>,
+    writer: Option<BufWriter<File>>,
+}
+
+impl ExchangeRecorder {
+    pub fn new(path: Option<PathBuf>) -> io::Result<Self> {
+        let writer = match &path {
+            Some(path) => Some(BufWriter::new(File::create(path)?)),
+            None => None,
+        };
+
+        Ok(Self {
+            path,
+            rounds: Vec::new(),
+            writer,
+        })
+    }
+
+    pub fn record(&mut self, round: ClearingRound) {
+        if let Err(e) = self.write(&round) {
+            eprintln!("EXCHANGE: Could not write to {}: {e}. Keeping the rounds in memory only.", self.path_display());
+            self.writer = None;
+        }
+        if self.rounds.len() >= KEPT_ROUNDS {
+            self.rounds.remove(0);
+        }
+        self.rounds.push(round);
+    }
+
+    fn write(&mut self, round: &ClearingRound) -> io::Result<()> {
+        let Some(writer) = self.writer.as_mut() else {
+            return Ok(());
+        };
+
+        serde_json::to_writer(&mut *writer, round)?;
+        writeln!(writer)
+    }
+
+    pub fn flush(&mut self) {
+        if let Some(writer) = self.writer.as_mut()
+            && let Err(e) = writer.flush()
+        {
+            eprintln!("EXCHANGE: Could not write to {}: {e}", self.path_display());
+        }
+    }
+
+    pub fn rounds(&self) -> &[ClearingRound] {
+        &self.rounds
+    }
+
+    fn path_display(&self) -> String {
+        self.path.as_ref().map(|path| path.display().to_string()).unwrap_or_default()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PP: Trader = Trader::PovverPlant;
+    const F1: Trader = Trader::Factory(1);
+    const F2: Trader = Trader::Factory(2);
+    const F3: Trader = Trader::Factory(3);
+    const F4: Trader = Trader::Factory(4);
+
+    fn order(trader: Trader, price_per_unit: SimFlo, units: SimInt) -> EnergyOrder {
+        EnergyOrder { trader, price_per_unit, units }
+    }
+
+    fn trade(buyer: Trader, seller: Trader, units: SimInt) -> EnergyTrade {
+        EnergyTrade { buyer, seller, units }
+    }
+
+    fn clear(bids: &[EnergyOrder], asks: &[EnergyOrder]) -> ClearingRound {
+        let mut exchange = EnergyExchange::new();
+        bids.iter().for_each(|bid| exchange.bid(*bid));
+        asks.iter().for_each(|ask| exchange.ask(*ask));
+        exchange.clear(0, "0000-01-01 00:00".to_string())
+    }
+
+    #[test]
+    fn partial_fills_leave_the_rest_unmatched() {
+        // The bid takes all of the cheap ask and part of the pricier one.
+        let round = clear(&[order(F1, 10.0, 150)], &[order(F2, 4.0, 100), order(PP, 8.0, 200)]);
+        assert_eq!(round.trades, vec![trade(F1, F2, 100), trade(F1, PP, 50)]);
+        assert_eq!(round.volume(), 150);
+
+        // The ask is split over two bids and still has some left.
+        let round = clear(&[order(F1, 10.0, 30), order(F2, 9.0, 40)], &[order(PP, 5.0, 100)]);
+        assert_eq!(round.trades, vec![trade(F1, PP, 30), trade(F2, PP, 40)]);
+        assert_eq!(round.volume(), 70);
+    }
+
+    #[test]
+    fn every_trade_goes_for_the_price_of_the_last_match() {
+        // F2's bid is below F4's ask, so matching ends at F2's bid against PP's ask.
+        let round = clear(
+            &[order(F1, 12.0, 50), order(F2, 9.0, 50), order(F3, 5.0, 50)],
+            &[order(PP, 6.0, 80), order(F4, 10.0, 50)],
+        );
+        assert_eq!(round.trades, vec![trade(F1, PP, 50), trade(F2, PP, 30)]);
+        assert_eq!(round.clearing_price, Some((9.0 + 6.0) / 2.0));
+    }
+
+    #[test]
+    fn no_price_when_no_bid_meets_an_ask() {
+        let round = clear(&[order(F1, 5.0, 100), order(F2, 7.0, 100)], &[order(PP, 8.0, 100)]);
+        assert!(round.trades.is_empty());
+        assert_eq!(round.clearing_price, None);
+        assert_eq!(round.volume(), 0);
+
+        let round = clear(&[order(F1, 5.0, 100)], &[]);
+        assert!(round.trades.is_empty());
+        assert_eq!(round.clearing_price, None);
+    }
+
+    #[test]
+    fn ties_go_to_the_first_order() {
+        let round = clear(&[order(F2, 10.0, 60), order(F1, 10.0, 60)], &[order(PP, 10.0, 100)]);
+        assert_eq!(round.trades, vec![trade(F2, PP, 60), trade(F1, PP, 40)]);
+        assert_eq!(round.clearing_price, Some(10.0));
+
+        let round = clear(&[order(F3, 8.0, 100)], &[order(F2, 6.0, 70), order(F1, 6.0, 70)]);
+        assert_eq!(round.trades, vec![trade(F3, F2, 70), trade(F3, F1, 30)]);
+    }
+
+    #[test]
+    fn a_new_order_replaces_the_old_one() {
+        let mut exchange = EnergyExchange::new();
+        exchange.bid(order(F1, 10.0, 100));
+        exchange.ask(order(PP, 4.0, 100));
+        // F1 has spare energy now and asks instead, so it can't trade with itself.
+        exchange.ask(order(F1, 3.0, 20));
+        exchange.bid(order(F2, 5.0, 50));
+
+        let round = exchange.clear(0, "0000-01-01 00:00".to_string());
+        assert_eq!(round.bids, vec![order(F2, 5.0, 50)]);
+        assert_eq!(round.trades, vec![trade(F2, F1, 20), trade(F2, PP, 30)]);
+        assert_eq!(round.clearing_price, Some((5.0 + 4.0) / 2.0));
+
+        // The book is empty after clearing.
+        assert!(exchange.clear(0, "0000-01-01 00:00".to_string()).trades.is_empty());
+    }
+}
@@ -1,26 +1,32 @@
 use std::{
+    collections::BTreeMap,
     sync::{Arc, Mutex, RwLock},
     thread,
 };
 use std::time::Duration;
+use serde::{Serialize, Deserialize};
 use tokio::sync::broadcast as tokio_broadcast;
 
 use crate::{
     app_state::{PovverPlantStateData, FactoryStateData, HubState, EconomyStateData, TimerStateData, EnvStateData},
     economy::{
-        povver_plant::PovverPlant,
-        factory::Factory,
-        economy_types::{Money, EnergyUnit},
+        povver_plant::{PovverPlant, PPLinks},
+        factory::{Factory, FactoryLinks},
     },
     simulation::{
-        SimFlo,
         hub_jobs::*,
-        sim_constants::*,
         hub_comms::*,
+        scheduler::{Scheduler, SchedulerSnapshot},
+        exchange::{EnergyExchange, EnergyOrder, ExchangeRecorder, Trader},
         StateAction,
+        ClockMode,
         speed::Speed,
+        stepper::{Stepper, StepTurn},
+        scenario::{Scenario, FactoryStrategy},
     },
     utils_data::ReadOnlyRwLock,
+    utils_random::{SeedBank, RngStream},
+    config::Config,
     logger::{
         Logger,
         LogLevel::*,
@@ -28,8 +34,7 @@ use crate::{
     },
 };
 
-use crate::simulation::test_factories::{get_test_factories, TEST_INDUSTRIES};
-use crate::simulation::TickDuration;
+use crate::simulation::{SimInt, TickDuration};
 
 pub struct TheHub {
     pub povver_plant: Arc<Mutex<PovverPlant>>,
@@ -39,93 +44,130 @@ pub struct TheHub {
     pub econ_state: Arc<RwLock<EconomyStateData>>,
     pub timer_state_ro: ReadOnlyRwLock<TimerStateData>,
     pub env_state_ro: ReadOnlyRwLock<EnvStateData>,
-    pub minutely_jobs: Vec<MinutelyJob>,
-    pub hourly_jobs: Vec<HourlyJob>,
-    pub daily_jobs: Vec<DailyJob>,
+    pub scheduler: Scheduler<HubJob>,
+    pub exchange: EnergyExchange,
+    pub exchange_recorder: ExchangeRecorder,
     pub ui_log_sender: tokio_broadcast::Sender<LogMessage>,
     pub comms: HubComms,
+    pub config: Arc<Config>,
+    seeds: SeedBank,
+    pp_postmark: Arc<Postmark>,
+    // Threads of the factories by factory id, once the hub has started.
+    factory_threads: BTreeMap<usize, thread::JoinHandle<()>>,
+    // Names of the Povver Plant and factory threads that panicked, found out while joining them.
+    pub crashed_threads: Vec<String>,
+    next_factory_id: usize,
+    is_started: bool,
+    tick_duration: TickDuration,
     sleeptime: Duration,
+    clock_mode: ClockMode,
+    // Handed over to the thread when it starts, so the turn is gone along with the thread.
+    step_turn: Option<StepTurn>,
+}
+
+// Jobs the hub has scheduled but not done yet, and the orders waiting for the next clearing round.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HubSnapshot {
+    scheduler: SchedulerSnapshot<HubJob>,
+    exchange: EnergyExchange,
+    next_factory_id: usize,
+}
+
+// What is left of a factory taken out of the simulation.
+pub struct RetiredFactory {
+    pub state: FactoryStateData,
+    // Only there if the hub had started. It ends once the factory gets the retirement signal.
+    pub thread: Option<thread::JoinHandle<()>>,
+    // The factory's turn in stepped mode, to be taken off the stepper.
+    pub step_turn_id: usize,
+}
+
+// The world state the hub works on and its line to the UI log.
+pub struct HubLinks {
+    pub econ_state: Arc<RwLock<EconomyStateData>>,
+    pub timer_state_ro: ReadOnlyRwLock<TimerStateData>,
+    pub env_state_ro: ReadOnlyRwLock<EnvStateData>,
+    pub ui_log_sender: tokio_broadcast::Sender<LogMessage>,
 }
 
 impl TheHub {
     pub fn new(
-        econ_state: Arc<RwLock<EconomyStateData>>,
-        timer_state_ro: ReadOnlyRwLock<TimerStateData>,
-        env_state_ro: ReadOnlyRwLock<EnvStateData>,
-        ui_log_sender: tokio_broadcast::Sender<LogMessage>,
+        links: HubLinks,
+        seeds: &SeedBank,
+        scenario: &Scenario,
+        exchange_recorder: ExchangeRecorder,
+        config: Arc<Config>,
+        clock_mode: ClockMode,
+        stepper: &mut Stepper,
     ) -> (Self, HubState) {
-        // TODO: Test factories for now (from test-factories.rs). Maybe switch to user defined in the future.
-        let comms = HubComms::new(TEST_INDUSTRIES.len());
-
-        let povver_plant_state = Arc::new(RwLock::new(PovverPlantStateData {
-            fuel: PP_INIT_FUEL_CAPACITY,
-            fuel_capacity: PP_INIT_FUEL_CAPACITY,
-            production_capacity: EnergyUnit::new(PP_INIT_PRODUCTION_CAP),
-            balance: Money::new(PP_INIT_MONEY - (econ_state.read().unwrap().fuel_price.val() * PP_INIT_FUEL_CAPACITY as SimFlo)),
-            is_awaiting_fuel: false,
-            is_awaiting_fuel_capacity: false,
-            is_awaiting_production_capacity: false,
-            is_bankrupt: false,
-        }));
-
-        // TODO: Test factories for now (from test-factories.rs). Maybe switch to user defined in the future.
-        let factories_state = get_test_factories();
-
-        let (factories, to_factory_senders) = {
-            let mut to_factory_senders = Vec::new();
-            let factories = Arc::new(Mutex::new(
-                factories_state
-                    .iter()
-                    .enumerate()
-                    .map(|(id, f)| {
-                        to_factory_senders.push(comms.clone_to_factory_dyn_sender(id));
-                        Arc::new(Mutex::new(
-                            Factory::new(
-                                ReadOnlyRwLock::from(Arc::clone(f)),
-                                ReadOnlyRwLock::from(Arc::clone(&econ_state)),
-                                ReadOnlyRwLock::clone(&timer_state_ro),
-                                ui_log_sender.clone(),
-                                comms.clone_broadcast_state_receiver(),
-                                comms.clone_broadcast_signal_receiver(),
-                                comms.clone_from_factory_dyn_sender(id),
-                                comms.clone_to_factory_dyn_receiver(id),
-                            )
-                        ))
-                    }).collect()
-            ));
-            (factories, to_factory_senders)
-        };
+        let HubLinks { econ_state, timer_state_ro, env_state_ro, ui_log_sender } = links;
+        let comms = HubComms::new(ReadOnlyRwLock::clone(&timer_state_ro));
 
-        let factories_state = Arc::new(RwLock::new(factories_state));
+        // Turn order in stepped mode: the hub, the Povver Plant and then the factories by id.
+        let step_turn = stepper.add_actor();
+        let pp_step_turn = stepper.add_actor();
 
+        let povver_plant_state = Arc::new(RwLock::new(
+            scenario.povver_plant_state(&config, econ_state.read().unwrap().fuel_price.val())
+        ));
+
+        // Factories connect to the Povver Plant as they are added.
+        let pp_postmark = Postmark::new(MessageEntity::PP, ReadOnlyRwLock::clone(&timer_state_ro));
+        let pp_links = PPLinks {
+            state_ro: ReadOnlyRwLock::from(Arc::clone(&povver_plant_state)),
+            econ_state_ro: ReadOnlyRwLock::from(Arc::clone(&econ_state)),
+            timer_state_ro: ReadOnlyRwLock::clone(&timer_state_ro),
+            ui_log_sender: ui_log_sender.clone(),
+            wakeup_receiver: comms.clone_broadcast_state_receiver(),
+            hub_broadcast_receiver: comms.clone_broadcast_signal_receiver(),
+            to_hub: comms.pp_to_hub_outbox(&pp_postmark),
+            from_hub: comms.hub_to_pp_inbox(),
+            to_factories: BTreeMap::new(),
+            from_factories: BTreeMap::new(),
+        };
         let povver_plant = Arc::new(Mutex::new(PovverPlant::new(
-            ReadOnlyRwLock::from(Arc::clone(&povver_plant_state)),
-            ReadOnlyRwLock::from(Arc::clone(&econ_state)),
-            ReadOnlyRwLock::clone(&timer_state_ro),
-            ui_log_sender.clone(),
-            comms.clone_broadcast_state_receiver(),
-            comms.clone_pp_dyn_channel(),
-            comms.clone_broadcast_signal_receiver(),
-            to_factory_senders,
-            comms.clone_from_factory_dyn_receivers()
+            pp_links,
+            seeds.stream(RngStream::PovverPlant),
+            Arc::clone(&config),
+            clock_mode,
+            pp_step_turn,
         )));
 
+        let factories_state = Arc::new(RwLock::new(Vec::new()));
+
+        let mut hub = Self {
+            povver_plant,
+            povver_plant_state: Arc::clone(&povver_plant_state),
+            factories_state: Arc::clone(&factories_state),
+            factories: Arc::new(Mutex::new(Vec::new())),
+            econ_state,
+            timer_state_ro,
+            env_state_ro,
+            scheduler: Scheduler::new(),
+            exchange: EnergyExchange::new(),
+            exchange_recorder,
+            ui_log_sender,
+            comms,
+            config,
+            seeds: *seeds,
+            pp_postmark,
+            factory_threads: BTreeMap::new(),
+            crashed_threads: Vec::new(),
+            next_factory_id: 0,
+            is_started: false,
+            tick_duration: Speed::NORMAL.get_tick_duration(),
+            sleeptime: Self::recalculate_sleeptime(Speed::NORMAL.get_tick_duration()),
+            clock_mode,
+            step_turn: Some(step_turn),
+        };
+
+        for (id, factory) in scenario.factories.iter().enumerate() {
+            let state = factory.state(id, &hub.config);
+            hub.add_factory(state, &factory.strategy, stepper.add_actor());
+        }
+
         (
-            Self {
-                povver_plant,
-                povver_plant_state: Arc::clone(&povver_plant_state),
-                factories_state: Arc::clone(&factories_state),
-                factories,
-                econ_state,
-                timer_state_ro,
-                env_state_ro,
-                minutely_jobs: Vec::new(),
-                hourly_jobs: Vec::new(),
-                daily_jobs: Vec::new(),
-                ui_log_sender,
-                comms,
-                sleeptime: Self::recalculate_sleeptime(Speed::NORMAL.get_tick_duration())
-            },
+            hub,
             HubState {
                 povver_plant: povver_plant_state,
                 factories: factories_state,
@@ -137,11 +179,25 @@ impl TheHub {
 impl TheHub {
     fn recalculate_sleeptime(tick_duration: TickDuration) -> Duration {
         // tick durations are in milliseconds so we multiply with 1k to get micros
-        let micros = (tick_duration / 2) * 1000 - 500;
+        let micros = ((tick_duration / 2) * 1000).saturating_sub(500);
 
         Duration::from_micros(micros)
     }
 
+    pub fn snapshot(&self) -> HubSnapshot {
+        HubSnapshot {
+            scheduler: self.scheduler.snapshot(),
+            exchange: self.exchange.clone(),
+            next_factory_id: self.next_factory_id,
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: HubSnapshot) {
+        self.scheduler.restore(snapshot.scheduler);
+        self.exchange = snapshot.exchange;
+        self.next_factory_id = snapshot.next_factory_id;
+    }
+
     pub fn get_factory_state(&self, factory_id: usize) -> Option<Arc<RwLock<FactoryStateData>>> {
         let factories_state = self.factories_state.read().unwrap();
         let factory_state = factories_state.iter().find(|fac| fac.read().unwrap().id == factory_id);
@@ -152,6 +208,106 @@ impl TheHub {
             None
         }
     }
+
+    pub fn get_factory(&self, factory_id: usize) -> Option<Arc<Mutex<Factory>>> {
+        let index = self.factories_state.read().unwrap().iter().position(|fac| fac.read().unwrap().id == factory_id)?;
+
+        self.factories.lock().unwrap().get(index).cloned()
+    }
+
+    // The id the next new factory gets. Ids of retired factories are not given out again.
+    pub fn next_factory_id(&self) -> usize {
+        self.next_factory_id
+    }
+}
+
+// Factories joining and leaving the simulation
+impl TheHub {
+    // Puts a factory to work with the id in its state. It gets its lines to the hub and the Povver Plant,
+    // and its own thread right away if the hub has already started.
+    pub fn add_factory(&mut self, state: FactoryStateData, strategy: &FactoryStrategy, step_turn: StepTurn) {
+        let id = state.id;
+        if self.get_factory_state(id).is_some() {
+            self.log_console(format!("Factory No. {id} is already in the simulation. It can't be added twice."), Error);
+            return;
+        }
+
+        self.comms.add_factory_lines(id);
+        self.povver_plant.lock().unwrap().connect_factory(
+            id,
+            self.comms.pp_to_factory_outbox(id, &self.pp_postmark),
+            self.comms.factory_to_pp_inbox(id),
+        );
+
+        let state = Arc::new(RwLock::new(state));
+        let postmark = Postmark::new(MessageEntity::Factory(id as SimInt), ReadOnlyRwLock::clone(&self.timer_state_ro));
+        let links = FactoryLinks {
+            state_ro: ReadOnlyRwLock::from(Arc::clone(&state)),
+            econ_state_ro: ReadOnlyRwLock::from(Arc::clone(&self.econ_state)),
+            timer_state_ro: ReadOnlyRwLock::clone(&self.timer_state_ro),
+            ui_log_sender: self.ui_log_sender.clone(),
+            wakeup_receiver: self.comms.clone_broadcast_state_receiver(),
+            hub_broadcast_receiver: self.comms.clone_broadcast_signal_receiver(),
+            to_hub: self.comms.factory_to_hub_outbox(id, &postmark),
+            to_pp: self.comms.factory_to_pp_outbox(id, &postmark),
+            from_hub: self.comms.hub_to_factory_inbox(id),
+            from_pp: self.comms.pp_to_factory_inbox(id),
+        };
+        let mut factory = Factory::new(
+            links,
+            self.seeds.stream(RngStream::Factory(id)),
+            strategy,
+            Arc::clone(&self.config),
+            self.clock_mode,
+            step_turn,
+        );
+        factory.set_tick_duration(self.tick_duration);
+        let factory = Arc::new(Mutex::new(factory));
+
+        if self.is_started {
+            self.factory_threads.insert(id, Factory::start(Arc::clone(&factory)));
+            self.log_ui_console(format!("Factory No. {id} opened its doors."), Info);
+        }
+        self.factories.lock().unwrap().push(factory);
+        self.factories_state.write().unwrap().push(state);
+        self.next_factory_id = self.next_factory_id.max(id + 1);
+    }
+
+    // Takes a factory out of the simulation. Its lines are closed, deliveries still on their way
+    // to it are canceled and it is told to end its thread.
+    pub fn retire_factory(&mut self, factory_id: usize) -> Option<RetiredFactory> {
+        let index = self.factories_state.read().unwrap().iter().position(|fac| fac.read().unwrap().id == factory_id)?;
+
+        self.comms.hub_to_factory(factory_id, HubFactorySignal::Retired);
+        self.comms.remove_factory_lines(factory_id);
+        self.povver_plant.lock().unwrap().disconnect_factory(factory_id);
+
+        let canceled_jobs = self.scheduler.pending()
+            .into_iter()
+            .filter(|scheduled| scheduled.job.factory_id() == Some(factory_id))
+            .map(|scheduled| (scheduled.id, scheduled.job.clone()))
+            .collect::<Vec<_>>();
+        for (job_id, job) in canceled_jobs {
+            // Energy that won't be delivered doesn't keep the Povver Plant busy anymore.
+            if let HubJob::PPProducesEnergy(receipt) = job {
+                self.povver_plant_state.write().unwrap().committed_energy -= receipt.units;
+            }
+            self.scheduler.cancel(job_id);
+        }
+        self.exchange.withdraw(Trader::Factory(factory_id));
+
+        let factory = self.factories.lock().unwrap().remove(index);
+        let state = self.factories_state.write().unwrap().remove(index);
+        let step_turn_id = factory.lock().unwrap().step_turn_id();
+        self.log_ui_console(format!("Factory No. {factory_id} closed down for good."), Warning);
+
+        let state = state.read().unwrap().clone();
+        Some(RetiredFactory {
+            state,
+            thread: self.factory_threads.remove(&factory_id),
+            step_turn_id,
+        })
+    }
 }
 
 impl TheHub {
@@ -159,131 +315,173 @@ impl TheHub {
         me: Arc<Mutex<Self>>,
         mut wakeup_receiver: tokio_broadcast::Receiver<StateAction>,
     ) -> thread::JoinHandle<()> {
-        let join_handles = {
-            let me_lock = me.lock().unwrap();
-            let mut handles = vec![
-                PovverPlant::start(
-                    Arc::clone(&me_lock.povver_plant),
-                )
-            ];
-            handles.extend(
-                me_lock.factories.lock().unwrap()
-                    .iter()
-                    .map(|fac| Factory::start(Arc::clone(&fac)))
-                    .collect::<Vec<thread::JoinHandle<()>>>()
-                    .into_iter()
-            );
-
-            handles
-        };
+        let (pp_handle, pp_inbox, step_turn) = {
+            let mut me_lock = me.lock().unwrap();
+            me_lock.is_started = true;
+            let factory_threads = me_lock.factories_state.read().unwrap()
+                .iter()
+                .zip(me_lock.factories.lock().unwrap().iter())
+                .map(|(state, fac)| (state.read().unwrap().id, Factory::start(Arc::clone(fac))))
+                .collect();
+            me_lock.factory_threads = factory_threads;
 
-        let (pp_dyn_receiver, mut from_factory_dyn_receivers) = {
-            let me_lock = me.lock().unwrap();
             (
-                me_lock.comms.clone_pp_dyn_receiver(),
-                me_lock.comms.clone_from_factory_dyn_receivers(),
+                PovverPlant::start(Arc::clone(&me_lock.povver_plant)),
+                me_lock.comms.pp_to_hub_inbox(),
+                me_lock.step_turn.take().expect("The hub is only started once"),
             )
         };
 
         thread::Builder::new().name("POVVER_HUB".to_string()).spawn(move || {
-            loop {
-                if let Ok(signal) = pp_dyn_receiver.try_recv() {
-                    let signal_any = signal.as_any();
-                    match signal_any {
-                        s if s.is::<PPHubSignal>() => {
-                            let signal_from_pp = signal_any.downcast_ref::<PPHubSignal>().unwrap();
-                            match signal_from_pp {
-                                PPHubSignal::BuyFuel(amount) => {
-                                    me.lock().unwrap().pp_buys_fuel(*amount);
-                                },
-                                PPHubSignal::ProduceEnergy(offer) => {
-                                    me.lock().unwrap().pp_produces_energy(offer);
-                                },
-                                PPHubSignal::IncreaseFuelCapacity => {
-                                    me.lock().unwrap().pp_increases_fuel_capacity();
-                                },
-                                PPHubSignal::IncreaseProductionCapacity => {
-                                    me.lock().unwrap().pp_increases_production_capacity();
-                                },
-                                PPHubSignal::DeclaringBankrupcy => {
-                                    me.lock().unwrap().povver_plant_state.write().unwrap().is_bankrupt = true;
-                                    me.lock().unwrap().log_ui_console("Povver Plant decleared bankruptcy.".to_string(), Warning);
-                                },
-                            }
+            // One pass over the inboxes. Returns the number of handled messages and whether to quit.
+            let mut poll = || -> (usize, bool) {
+                let mut handled = 0;
+
+                if let Ok(message) = pp_inbox.try_recv() {
+                    handled += 1;
+                    match message.signal {
+                        PPHubSignal::BuyFuel(amount) => {
+                            me.lock().unwrap().pp_buys_fuel(amount);
+                        },
+                        PPHubSignal::ProduceEnergy(offer) => {
+                            me.lock().unwrap().pp_produces_energy(&offer);
+                        },
+                        PPHubSignal::ExchangeAsk(units, price_per_unit) => {
+                            me.lock().unwrap().exchange.ask(EnergyOrder { trader: Trader::PovverPlant, price_per_unit, units });
+                        },
+                        PPHubSignal::IncreaseFuelCapacity => {
+                            me.lock().unwrap().pp_increases_fuel_capacity();
+                        },
+                        PPHubSignal::IncreaseProductionCapacity => {
+                            me.lock().unwrap().pp_increases_production_capacity();
+                        },
+                        PPHubSignal::DeclaringBankrupcy => {
+                            me.lock().unwrap().povver_plant_state.write().unwrap().is_bankrupt = true;
+                            me.lock().unwrap().log_ui_console("Povver Plant decleared bankruptcy.".to_string(), Warning);
                         },
-                        _ => ()
                     }
                 }
 
-                from_factory_dyn_receivers
-                    .iter_mut()
-                    .enumerate()
-                    .for_each(|(fid, receiver)| {
-                    if let Ok(signal) = receiver.try_recv() {
-                        let signal_any = signal.as_any();
-                        match signal_any {
-                            s if s.is::<FactoryHubSignal>() => {
-                                if let Some(signal_from_factory) = signal_any.downcast_ref::<FactoryHubSignal>() {
-                                    match signal_from_factory {
-                                        FactoryHubSignal::EnergyDemand(demand) => {
-                                            me.lock().unwrap().factory_needs_energy(demand);
-                                        },
-                                        FactoryHubSignal::ProducingProductDemand(demand, units, unit_cost) => {
-                                            me.lock().unwrap().factory_will_produce(fid, demand, *units, *unit_cost);
-                                        },
-                                        FactoryHubSignal::SellingProduct(stock_index, unit_price) => {
-                                            me.lock().unwrap().factory_sells_product(fid, *stock_index, *unit_price);
-                                        },
-                                        FactoryHubSignal::BuyingSolarPanels(panels_count) => {
-                                            me.lock().unwrap().factory_buys_solar_panels(fid, *panels_count);
-                                        },
-                                        FactoryHubSignal::DeclaringBankrupcy => {
-                                            let factory = me.lock().unwrap().get_factory_state(fid).unwrap();
-                                            factory.write().unwrap().is_bankrupt = true;
-                                            me.lock().unwrap().log_ui_console(format!("Factory No. {} decleared bankruptcy.", fid), Warning);
-                                        }
-                                    }
-                                }
+                // Factories come and go, so we pick up their inboxes on every pass.
+                let factory_inboxes = me.lock().unwrap().comms.factory_to_hub_inboxes();
+                for (fid, inbox) in factory_inboxes {
+                    if let Ok(message) = inbox.try_recv() {
+                        handled += 1;
+                        match message.signal {
+                            FactoryHubSignal::EnergyDemand(demand) => {
+                                me.lock().unwrap().factory_needs_energy(&demand);
+                            },
+                            FactoryHubSignal::EnergyOffer(offer) => {
+                                me.lock().unwrap().factory_offers_energy(&offer);
+                            },
+                            FactoryHubSignal::AcceptFactoryEnergyOffer(offer) => {
+                                me.lock().unwrap().factory_buys_energy_from_factory(&offer);
+                            },
+                            FactoryHubSignal::RejectFactoryEnergyOffer(offer) => {
+                                me.lock().unwrap().factory_rejects_energy_offer(&offer);
+                            },
+                            FactoryHubSignal::ExchangeBid(units, price_per_unit) => {
+                                me.lock().unwrap().exchange.bid(EnergyOrder { trader: Trader::Factory(fid), price_per_unit, units });
+                            },
+                            FactoryHubSignal::ExchangeAsk(units, price_per_unit) => {
+                                me.lock().unwrap().exchange.ask(EnergyOrder { trader: Trader::Factory(fid), price_per_unit, units });
+                            },
+                            FactoryHubSignal::ProducingProductDemand(demand, units, unit_cost) => {
+                                me.lock().unwrap().factory_will_produce(fid, &demand, units, unit_cost);
                             },
-                            _ => ()
+                            FactoryHubSignal::SellingProduct(stock_index, unit_price) => {
+                                me.lock().unwrap().factory_sells_product(fid, stock_index, unit_price);
+                            },
+                            FactoryHubSignal::BuyingSolarPanels(panels_count) => {
+                                me.lock().unwrap().factory_buys_solar_panels(fid, panels_count);
+                            },
+                            FactoryHubSignal::BuyingWindTurbines(turbines_count) => {
+                                me.lock().unwrap().factory_buys_wind_turbines(fid, turbines_count);
+                            },
+                            FactoryHubSignal::BuyingBatteries(batteries_count) => {
+                                me.lock().unwrap().factory_buys_batteries(fid, batteries_count);
+                            },
+                            FactoryHubSignal::ChargeBatteries(energy) => {
+                                me.lock().unwrap().factory_charges_batteries(fid, energy);
+                            },
+                            FactoryHubSignal::DrawFromBatteries(energy) => {
+                                me.lock().unwrap().factory_draws_from_batteries(fid, energy);
+                            },
+                            FactoryHubSignal::DeclaringBankrupcy => {
+                                if let Some(factory) = me.lock().unwrap().get_factory_state(fid) {
+                                    factory.write().unwrap().is_bankrupt = true;
+                                }
+                                me.lock().unwrap().log_ui_console(format!("Factory No. {} decleared bankruptcy.", fid), Warning);
+                            }
                         }
                     }
-                });
+                }
 
                 if let Ok(action) = wakeup_receiver.try_recv() {
+                    handled += 1;
                     me.lock().unwrap().comms.send_state_broadcast(action.clone());
 
                     match action {
                         StateAction::Timer(event) => {
                             if event.at_least_minute() {
-                                me.lock().unwrap().do_minutely_jobs();
+                                me.lock().unwrap().do_due_jobs();
+                                me.lock().unwrap().maybe_open_exchange();
                             }
                             if event.at_least_hour() {
-                                me.lock().unwrap().do_hourly_jobs(&event);
+                                me.lock().unwrap().factories_batteries_new_hour(&event);
+                                me.lock().unwrap().factories_renewable_produce_energy(&event);
                             }
                             if event.at_least_day() {
-                                me.lock().unwrap().do_daily_jobs();
+                                // If the factory doesn't use it's available energy in a day, it will be expired the next day.
+                                // Unless it's stored in batteries.
+                                me.lock().unwrap().factories_energy_expired();
                             }
                         },
                         StateAction::SpeedChange(td) => {
-                            me.lock().unwrap().sleeptime = Self::recalculate_sleeptime(td);
+                            let mut me_lock = me.lock().unwrap();
+                            me_lock.tick_duration = td;
+                            me_lock.sleeptime = Self::recalculate_sleeptime(td);
+                        }
+                        StateAction::ClockModeChange(clock_mode) => {
+                            me.lock().unwrap().clock_mode = clock_mode;
                         }
                         StateAction::Env => {},
                         StateAction::Misc => {},
                         StateAction::Quit => {
                             me.lock().unwrap().log_console("Quit signal received.".to_string(), Warning);
-                            for handle in join_handles {
-                                if let Err(e) = handle.join() {
-                                    me.lock().unwrap().log_console(format!("Failed to join thread: {:?}", e), Warning);
-                                }
-                            }
-                            break;
+                            return (handled, true);
                         },
                         _ => (),
                     }
                 }
 
-                thread::sleep(me.lock().unwrap().sleeptime);
+                (handled, false)
+            };
+
+            loop {
+                let clock_mode = me.lock().unwrap().clock_mode;
+                if clock_mode == ClockMode::Stepped {
+                    if step_turn.take(&mut poll) {
+                        break;
+                    }
+                } else {
+                    if poll().1 {
+                        break;
+                    }
+                    thread::sleep(me.lock().unwrap().sleeptime);
+                }
+            }
+
+            // In stepped mode the other actors only see the quit signal on their next turn,
+            // which they get after we've acknowledged ours.
+            let factory_threads = std::mem::take(&mut me.lock().unwrap().factory_threads);
+            for handle in std::iter::once(pp_handle).chain(factory_threads.into_values()) {
+                let name = handle.thread().name().unwrap_or("unnamed").to_string();
+                if let Err(e) = handle.join() {
+                    let mut me_lock = me.lock().unwrap();
+                    me_lock.log_console(format!("Failed to join thread: {:?}", e), Warning);
+                    me_lock.crashed_threads.push(name);
+                }
             }
         }).unwrap()
     }
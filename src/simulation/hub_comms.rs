@@ -1,31 +1,33 @@
 use std::{
-    any::Any,
-    sync::Arc,
-    fmt::Debug,
+    collections::BTreeMap,
+    sync::{Arc, atomic::{AtomicU64, Ordering}},
 };
 use crossbeam_channel::{Sender as CrossbeamSender, Receiver as CrossbeamReceiver, bounded};
 use tokio::{
     sync::broadcast as tokio_broadcast
 };
+use serde::{Serialize, Deserialize};
 
 use crate::{
+    app_state::TimerStateData,
     simulation::{
         StateAction,
         SimInt,
         SimFlo,
+        timer::Date,
     },
-    economy::economy_types::ProductDemand,
-    ui_controller::Date,
+    economy::economy_types::{ProductDemand, TariffWindow},
+    utils_data::ReadOnlyRwLock,
 };
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum MessageEntity {
     Hub,
     PP,
     Factory(SimInt),
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct FuelReceipt {
     pub units: SimInt,
     pub price_per_unit: SimFlo,
@@ -33,16 +35,18 @@ pub struct FuelReceipt {
     pub total_price: SimFlo,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct EnergyReceipt {
     pub units: SimInt,
     pub price_per_unit: SimFlo,
     pub date: Date,
     pub factory_id: usize,
     pub total_price: SimFlo,
+    // The factory that sold the energy. None when it came from the Povver Plant.
+    pub seller_factory_id: Option<usize>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ProductionReceipt {
     pub demand: ProductDemand,
     pub units_produced: SimInt,
@@ -52,11 +56,22 @@ pub struct ProductionReceipt {
     pub total_price: SimFlo,
 }
 
-#[derive(Debug, Default, Copy, Clone, PartialEq)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub struct PPEnergyOffer {
     pub price_per_unit: SimFlo,
     pub units: SimInt,
     pub to_factory_id: usize,
+    // The time-of-use window the price was made for.
+    pub tariff_window: TariffWindow,
+}
+
+// Spare energy a factory offers to another one that needs it.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FactoryEnergyOffer {
+    pub price_per_unit: SimFlo,
+    pub units: SimInt,
+    pub from_factory_id: usize,
+    pub to_factory_id: usize,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -65,7 +80,8 @@ pub struct FactoryEnergyDemand {
     pub energy_needed: SimInt,
 }
 
-#[derive(Debug, PartialEq)]
+// Hub to the Povver Plant
+#[derive(Debug, Clone, PartialEq)]
 pub enum HubPPSignal {
     FuelTransfered(FuelReceipt),
     EnergyTransfered(EnergyReceipt),
@@ -73,166 +89,275 @@ pub enum HubPPSignal {
     ProductionCapacityIncreased,
 }
 
-#[derive(Debug, PartialEq)]
+// Hub to a single factory
+#[derive(Debug, Clone, PartialEq)]
 pub enum HubFactorySignal {
     EnergyTransfered(EnergyReceipt),
+    // Another factory's offer for the energy we need.
+    EnergyOffer(FactoryEnergyOffer),
+    // Every offer for our last energy demand should have come in by now.
+    EnergyOffersClosed,
+    // Our offer was turned down or couldn't be settled.
+    EnergyOfferRejected(FactoryEnergyOffer),
+    EnergySold(EnergyReceipt),
+    // Our bid on the energy exchange was matched, for all or some of its units. The energy follows.
+    ExchangeBidFilled(EnergyReceipt),
+    BatteriesDischarged(SimInt),
     ProductionComplete(ProductionReceipt),
     RenewableEnergyProduced,
+    // The factory is taken out of the simulation and its thread should end.
+    Retired,
 }
 
-#[derive(Debug)]
+// Hub to the Povver Plant and every factory at once
+#[derive(Debug, Clone, PartialEq)]
+pub enum HubBroadcastSignal {
+    FactoryNeedsEnergy(FactoryEnergyDemand),
+    // The energy exchange takes orders for its next clearing round.
+    ExchangeOpen,
+}
+
+// Povver Plant to the hub
+#[derive(Debug, Clone, PartialEq)]
 pub enum PPHubSignal {
     BuyFuel(SimInt),
     ProduceEnergy(PPEnergyOffer),
+    // Units and the price per unit
+    ExchangeAsk(SimInt, SimFlo),
     IncreaseFuelCapacity,
     IncreaseProductionCapacity,
     DeclaringBankrupcy,
 }
 
-#[derive(Debug, PartialEq)]
+// Povver Plant to a single factory
+#[derive(Debug, Clone, PartialEq)]
+pub enum PPFactorySignal {
+    EnergyOffer(PPEnergyOffer),
+}
+
+// Factory to the hub
+#[derive(Debug, Clone, PartialEq)]
 pub enum FactoryHubSignal {
     EnergyDemand(FactoryEnergyDemand),
+    EnergyOffer(FactoryEnergyOffer),
+    AcceptFactoryEnergyOffer(FactoryEnergyOffer),
+    RejectFactoryEnergyOffer(FactoryEnergyOffer),
+    // Units and the price per unit
+    ExchangeBid(SimInt, SimFlo),
+    ExchangeAsk(SimInt, SimFlo),
     ProducingProductDemand(ProductDemand, SimInt, SimFlo),
     SellingProduct(usize, SimFlo),
     BuyingSolarPanels(usize),
+    BuyingWindTurbines(usize),
+    BuyingBatteries(usize),
+    ChargeBatteries(SimInt),
+    DrawFromBatteries(SimInt),
     DeclaringBankrupcy,
 }
 
-#[derive(Debug, PartialEq)]
-pub enum FactorySignal {
+// Factory to the Povver Plant
+#[derive(Debug, Clone, PartialEq)]
+pub enum FactoryPPSignal {
     AcceptPPEnergyOffer(PPEnergyOffer),
     RejectPPEnergyOffer(PPEnergyOffer),
 }
 
-pub trait DynSignalable: Send + Sync + Debug {
-    fn as_any(&self) -> &dyn Any;
-}
-
-macro_rules! impl_dynsignalable {
-    ($($structname:ident),+) => {
-        $(
-            impl DynSignalable for $structname {
-                fn as_any(&self) -> &dyn Any {
-                    self
-                }
-            }
-        )+
-    };
-}
-
-impl_dynsignalable!(
-    PPHubSignal,
-    PPEnergyOffer,
-    HubPPSignal,
-    HubFactorySignal,
-    FactoryEnergyDemand,
-    FactorySignal,
-    FactoryHubSignal
-);
-
-pub type DynamicSignal = Arc<dyn DynSignalable>;
-pub type DynamicSender = CrossbeamSender<DynamicSignal>;
-pub type DynamicReceiver = CrossbeamReceiver<DynamicSignal>;
-pub type DynamicChannel = (CrossbeamSender<DynamicSignal>, CrossbeamReceiver<DynamicSignal>);
-pub type BroadcastDynSender = tokio_broadcast::Sender<DynamicSignal>;
-pub type BroadcastDynReceiver = tokio_broadcast::Receiver<DynamicSignal>;
-pub type BroadcastDynChannel = (BroadcastDynSender, BroadcastDynReceiver);
+// A signal on its way, with who sent it, the sender's running count of messages and
+// the simulation timestamp it was sent at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message<T> {
+    pub from: MessageEntity,
+    pub seq: u64,
+    pub timestamp: u128,
+    pub signal: T,
+}
+
+// Stamps every message of one sender. Shared by all of its outboxes so the sequence
+// numbers run across them.
+pub struct Postmark {
+    from: MessageEntity,
+    next_seq: AtomicU64,
+    timer_state_ro: ReadOnlyRwLock<TimerStateData>,
+}
+
+impl Postmark {
+    pub fn new(from: MessageEntity, timer_state_ro: ReadOnlyRwLock<TimerStateData>) -> Arc<Self> {
+        Arc::new(Self {
+            from,
+            next_seq: AtomicU64::new(0),
+            timer_state_ro,
+        })
+    }
+
+    pub fn stamp<T>(&self, signal: T) -> Message<T> {
+        Message {
+            from: self.from,
+            seq: self.next_seq.fetch_add(1, Ordering::Relaxed),
+            timestamp: self.timer_state_ro.read().unwrap().timestamp,
+            signal,
+        }
+    }
+}
+
+// Sending end of a one way line. Only takes the signals of its direction.
+pub struct Outbox<T> {
+    sender: CrossbeamSender<Message<T>>,
+    postmark: Arc<Postmark>,
+}
+
+impl<T> Outbox<T> {
+    pub fn new(sender: CrossbeamSender<Message<T>>, postmark: Arc<Postmark>) -> Self {
+        Self {
+            sender,
+            postmark,
+        }
+    }
+
+    pub fn send(&self, signal: T) {
+        if let Err(e) = self.sender.send(self.postmark.stamp(signal)) {
+            eprintln!("HUB COMMS: {:?} could not send a signal, the receiver is gone: {e}", self.postmark.from);
+        }
+    }
+}
+
+pub type Inbox<T> = CrossbeamReceiver<Message<T>>;
+type Line<T> = (CrossbeamSender<Message<T>>, Inbox<T>);
+pub type BroadcastInbox = tokio_broadcast::Receiver<Message<HubBroadcastSignal>>;
 
 pub struct HubComms {
+    postmark: Arc<Postmark>,
     broadcast_state_channel: (tokio_broadcast::Sender<StateAction>, tokio_broadcast::Receiver<StateAction>),
-    broadcast_signal_channel: BroadcastDynChannel,
-    pp_dyn_channel: DynamicChannel,
-    from_factory_dyn_channels: Vec<BroadcastDynChannel>,
-    to_factory_dyn_channels: Vec<DynamicChannel>,
+    broadcast_signal_channel: (tokio_broadcast::Sender<Message<HubBroadcastSignal>>, BroadcastInbox),
+    hub_to_pp_line: Line<HubPPSignal>,
+    pp_to_hub_line: Line<PPHubSignal>,
+    // Lines of the factories by factory id. Factories come and go while the simulation runs.
+    hub_to_factory_lines: BTreeMap<usize, Line<HubFactorySignal>>,
+    pp_to_factory_lines: BTreeMap<usize, Line<PPFactorySignal>>,
+    factory_to_hub_lines: BTreeMap<usize, Line<FactoryHubSignal>>,
+    factory_to_pp_lines: BTreeMap<usize, Line<FactoryPPSignal>>,
 }
 
 impl HubComms {
-    pub fn new(factory_count: usize) -> Self {
-        let from_factory_dyn_channels = (0..factory_count).map(|_| tokio_broadcast::channel(64)).collect();
-        let to_factory_dyn_channels = (0..factory_count).map(|_| bounded(64)).collect();
-
+    // Factories get their lines as they are added.
+    pub fn new(timer_state_ro: ReadOnlyRwLock<TimerStateData>) -> Self {
         Self {
+            postmark: Postmark::new(MessageEntity::Hub, timer_state_ro),
             broadcast_state_channel: tokio_broadcast::channel(64),
             broadcast_signal_channel: tokio_broadcast::channel(64),
-            pp_dyn_channel: bounded(64),
-            from_factory_dyn_channels,
-            to_factory_dyn_channels,
+            hub_to_pp_line: bounded(64),
+            pp_to_hub_line: bounded(64),
+            hub_to_factory_lines: BTreeMap::new(),
+            pp_to_factory_lines: BTreeMap::new(),
+            factory_to_hub_lines: BTreeMap::new(),
+            factory_to_pp_lines: BTreeMap::new(),
         }
     }
+
+    pub fn add_factory_lines(&mut self, factory_id: usize) {
+        self.hub_to_factory_lines.insert(factory_id, bounded(64));
+        self.pp_to_factory_lines.insert(factory_id, bounded(64));
+        self.factory_to_hub_lines.insert(factory_id, bounded(64));
+        self.factory_to_pp_lines.insert(factory_id, bounded(64));
+    }
+
+    // Our ends of the lines go away. Whatever is still in them can be read by the ends
+    // that are left with the factory and the Povver Plant.
+    pub fn remove_factory_lines(&mut self, factory_id: usize) {
+        self.hub_to_factory_lines.remove(&factory_id);
+        self.pp_to_factory_lines.remove(&factory_id);
+        self.factory_to_hub_lines.remove(&factory_id);
+        self.factory_to_pp_lines.remove(&factory_id);
+    }
 }
 
+// Ends of the lines for the Povver Plant and the factories.
+// Outboxes get the postmark of the entity that sends through them.
 impl HubComms {
-    fn broadcast_state_sender(&self) -> &tokio_broadcast::Sender<StateAction> {
-        &self.broadcast_state_channel.0
+    pub fn clone_broadcast_state_receiver(&self) -> tokio_broadcast::Receiver<StateAction> {
+        self.broadcast_state_channel.1.resubscribe()
     }
 
-    fn broadcast_signal_sender(&self) -> &tokio_broadcast::Sender<DynamicSignal> {
-        &self.broadcast_signal_channel.0
+    pub fn clone_broadcast_signal_receiver(&self) -> BroadcastInbox {
+        self.broadcast_signal_channel.1.resubscribe()
     }
 
-    fn pp_dyn_sender(&self) -> &DynamicSender {
-        &self.pp_dyn_channel.0
+    pub fn pp_to_hub_outbox(&self, postmark: &Arc<Postmark>) -> Outbox<PPHubSignal> {
+        Outbox::new(self.pp_to_hub_line.0.clone(), Arc::clone(postmark))
     }
 
-    fn to_factory_dyn_sender(&self, factory_id: usize) -> &CrossbeamSender<DynamicSignal> {
-        &self.to_factory_dyn_channels[factory_id].0
+    pub fn hub_to_pp_inbox(&self) -> Inbox<HubPPSignal> {
+        self.hub_to_pp_line.1.clone()
     }
-}
 
-impl HubComms {
-    pub fn clone_broadcast_state_receiver(&self) -> tokio_broadcast::Receiver<StateAction> {
-        self.broadcast_state_channel.1.resubscribe()
+    pub fn pp_to_factory_outboxes(&self, postmark: &Arc<Postmark>) -> BTreeMap<usize, Outbox<PPFactorySignal>> {
+        self.pp_to_factory_lines.keys().map(|&factory_id| (factory_id, self.pp_to_factory_outbox(factory_id, postmark))).collect()
     }
 
-    pub fn clone_broadcast_signal_receiver(&self) -> tokio_broadcast::Receiver<DynamicSignal> {
-        self.broadcast_signal_channel.1.resubscribe()
+    pub fn factory_to_pp_inboxes(&self) -> BTreeMap<usize, Inbox<FactoryPPSignal>> {
+        self.factory_to_pp_lines.keys().map(|&factory_id| (factory_id, self.factory_to_pp_inbox(factory_id))).collect()
     }
 
-    pub fn clone_to_factory_dyn_sender(&self, factory_id: usize) -> DynamicSender {
-        self.to_factory_dyn_channels[factory_id].0.clone()
+    pub fn pp_to_factory_outbox(&self, factory_id: usize, postmark: &Arc<Postmark>) -> Outbox<PPFactorySignal> {
+        Outbox::new(self.pp_to_factory_lines[&factory_id].0.clone(), Arc::clone(postmark))
     }
 
-    pub fn clone_to_factory_dyn_receiver(&self, factory_id: usize) -> DynamicReceiver {
-        self.to_factory_dyn_channels[factory_id].1.clone()
+    pub fn factory_to_pp_inbox(&self, factory_id: usize) -> Inbox<FactoryPPSignal> {
+        self.factory_to_pp_lines[&factory_id].1.clone()
     }
 
-    pub fn clone_from_factory_dyn_sender(&self, factory_id: usize) -> BroadcastDynSender {
-        self.from_factory_dyn_channels[factory_id].0.clone()
+    pub fn factory_to_hub_outbox(&self, factory_id: usize, postmark: &Arc<Postmark>) -> Outbox<FactoryHubSignal> {
+        Outbox::new(self.factory_to_hub_lines[&factory_id].0.clone(), Arc::clone(postmark))
     }
 
-    pub fn clone_from_factory_dyn_receivers(&self) -> Vec<BroadcastDynReceiver> {
-        self.from_factory_dyn_channels.iter().map(|(_, r)| r.resubscribe()).collect()
+    pub fn factory_to_pp_outbox(&self, factory_id: usize, postmark: &Arc<Postmark>) -> Outbox<FactoryPPSignal> {
+        Outbox::new(self.factory_to_pp_lines[&factory_id].0.clone(), Arc::clone(postmark))
     }
 
-    pub fn clone_pp_dyn_receiver(&self) -> DynamicReceiver {
-        self.pp_dyn_channel.1.clone()
+    pub fn hub_to_factory_inbox(&self, factory_id: usize) -> Inbox<HubFactorySignal> {
+        self.hub_to_factory_lines[&factory_id].1.clone()
     }
 
-    pub fn clone_pp_dyn_channel(&self) -> DynamicChannel {
-        self.pp_dyn_channel.clone()
+    pub fn pp_to_factory_inbox(&self, factory_id: usize) -> Inbox<PPFactorySignal> {
+        self.pp_to_factory_lines[&factory_id].1.clone()
     }
 }
 
+// The hub's own ends of the lines.
 impl HubComms {
+    pub fn pp_to_hub_inbox(&self) -> Inbox<PPHubSignal> {
+        self.pp_to_hub_line.1.clone()
+    }
+
+    // Inboxes of the factories there are right now, with their factory ids.
+    pub fn factory_to_hub_inboxes(&self) -> Vec<(usize, Inbox<FactoryHubSignal>)> {
+        self.factory_to_hub_lines.iter().map(|(&factory_id, (_, receiver))| (factory_id, receiver.clone())).collect()
+    }
+
     pub fn send_state_broadcast(&self, action: StateAction) {
-        if let Err(e) = self.broadcast_state_sender().send(action) {
+        if let Err(e) = self.broadcast_state_channel.0.send(action) {
             eprintln!("HUB COMMS: Could not send state broadcast to one recipient: {e}");
         }
     }
 
-    pub fn send_signal_broadcast(&self, signal: DynamicSignal) {
-        if let Err(e) = self.broadcast_signal_sender().send(signal) {
+    pub fn send_signal_broadcast(&self, signal: HubBroadcastSignal) {
+        if let Err(e) = self.broadcast_signal_channel.0.send(self.postmark.stamp(signal)) {
             eprintln!("HUB COMMS: Could not send broadcast signal to one recipient: {e}");
         }
     }
 
-    pub fn hub_to_pp(&self, signal: DynamicSignal) {
-        self.pp_dyn_sender().send(signal).unwrap();
+    pub fn hub_to_pp(&self, signal: HubPPSignal) {
+        if let Err(e) = self.hub_to_pp_line.0.send(self.postmark.stamp(signal)) {
+            eprintln!("HUB COMMS: Could not send signal to the Povver Plant: {e}");
+        }
     }
 
-    pub fn hub_to_factory(&self, fid: usize, signal: DynamicSignal) {
-        if let Err(e) = self.to_factory_dyn_sender(fid).send(signal) {
+    pub fn hub_to_factory(&self, fid: usize, signal: HubFactorySignal) {
+        let Some((sender, _)) = self.hub_to_factory_lines.get(&fid) else {
+            eprintln!("HUB COMMS: Could not send signal to factory No. {fid}, it's not in the simulation.");
+            return;
+        };
+        if let Err(e) = sender.send(self.postmark.stamp(signal)) {
             eprintln!("HUB COMMS: Could not send signal to factory No. {fid}: {e}");
         }
     }
-}
\ No newline at end of file
+}
@@ -1,14 +1,12 @@
-use std::sync::Arc;
-
 use crate::{
     logger::{Logger, LogLevel::*},
     simulation::{
         SimInt,
         SimFlo,
         hub::TheHub,
-        sim_constants::*,
         hub_jobs::*,
         hub_comms::*,
+        exchange::Trader,
         Percentage
     },
     economy::{
@@ -38,13 +36,7 @@ impl TheHub {
             if delay == 0 {
                 self.transfer_fuel_to_pp(receipt);
             } else {
-                self.hourly_jobs.push(
-                    HourlyJob {
-                        kind: HourlyJobKind::PPBoughtFuel(receipt),
-                        delay,
-                        timestamp: self.timer_state_ro.read().unwrap().timestamp,
-                    }
-                );
+                self.schedule_job(delay * 60, HubJob::PPBoughtFuel(receipt));
                 self.log_ui_console(format!("PP bought fuel for amount {amount}. ETA is {delay} hours."), Info);
                 self.povver_plant_state.write().unwrap().is_awaiting_fuel = true;
             }
@@ -56,16 +48,12 @@ impl TheHub {
     pub fn pp_increases_fuel_capacity(&mut self) {
         let transaction_successful =
             self.povver_plant_state.write().unwrap()
-                .balance.dec(PP_FUEL_CAPACITY_INCREASE_COST.val());
+                .balance.dec(self.config.povver_plant.fuel_capacity_increase_cost);
 
         if transaction_successful {
             self.povver_plant_state.write().unwrap().is_awaiting_fuel_capacity = true;
             let delay = 1;
-            self.daily_jobs.push(DailyJob {
-                kind: DailyJobKind::PPFuelCapIncrease,
-                delay,
-                timestamp: self.timer_state_ro.read().unwrap().timestamp,
-            });
+            self.schedule_job(delay * 60 * 24, HubJob::PPFuelCapIncrease);
             self.log_ui_console(format!("PP is upgrading it's fuel capacity. ETA is {delay} days."), Info);
             println!();
         } else {
@@ -76,16 +64,12 @@ impl TheHub {
     pub fn pp_increases_production_capacity(&mut self) {
         let transaction_successful =
             self.povver_plant_state.write().unwrap()
-                .balance.dec(PP_PRODUCTION_CAPACITY_INCREASE_COST.val());
+                .balance.dec(self.config.povver_plant.production_capacity_increase_cost);
 
         if transaction_successful {
             self.povver_plant_state.write().unwrap().is_awaiting_production_capacity = true;
             let delay = 3;
-            self.daily_jobs.push(DailyJob {
-                kind: DailyJobKind::PPProductionCapIncrease,
-                delay,
-                timestamp: self.timer_state_ro.read().unwrap().timestamp,
-            });
+            self.schedule_job(delay * 60 * 24, HubJob::PPProductionCapIncrease);
             self.log_ui_console(format!("PP is upgrading it's production capacity. ETA is {delay} days."), Info);
         } else {
             self.log_ui_console("PP couldn't pay for production capacity increase. Upgrade canceled.".to_string(), Critical);
@@ -93,44 +77,188 @@ impl TheHub {
     }
 
     pub fn pp_produces_energy(&mut self, offer: &PPEnergyOffer) {
+        if let Some(receipt) = self.factory_pays_pp_for_energy(offer) {
+            self.pp_delivers_energy(receipt);
+        }
+    }
+
+    fn factory_pays_pp_for_energy(&mut self, offer: &PPEnergyOffer) -> Option<EnergyReceipt> {
         let fid = offer.to_factory_id;
-        if let Some(factory) = self.get_factory_state(fid) {
-            let fee = offer.price_per_unit * offer.units as SimFlo;
-            if !factory.write().unwrap().balance.dec(fee.val()) {
-                factory.write().unwrap().is_bankrupt = true;
+        let Some(factory) = self.get_factory_state(fid) else {
+            self.log_console(format!("Factory No. {} is not found. PP energy production canceled.", fid), Error);
+            return None;
+        };
 
-                self.log_ui_console(format!("Factory No. {} has gone bankrupt. I'm the hub. I don't go bankrupt.", fid), Critical);
+        let fee = offer.price_per_unit * offer.units as SimFlo;
+        if !factory.write().unwrap().balance.dec(fee.val()) {
+            factory.write().unwrap().is_bankrupt = true;
 
-                return;
-            }
+            self.log_ui_console(format!("Factory No. {} has gone bankrupt. I'm the hub. I don't go bankrupt.", fid), Critical);
 
-            let date = self.timer_state_ro.read().unwrap().date.clone();
-            let receipt = EnergyReceipt {
-                units: offer.units,
-                price_per_unit: offer.price_per_unit.val(),
-                date,
-                factory_id: fid,
-                total_price: fee.val(),
-            };
+            return None;
+        }
 
-            let delay = offer.units / 100;
-            if delay == 0 {
-                self.pp_energy_to_factory(receipt);
-            } else {
-                self.minutely_jobs.push(MinutelyJob {
-                    kind: MinutelyJobKind::PPProducesEnergy(receipt),
-                    delay,
-                    timestamp: self.timer_state_ro.read().unwrap().timestamp,
-                });
-                self.log_ui_console(format!("PP is producing {} units of energy for factory No. {}. ETA is {} minutes.", offer.units, fid, delay), Info);
-            }
+        Some(EnergyReceipt {
+            units: offer.units,
+            price_per_unit: offer.price_per_unit.val(),
+            date: self.timer_state_ro.read().unwrap().date.clone(),
+            factory_id: fid,
+            total_price: fee.val(),
+            seller_factory_id: None,
+        })
+    }
+
+    fn pp_delivers_energy(&mut self, receipt: EnergyReceipt) {
+        let delay = receipt.units / 100;
+        if delay == 0 {
+            self.pp_energy_to_factory(receipt);
         } else {
-            self.log_console(format!("Factory No. {} is not found. PP energy production canceled.", fid), Error);
+            self.log_ui_console(format!("PP is producing {} units of energy for factory No. {}. ETA is {} minutes.", receipt.units, receipt.factory_id, delay), Info);
+            self.povver_plant_state.write().unwrap().committed_energy += receipt.units;
+            self.schedule_job(delay, HubJob::PPProducesEnergy(receipt));
         }
     }
 
     pub fn factory_needs_energy(&mut self, demand: &FactoryEnergyDemand) {
-        self.comms.send_signal_broadcast(Arc::new(*demand))
+        self.comms.send_signal_broadcast(HubBroadcastSignal::FactoryNeedsEnergy(*demand));
+        // The Povver Plant and the other factories get a minute to make their offers.
+        self.schedule_job(1, HubJob::FactoryEnergyOffersClose(demand.factory_id));
+    }
+
+    pub fn factory_offers_energy(&mut self, offer: &FactoryEnergyOffer) {
+        if self.get_factory_state(offer.to_factory_id).is_some() {
+            self.comms.hub_to_factory(offer.to_factory_id, HubFactorySignal::EnergyOffer(*offer));
+        } else {
+            self.log_console(format!("Factory No. {} is not found. Energy offer from factory No. {} canceled.", offer.to_factory_id, offer.from_factory_id), Warning);
+            self.comms.hub_to_factory(offer.from_factory_id, HubFactorySignal::EnergyOfferRejected(*offer));
+        }
+    }
+
+    pub fn factory_rejects_energy_offer(&mut self, offer: &FactoryEnergyOffer) {
+        self.comms.hub_to_factory(offer.from_factory_id, HubFactorySignal::EnergyOfferRejected(*offer));
+    }
+
+    pub fn factory_buys_energy_from_factory(&mut self, offer: &FactoryEnergyOffer) {
+        if let Some(receipt) = self.factory_sells_energy(offer) {
+            self.comms.hub_to_factory(offer.to_factory_id, HubFactorySignal::EnergyTransfered(receipt));
+        }
+    }
+
+    // Moves the energy and the money between the factories. The seller hears how it went,
+    // the buyer gets the receipt if it went through.
+    fn factory_sells_energy(&mut self, offer: &FactoryEnergyOffer) -> Option<EnergyReceipt> {
+        let (buyer_id, seller_id) = (offer.to_factory_id, offer.from_factory_id);
+        let (Some(buyer), Some(seller)) = (self.get_factory_state(buyer_id), self.get_factory_state(seller_id)) else {
+            self.log_console(format!("Factory No. {} or No. {} is not found. Energy sale canceled.", seller_id, buyer_id), Error);
+            self.comms.hub_to_factory(seller_id, HubFactorySignal::EnergyOfferRejected(*offer));
+            return None;
+        };
+
+        // Energy might have expired or gone to another buyer since the offer was made.
+        if seller.read().unwrap().available_energy.val() < offer.units {
+            self.log_ui_console(format!("Factory No. {} doesn't have the {} units of energy it offered anymore. Sale canceled.", seller_id, offer.units), Warning);
+            self.comms.hub_to_factory(seller_id, HubFactorySignal::EnergyOfferRejected(*offer));
+            return None;
+        }
+
+        let fee = offer.price_per_unit * offer.units as SimFlo;
+        if !buyer.write().unwrap().balance.dec(fee.val()) {
+            buyer.write().unwrap().is_bankrupt = true;
+            self.log_ui_console(format!("Factory No. {} has gone bankrupt. It couldn't pay factory No. {} for energy.", buyer_id, seller_id), Critical);
+            self.comms.hub_to_factory(seller_id, HubFactorySignal::EnergyOfferRejected(*offer));
+            return None;
+        }
+
+        {
+            let mut sel = seller.write().unwrap();
+            sel.available_energy.dec(offer.units);
+            sel.balance.inc(fee.val());
+            sel.energy_to_factories_total += offer.units.max(0) as u64;
+        }
+        {
+            let mut buy = buyer.write().unwrap();
+            buy.available_energy.inc(offer.units);
+            buy.energy_from_factories_total += offer.units.max(0) as u64;
+        }
+
+        let receipt = EnergyReceipt {
+            units: offer.units,
+            price_per_unit: offer.price_per_unit.val(),
+            date: self.timer_state_ro.read().unwrap().date.clone(),
+            factory_id: buyer_id,
+            total_price: fee.val(),
+            seller_factory_id: Some(seller_id),
+        };
+
+        self.log_ui_console(format!("Energy of {} units transfered to Factory No. {} from Factory No. {}.", offer.units, buyer_id, seller_id), Info);
+
+        self.comms.hub_to_factory(seller_id, HubFactorySignal::EnergySold(receipt.clone()));
+
+        Some(receipt)
+    }
+
+    // Every clearing interval the traders get a minute to put in their orders.
+    pub fn maybe_open_exchange(&mut self) {
+        let exchange = &self.config.exchange;
+        let now = self.timer_state_ro.read().unwrap().timestamp;
+        if !exchange.enabled || !now.is_multiple_of(exchange.clearing_interval as u128) {
+            return;
+        }
+
+        self.comms.send_signal_broadcast(HubBroadcastSignal::ExchangeOpen);
+        self.schedule_job(1, HubJob::ExchangeClears);
+    }
+
+    // Trades settle like any other energy sale, for the clearing price. Buyers hear about
+    // their fills before the energy gets to them.
+    pub fn clear_exchange(&mut self) {
+        let (timestamp, date, hour) = {
+            let timer = self.timer_state_ro.read().unwrap();
+            (timer.timestamp, timer.date.to_string(), timer.date.hour)
+        };
+        let round = self.exchange.clear(timestamp, date);
+
+        if let Some(price) = round.clearing_price {
+            for trade in round.trades.iter() {
+                let Trader::Factory(buyer_id) = trade.buyer else {
+                    continue;
+                };
+
+                match trade.seller {
+                    Trader::PovverPlant => {
+                        let offer = PPEnergyOffer {
+                            price_per_unit: price,
+                            units: trade.units,
+                            to_factory_id: buyer_id,
+                            tariff_window: self.config.tariff.window(hour),
+                        };
+                        if let Some(receipt) = self.factory_pays_pp_for_energy(&offer) {
+                            self.comms.hub_to_factory(buyer_id, HubFactorySignal::ExchangeBidFilled(receipt.clone()));
+                            self.pp_delivers_energy(receipt);
+                        }
+                    },
+                    Trader::Factory(seller_id) => {
+                        let offer = FactoryEnergyOffer {
+                            price_per_unit: price,
+                            units: trade.units,
+                            from_factory_id: seller_id,
+                            to_factory_id: buyer_id,
+                        };
+                        if let Some(receipt) = self.factory_sells_energy(&offer) {
+                            self.comms.hub_to_factory(buyer_id, HubFactorySignal::ExchangeBidFilled(receipt.clone()));
+                            self.comms.hub_to_factory(buyer_id, HubFactorySignal::EnergyTransfered(receipt));
+                        }
+                    },
+                }
+            }
+
+            self.econ_state.write().unwrap().exchange_price = Some(price);
+            self.log_ui_console(format!("Energy exchange cleared {} units at {} per EU.", round.volume(), price), Info);
+        } else if !round.bids.is_empty() {
+            self.log_ui_console(format!("Energy exchange had {} bids and {} asks. None of the bids met an ask.", round.bids.len(), round.asks.len()), Info);
+        }
+
+        self.exchange_recorder.record(round);
     }
 
     pub fn factory_will_produce(&mut self, fid: usize, demand: &ProductDemand, units: SimInt, unit_cost: SimFlo) {
@@ -159,11 +287,7 @@ impl TheHub {
                     total_price: total_cost_ex_energy.val(),
                 };
 
-                self.minutely_jobs.push(MinutelyJob {
-                    kind: MinutelyJobKind::FactoryProducesProduct(receipt),
-                    delay,
-                    timestamp: self.timer_state_ro.read().unwrap().timestamp,
-                });
+                self.schedule_job(delay, HubJob::FactoryProducesProduct(receipt));
             } else {
                 factory.write().unwrap().is_bankrupt = true;
                 self.log_ui_console(
@@ -182,10 +306,11 @@ impl TheHub {
 
     pub fn factory_buys_solar_panels(&mut self, fid: usize, panels_count: usize) {
         if let Some(factory) = self.get_factory_state(fid) {
-            let fee = panels_count as SimFlo * SOLAR_PANEL_PRICE;
+            let fee = panels_count as SimFlo * self.config.solar_panel.price;
+            let max_solar_panels = self.config.factory.max_solar_panels;
             let current_panels_count = factory.read().unwrap().solarpanels.len();
-            let amount_purchasable = if current_panels_count + panels_count >= FACTORY_MAX_SOLAR_PANELS {
-                FACTORY_MAX_SOLAR_PANELS - (current_panels_count - panels_count)
+            let amount_purchasable = if current_panels_count + panels_count >= max_solar_panels {
+                max_solar_panels.saturating_sub(current_panels_count)
             } else {
                 panels_count
             };
@@ -195,11 +320,7 @@ impl TheHub {
                 if transaction_successful {
                     let delay = (panels_count as SimFlo / 6.0).ceil() as SimInt;
 
-                    self.daily_jobs.push(DailyJob {
-                        kind: DailyJobKind::FactoryBoughtSolarpanels(fid, panels_count),
-                        delay,
-                        timestamp: self.timer_state_ro.read().unwrap().timestamp,
-                    });
+                    self.schedule_job(delay * 60 * 24, HubJob::FactoryBoughtSolarpanels(fid, panels_count));
                     self.log_ui_console(format!("Factory No. {} bought {} units of solar panels. ETA is {} day(s)", fid, panels_count, delay), Info);
                     factory.write().unwrap().is_awaiting_solarpanels = true;
                 } else {
@@ -207,7 +328,7 @@ impl TheHub {
                     self.log_ui_console(format!("Factory No. {} has gone bankrupt. It can't even pay for {} freaking solar panels!", fid, panels_count), Critical);
                 }
             } else {
-                self.log_ui_console(format!("Factory No. {} has reached it's solarpanel limit of {FACTORY_MAX_SOLAR_PANELS}. It can't buy another one!", fid), Warning);
+                self.log_ui_console(format!("Factory No. {} has reached it's solarpanel limit of {max_solar_panels}. It can't buy another one!", fid), Warning);
             }
         } else {
             self.log_console(format!("Factory No. {} is not found. So it can't buy any solar panels now, can it?", fid), Error);
@@ -215,20 +336,121 @@ impl TheHub {
 
     }
 
+    pub fn factory_buys_wind_turbines(&mut self, fid: usize, turbines_count: usize) {
+        if let Some(factory) = self.get_factory_state(fid) {
+            let max_wind_turbines = self.config.factory.max_wind_turbines;
+            let current_turbines_count = factory.read().unwrap().windturbines.len();
+            let amount_purchasable = turbines_count.min(max_wind_turbines.saturating_sub(current_turbines_count));
+
+            if amount_purchasable > 0 {
+                let fee = amount_purchasable as SimFlo * self.config.wind_turbine.price;
+                let transaction_successful = factory.write().unwrap().balance.dec(fee.val());
+                if transaction_successful {
+                    // Turbines go up two at a time.
+                    let delay = (amount_purchasable as SimFlo / 2.0).ceil() as SimInt;
+
+                    self.schedule_job(delay * 60 * 24, HubJob::FactoryBoughtWindTurbines(fid, amount_purchasable));
+                    self.log_ui_console(format!("Factory No. {} bought {} wind turbines. ETA is {} day(s)", fid, amount_purchasable, delay), Info);
+                    factory.write().unwrap().is_awaiting_windturbines = true;
+                } else {
+                    factory.write().unwrap().is_bankrupt = true;
+                    self.log_ui_console(format!("Factory No. {} has gone bankrupt. It couldn't pay for {} wind turbines.", fid, amount_purchasable), Critical);
+                }
+            } else {
+                self.log_ui_console(format!("Factory No. {} has reached it's wind turbine limit of {max_wind_turbines}. It can't buy another one!", fid), Warning);
+            }
+        } else {
+            self.log_console(format!("Factory No. {} is not found. So it can't buy any wind turbines.", fid), Error);
+        }
+    }
+
+    pub fn factory_buys_batteries(&mut self, fid: usize, batteries_count: usize) {
+        if let Some(factory) = self.get_factory_state(fid) {
+            let max_batteries = self.config.factory.max_batteries;
+            let current_batteries_count = factory.read().unwrap().batteries.len();
+            let amount_purchasable = batteries_count.min(max_batteries.saturating_sub(current_batteries_count));
+
+            if amount_purchasable > 0 {
+                let fee = amount_purchasable as SimFlo * self.config.battery.price;
+                let transaction_successful = factory.write().unwrap().balance.dec(fee.val());
+                if transaction_successful {
+                    let delay = 1;
+
+                    self.schedule_job(delay * 60 * 24, HubJob::FactoryBoughtBatteries(fid, amount_purchasable));
+                    self.log_ui_console(format!("Factory No. {} bought {} batteries. ETA is {} day(s)", fid, amount_purchasable, delay), Info);
+                    factory.write().unwrap().is_awaiting_batteries = true;
+                } else {
+                    factory.write().unwrap().is_bankrupt = true;
+                    self.log_ui_console(format!("Factory No. {} has gone bankrupt. It couldn't pay for {} batteries.", fid, amount_purchasable), Critical);
+                }
+            } else {
+                self.log_ui_console(format!("Factory No. {} has reached it's battery limit of {max_batteries}. It can't buy another one!", fid), Warning);
+            }
+        } else {
+            self.log_console(format!("Factory No. {} is not found. So it can't buy any batteries.", fid), Error);
+        }
+    }
+
+    // Batteries take what they can of the energy within their charge rates and room.
+    pub fn factory_charges_batteries(&mut self, fid: usize, energy: SimInt) {
+        let Some(factory) = self.get_factory_state(fid) else {
+            self.log_console(format!("Factory No. {} is not found. Battery charge canceled.", fid), Error);
+            return;
+        };
+
+        let mut fac = factory.write().unwrap();
+        let mut left = energy.min(fac.available_energy.val());
+        let mut charged = 0;
+        for battery in fac.batteries.iter_mut() {
+            let taken = battery.charge(left, &self.config.battery);
+            left -= taken;
+            charged += taken;
+        }
+        fac.available_energy.dec(charged);
+    }
+
+    pub fn factory_draws_from_batteries(&mut self, fid: usize, energy: SimInt) {
+        let Some(factory) = self.get_factory_state(fid) else {
+            self.log_console(format!("Factory No. {} is not found. Battery discharge canceled.", fid), Error);
+            return;
+        };
+
+        let drawn = {
+            let mut fac = factory.write().unwrap();
+            let mut left = energy;
+            let mut drawn = 0;
+            for battery in fac.batteries.iter_mut() {
+                let given = battery.discharge(left, &self.config.battery);
+                left -= given;
+                drawn += given;
+            }
+            fac.available_energy.inc(drawn);
+
+            drawn
+        };
+
+        if drawn > 0 {
+            self.comms.hub_to_factory(fid, HubFactorySignal::BatteriesDischarged(drawn));
+        }
+    }
+
     pub fn factory_sells_product(&mut self, fid: usize, stock_index: usize, unit_price: SimFlo) {
         if let Some(factory) = self.get_factory_state(fid) {
             if factory.read().unwrap().product_stocks.get(stock_index).is_some() {
                 let mut fac = factory.write().unwrap();
                 let stock = fac.product_stocks.remove(stock_index);
-                if let Some(demand) = self.econ_state.write().unwrap().product_demands.iter_mut().find(|demand| demand.product == stock.product) {
+                let mut econ = self.econ_state.write().unwrap();
+                if let Some(demand) = econ.product_demands.iter_mut().find(|demand| demand.product == stock.product) {
                     // TODO: A more complicated code to determine how many to buy would be better.
                     // For now all units are bought at the price set by the factory.
                     let met_percent = Percentage::new(stock.units as SimFlo / demand.units as SimFlo * 100.0);
+                    let sold_units = stock.units.clamp(0, demand.units.max(0)) as u64;
                     demand.demand_meet_percent = met_percent;
                     demand.units -= stock.units;
                     demand.percent.set(demand.percent.val() - (demand.percent.val() * met_percent.as_factor()));
                     let total_price = stock.units as SimFlo * unit_price;
                     fac.balance.inc(total_price.val());
+                    econ.sold_units_total += sold_units;
 
                     self.log_ui_console(format!("Factory No. {} sold {} units of {} for a total price of {}.", fid, stock.units, stock.product.name, total_price.val()), Info);
                 }
@@ -1,145 +1,102 @@
-use std::sync::Arc;
+use serde::{Serialize, Deserialize};
 use crate::{
     logger::{Logger, LogLevel::*},
     simulation::{
         SimInt,
         SimFlo,
         hub::TheHub,
-        sim_constants::*,
-        hub_comms::*
+        hub_comms::*,
+        scheduler::JobId,
     },
     economy::{
         products::ProductStock,
     },
 };
 use crate::economy::solarpanel::SolarPanel;
+use crate::economy::windturbine::WindTurbine;
+use crate::economy::battery::Battery;
 use crate::simulation::timer::TimerEvent;
 
-#[derive(Debug, Clone)]
-pub enum MinutelyJobKind {
+// Everything the hub delivers some time after it was paid for, and the deadlines it keeps.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum HubJob {
     PPProducesEnergy(EnergyReceipt),
-    FactoryProducesProduct(ProductionReceipt),
-    FactoryProducedRenewableEnergy(usize, SimInt),
-}
-
-#[derive(Debug, Clone)]
-pub struct MinutelyJob {
-    pub kind: MinutelyJobKind,
-    pub delay: SimInt,
-    pub timestamp: u128,
-}
-
-#[derive(Debug, Clone)]
-pub enum HourlyJobKind {
     PPBoughtFuel(FuelReceipt),
-}
-
-#[derive(Debug, Clone)]
-pub struct HourlyJob {
-    pub kind: HourlyJobKind,
-    pub delay: SimInt,
-    pub timestamp: u128,
-}
-
-#[derive(Debug, Clone)]
-pub enum DailyJobKind {
     PPFuelCapIncrease,
     PPProductionCapIncrease,
-    FactoryBoughtSolarpanels(usize, usize)
+    FactoryProducesProduct(ProductionReceipt),
+    // Solar and wind energy
+    FactoryProducedRenewableEnergy(usize, SimInt, SimInt),
+    FactoryBoughtSolarpanels(usize, usize),
+    FactoryBoughtWindTurbines(usize, usize),
+    FactoryBoughtBatteries(usize, usize),
+    FactoryEnergyOffersClose(usize),
+    ExchangeClears,
 }
 
-#[derive(Debug, Clone)]
-pub struct DailyJob {
-    pub kind: DailyJobKind,
-    pub delay: SimInt,
-    pub timestamp: u128,
+impl HubJob {
+    // The factory the job delivers to, if it's for a factory.
+    pub fn factory_id(&self) -> Option<usize> {
+        match self {
+            Self::PPProducesEnergy(receipt) => Some(receipt.factory_id),
+            Self::FactoryProducesProduct(receipt) => Some(receipt.factory_id),
+            Self::FactoryProducedRenewableEnergy(fid, _, _) => Some(*fid),
+            Self::FactoryBoughtSolarpanels(fid, _) | Self::FactoryBoughtWindTurbines(fid, _) | Self::FactoryBoughtBatteries(fid, _) => Some(*fid),
+            Self::FactoryEnergyOffersClose(fid) => Some(*fid),
+            Self::PPBoughtFuel(_) | Self::PPFuelCapIncrease | Self::PPProductionCapIncrease | Self::ExchangeClears => None,
+        }
+    }
 }
 
 impl TheHub {
-    pub fn do_minutely_jobs(&mut self) {
+    // Schedules a job to be done the given number of minutes from now.
+    pub fn schedule_job(&mut self, delay_minutes: SimInt, job: HubJob) -> JobId {
         let now = self.timer_state_ro.read().unwrap().timestamp;
 
-        let mut due_jobs = Vec::new();
-        self.minutely_jobs
-            .retain_mut(|job| {
-                if (job.timestamp + job.delay as u128) <= now {
-                    due_jobs.push(job.clone());
-                    return false;
-                }
-
-                true
-            });
-
-        for job in due_jobs.drain(..) {
-            match job.kind {
-                MinutelyJobKind::PPProducesEnergy(receipt) => {
-                    self.pp_energy_to_factory(receipt);
-                }
-                MinutelyJobKind::FactoryProducesProduct(receipt) => {
-                    self.factory_produce(receipt);
-                }
-                MinutelyJobKind::FactoryProducedRenewableEnergy(fid, energy) => {
-                    self.renewable_energy_to_factory(fid, energy);
-                }
-            }
-        }
+        self.scheduler.schedule(now + delay_minutes.max(0) as u128, job)
     }
 
-    pub fn do_hourly_jobs(&mut self, event: &TimerEvent) {
+    pub fn do_due_jobs(&mut self) {
         let now = self.timer_state_ro.read().unwrap().timestamp;
 
-        let mut due_jobs = Vec::new();
-        self.hourly_jobs
-            .retain_mut(|job| {
-                if (job.timestamp + (job.delay * 60) as u128) <= now {
-                    due_jobs.push(job.clone());
-                    return false;
+        while let Some(scheduled) = self.scheduler.pop_due(now) {
+            match scheduled.job {
+                HubJob::PPProducesEnergy(receipt) => {
+                    self.povver_plant_state.write().unwrap().committed_energy -= receipt.units;
+                    self.pp_energy_to_factory(receipt);
                 }
-
-                true
-            });
-
-        for job in due_jobs.drain(..) {
-            match job.kind {
-                HourlyJobKind::PPBoughtFuel(receipt) => {
+                HubJob::PPBoughtFuel(receipt) => {
                     self.transfer_fuel_to_pp(receipt);
                 }
-            }
-        }
-
-        self.factories_renewable_produce_energy(event);
-    }
-
-    pub fn do_daily_jobs(&mut self) {
-        let now = self.timer_state_ro.read().unwrap().timestamp;
-
-        let mut due_jobs = Vec::new();
-        self.daily_jobs
-            .retain_mut(|job| {
-                if (job.timestamp + (job.delay * 60 * 24) as u128) <= now {
-                    due_jobs.push(job.clone());
-                    return false;
-                }
-
-                true
-            });
-
-        for job in due_jobs.drain(..) {
-            match job.kind {
-                DailyJobKind::PPFuelCapIncrease => {
+                HubJob::PPFuelCapIncrease => {
                     self.increase_pp_fuel_cap();
                 }
-                DailyJobKind::PPProductionCapIncrease => {
+                HubJob::PPProductionCapIncrease => {
                     self.increase_pp_prod_cap();
                 }
-                DailyJobKind::FactoryBoughtSolarpanels(fid, count) => {
+                HubJob::FactoryProducesProduct(receipt) => {
+                    self.factory_produce(receipt);
+                }
+                HubJob::FactoryProducedRenewableEnergy(fid, solar_energy, wind_energy) => {
+                    self.renewable_energy_to_factory(fid, solar_energy, wind_energy);
+                }
+                HubJob::FactoryBoughtSolarpanels(fid, count) => {
                     self.solar_panel_to_factory(fid, count);
                 }
+                HubJob::FactoryBoughtWindTurbines(fid, count) => {
+                    self.wind_turbine_to_factory(fid, count);
+                }
+                HubJob::FactoryBoughtBatteries(fid, count) => {
+                    self.battery_to_factory(fid, count);
+                }
+                HubJob::FactoryEnergyOffersClose(fid) => {
+                    self.comms.hub_to_factory(fid, HubFactorySignal::EnergyOffersClosed);
+                }
+                HubJob::ExchangeClears => {
+                    self.clear_exchange();
+                }
             }
         }
-
-        // If the factory doesn't use it's available energy in a day, it will be expired the next day.
-        self.factories_energy_expired();
     }
 
     pub fn transfer_fuel_to_pp(&self, receipt: FuelReceipt) {
@@ -148,36 +105,42 @@ impl TheHub {
         let mut pp = self.povver_plant_state.write().unwrap();
         pp.fuel += receipt.units.clamp(0, pp.fuel_capacity);
         pp.is_awaiting_fuel = false;
-        self.comms.hub_to_pp(Arc::new(HubPPSignal::FuelTransfered(receipt)));
+        self.comms.hub_to_pp(HubPPSignal::FuelTransfered(receipt));
     }
 
     pub fn increase_pp_fuel_cap(&self) {
-        self.log_ui_console(format!("Increasing povver plant fuel capacity by {PP_FUEL_CAPACITY_INCREASE}."), Info);
-        self.povver_plant_state.write().unwrap().fuel_capacity += PP_FUEL_CAPACITY_INCREASE;
+        let increase = self.config.povver_plant.fuel_capacity_increase;
+        self.log_ui_console(format!("Increasing povver plant fuel capacity by {increase}."), Info);
+        self.povver_plant_state.write().unwrap().fuel_capacity += increase;
         self.povver_plant_state.write().unwrap().is_awaiting_fuel_capacity = false;
-        self.comms.hub_to_pp(Arc::new(HubPPSignal::FuelCapacityIncreased));
+        self.comms.hub_to_pp(HubPPSignal::FuelCapacityIncreased);
     }
 
     pub fn increase_pp_prod_cap(&self) {
-        self.log_ui_console(format!("Increasing povver plant production capacity by {}.", PP_PRODUCTION_CAPACITY_INCREASE), Info);
-        self.povver_plant_state.write().unwrap().production_capacity.inc(PP_PRODUCTION_CAPACITY_INCREASE);
+        let increase = self.config.povver_plant.production_capacity_increase;
+        self.log_ui_console(format!("Increasing povver plant production capacity by {increase}."), Info);
+        self.povver_plant_state.write().unwrap().production_capacity.inc(increase);
         self.povver_plant_state.write().unwrap().is_awaiting_production_capacity = false;
-        self.comms.hub_to_pp(Arc::new(HubPPSignal::ProductionCapacityIncreased));
+        self.comms.hub_to_pp(HubPPSignal::ProductionCapacityIncreased);
     }
 
     pub fn pp_energy_to_factory(&self, receipt: EnergyReceipt) {
         let fid = receipt.factory_id;
 
         if let Some(factory) = self.get_factory_state(fid) {
-            factory.write().unwrap().available_energy.inc(receipt.units);
+            {
+                let mut fac = factory.write().unwrap();
+                fac.available_energy.inc(receipt.units);
+                fac.energy_from_pp_total += receipt.units.max(0) as u64;
+            }
             self.povver_plant_state.write().unwrap().balance.inc(receipt.total_price);
-            let fuel_needed = receipt.units / PP_ENERGY_PER_FUEL;
+            let fuel_needed = receipt.units / self.config.povver_plant.energy_per_fuel;
             self.povver_plant_state.write().unwrap().fuel -= fuel_needed;
 
             self.log_ui_console(format!("Energy of {} units transfered to Factory No. {} from Povver Plant.", receipt.units, fid), Info);
 
-            self.comms.hub_to_factory(fid, Arc::new(HubFactorySignal::EnergyTransfered(receipt.clone())));
-            self.comms.hub_to_pp(Arc::new(HubPPSignal::EnergyTransfered(receipt)));
+            self.comms.hub_to_factory(fid, HubFactorySignal::EnergyTransfered(receipt.clone()));
+            self.comms.hub_to_pp(HubPPSignal::EnergyTransfered(receipt));
         } else {
             self.log_console(format!("Factory No. {} is not found. PP energy transfer canceled.", fid), Error);
         }
@@ -194,7 +157,7 @@ impl TheHub {
                 unit_production_cost: receipt.price_per_unit,
             });
             self.log_ui_console(format!("Factory No. {} produced {} {}", fid, units, receipt.demand.product.name), Info);
-            self.comms.hub_to_factory(fid, Arc::new(HubFactorySignal::ProductionComplete(receipt)));
+            self.comms.hub_to_factory(fid, HubFactorySignal::ProductionComplete(receipt));
         } else {
             self.log_console(format!("Factory No. {} is not found. {} production canceled.", fid, receipt.demand.product.name), Error);
         }
@@ -207,48 +170,74 @@ impl TheHub {
         }
     }
 
+    pub fn factories_batteries_new_hour(&self, event: &TimerEvent) {
+        for factory in self.factories_state.read().unwrap().iter() {
+            for battery in factory.write().unwrap().batteries.iter_mut() {
+                battery.new_hour(event, &self.config.battery);
+            }
+        }
+    }
+
     pub fn factories_renewable_produce_energy(&mut self, event: &TimerEvent) {
         let sunshine = self.env_state_ro.read().unwrap().the_sun.brightness;
+        let wind_speed = self.env_state_ro.read().unwrap().wind_speed.clone();
+        let now = self.timer_state_ro.read().unwrap().timestamp;
         for factory in self.factories_state.read().unwrap().iter() {
-            let mut total_energy = 0;
             let mut solar_energy = 0;
             for solarpanel in factory.write().unwrap().solarpanels.iter_mut() {
-                let energy = solarpanel.produce_energy(event, sunshine);
+                let energy = solarpanel.produce_energy(event, sunshine, &self.config.solar_panel);
                 solar_energy += energy;
             }
+            // Old panels come out negative in the dark. That shouldn't eat into the wind energy.
+            solar_energy = solar_energy.max(0);
 
-            //TODO: Wind turbines
+            let mut wind_energy = 0;
+            for windturbine in factory.write().unwrap().windturbines.iter_mut() {
+                wind_energy += windturbine.produce_energy(event, &wind_speed, &self.config.wind_turbine);
+            }
 
-            total_energy += solar_energy;
+            let total_energy = solar_energy + wind_energy;
             if total_energy > 0 {
-                let (fid, solar_count) = {
+                let (fid, solar_count, wind_count) = {
                     let state = factory.read().unwrap();
                     (
                         state.id,
                         state.solarpanels.len(),
+                        state.windturbines.len(),
                     )
                 };
                 let delay = (total_energy as SimFlo / 10.0).floor() as SimInt;
                 if delay == 0 {
-                    self.renewable_energy_to_factory(fid, total_energy);
-                    //TODO: Report both solar and wind turbines once we have the turbines
-                    self.log_ui_console(format!("Factory No. {} produced {} energy from {} solarpanels.", fid, total_energy, solar_count), Info);
+                    self.renewable_energy_to_factory(fid, solar_energy, wind_energy);
+                    self.log_ui_console(
+                        format!(
+                            "Factory No. {} produced {} energy from {} solarpanels and {} energy from {} wind turbines.",
+                            fid, solar_energy, solar_count, wind_energy, wind_count
+                        ), Info);
                 } else {
-                    self.minutely_jobs.push(MinutelyJob {
-                        kind: MinutelyJobKind::FactoryProducedRenewableEnergy(fid, total_energy),
-                        delay,
-                        timestamp: self.timer_state_ro.read().unwrap().timestamp,
-                    });
-                    //TODO: Report both solar and wind turbines once we have the turbines
-                    self.log_ui_console(format!("Factory No. {} is producing {} energy from {} solarpanels. ETA is {} minutes.", fid, total_energy, solar_count, delay), Info);
+                    self.scheduler.schedule(now + delay as u128, HubJob::FactoryProducedRenewableEnergy(fid, solar_energy, wind_energy));
+                    self.log_ui_console(
+                        format!(
+                            "Factory No. {} is producing {} energy from {} solarpanels and {} energy from {} wind turbines. ETA is {} minutes.",
+                            fid, solar_energy, solar_count, wind_energy, wind_count, delay
+                        ), Info);
                 }
             }
         }
     }
 
-    pub fn renewable_energy_to_factory(&self, fid: usize, energy: SimInt) {
-        self.get_factory_state(fid).unwrap().write().unwrap().available_energy.inc(energy);
-        self.comms.hub_to_factory(fid, Arc::new(HubFactorySignal::RenewableEnergyProduced));
+    pub fn renewable_energy_to_factory(&self, fid: usize, solar_energy: SimInt, wind_energy: SimInt) {
+        let Some(factory) = self.get_factory_state(fid) else {
+            self.log_console(format!("Factory No. {} is not found. Its renewable energy goes to waste.", fid), Error);
+            return;
+        };
+        {
+            let mut fac = factory.write().unwrap();
+            fac.available_energy.inc(solar_energy + wind_energy);
+            fac.energy_from_solar_total += solar_energy.max(0) as u64;
+            fac.energy_from_wind_total += wind_energy.max(0) as u64;
+        }
+        self.comms.hub_to_factory(fid, HubFactorySignal::RenewableEnergyProduced);
     }
 
     pub fn solar_panel_to_factory(&self, fid: usize, count: usize) {
@@ -262,4 +251,28 @@ impl TheHub {
             self.log_console(format!("Factory No. {} is not found. So it can't buy any solar panels, period.", fid), Error);
         }
     }
+
+    pub fn wind_turbine_to_factory(&self, fid: usize, count: usize) {
+        if let Some(factory) = self.get_factory_state(fid) {
+            let turbines = vec![WindTurbine::new(); count];
+
+            factory.write().unwrap().windturbines.extend(turbines);
+            factory.write().unwrap().is_awaiting_windturbines = false;
+            self.log_ui_console(format!("Factory No. {} installed {} wind turbines. Let it blow!", fid, count), Info);
+        } else {
+            self.log_console(format!("Factory No. {} is not found. So it can't put up any wind turbines.", fid), Error);
+        }
+    }
+
+    pub fn battery_to_factory(&self, fid: usize, count: usize) {
+        if let Some(factory) = self.get_factory_state(fid) {
+            let batteries = vec![Battery::new(&self.config.battery); count];
+
+            factory.write().unwrap().batteries.extend(batteries);
+            factory.write().unwrap().is_awaiting_batteries = false;
+            self.log_ui_console(format!("Factory No. {} installed {} batteries. Sunshine in a box!", fid, count), Info);
+        } else {
+            self.log_console(format!("Factory No. {} is not found. So it can't install any batteries.", fid), Error);
+        }
+    }
 }
@@ -0,0 +1,316 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use serde::{Serialize, Deserialize};
+
+use crate::{
+    app_state::StatePayload,
+    simulation::{
+        SimFlo,
+        SimInt,
+        timer::TimerEvent,
+    },
+    utils_traits::AsFactor,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum MetricsInterval {
+    #[default]
+    Hourly,
+    Daily,
+}
+
+impl MetricsInterval {
+    fn is_due(&self, event: &TimerEvent) -> bool {
+        match self {
+            Self::Hourly => event.at_least_hour(),
+            Self::Daily => event.at_least_day(),
+        }
+    }
+}
+
+impl FromStr for MetricsInterval {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "hourly" => Ok(Self::Hourly),
+            "daily" => Ok(Self::Daily),
+            _ => Err(format!("\"{s}\" is not a metrics interval, use hourly or daily")),
+        }
+    }
+}
+
+// CSV comes in the long form with one value per row, so the factories can come and go.
+// JSON Lines has one whole sample per line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MetricsFormat {
+    #[default]
+    Csv,
+    JsonLines,
+}
+
+impl MetricsFormat {
+    // Goes by the file extension, CSV if it's not a JSON Lines file.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("jsonl") | Some("ndjson") => Self::JsonLines,
+            _ => Self::Csv,
+        }
+    }
+}
+
+impl FromStr for MetricsFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "csv" => Ok(Self::Csv),
+            "jsonl" | "jsonlines" | "ndjson" => Ok(Self::JsonLines),
+            _ => Err(format!("\"{s}\" is not a metrics format, use csv or jsonl")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MetricsOptions {
+    pub interval: MetricsInterval,
+    // File the samples are written to as they are taken. They are only kept in memory without one.
+    pub path: Option<PathBuf>,
+    pub format: MetricsFormat,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FactorySample {
+    pub id: usize,
+    pub balance: SimFlo,
+    pub available_energy: SimInt,
+    pub stored_energy: SimInt,
+    pub stock_units: SimInt,
+    pub solar_panels: usize,
+    pub wind_turbines: usize,
+    pub batteries: usize,
+    // Energy traded with other factories since the start.
+    pub energy_from_factories: u64,
+    pub energy_to_factories: u64,
+    pub is_bankrupt: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PovverPlantSample {
+    pub fuel: SimInt,
+    pub fuel_capacity: SimInt,
+    pub production_capacity: SimInt,
+    pub balance: SimFlo,
+    pub is_bankrupt: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetricsSample {
+    pub timestamp: u128,
+    pub date: String,
+    pub inflation_rate: SimFlo,
+    pub fuel_price: SimFlo,
+    // Clearing price of the energy exchange's last round with any trades.
+    pub exchange_price: Option<SimFlo>,
+    pub active_demands: usize,
+    pub sun_brightness: SimFlo,
+    pub wind_speed: SimInt,
+    pub povver_plant: PovverPlantSample,
+    pub factories: Vec<FactorySample>,
+}
+
+impl MetricsSample {
+    pub fn take(state: &StatePayload) -> Self {
+        let (timestamp, date) = {
+            let timer = state.timer.read().unwrap();
+            (timer.timestamp, timer.date.to_string())
+        };
+        let (sun_brightness, wind_speed) = {
+            let env = state.env.read().unwrap();
+            (env.the_sun.brightness.val(), env.wind_speed.val())
+        };
+        let (inflation_rate, fuel_price, exchange_price, active_demands) = {
+            let econ = state.economy.read().unwrap();
+            (econ.inflation_rate, econ.fuel_price.val(), econ.exchange_price, econ.product_demands.len())
+        };
+        let povver_plant = {
+            let pp = state.povver_plant.read().unwrap();
+            PovverPlantSample {
+                fuel: pp.fuel,
+                fuel_capacity: pp.fuel_capacity,
+                production_capacity: pp.production_capacity.val(),
+                balance: pp.balance.val(),
+                is_bankrupt: pp.is_bankrupt,
+            }
+        };
+        let factories = state.factories.read().unwrap()
+            .iter()
+            .map(|factory| {
+                let fs = factory.read().unwrap();
+                FactorySample {
+                    id: fs.id,
+                    balance: fs.balance.val(),
+                    available_energy: fs.available_energy.val(),
+                    stored_energy: fs.stored_energy(),
+                    stock_units: fs.product_stocks.iter().map(|stock| stock.units).sum(),
+                    solar_panels: fs.solarpanels.len(),
+                    wind_turbines: fs.windturbines.len(),
+                    batteries: fs.batteries.len(),
+                    energy_from_factories: fs.energy_from_factories_total,
+                    energy_to_factories: fs.energy_to_factories_total,
+                    is_bankrupt: fs.is_bankrupt,
+                }
+            })
+            .collect();
+
+        Self {
+            timestamp,
+            date,
+            inflation_rate,
+            fuel_price,
+            exchange_price,
+            active_demands,
+            sun_brightness,
+            wind_speed,
+            povver_plant,
+            factories,
+        }
+    }
+
+    // Rows of subject, metric and value.
+    fn csv_rows(&self) -> Vec<(String, &'static str, String)> {
+        let world = |metric, value: String| ("world".to_string(), metric, value);
+        let pp = |metric, value: String| ("povver_plant".to_string(), metric, value);
+        let pp_sample = &self.povver_plant;
+
+        let mut rows = vec![
+            world("inflation_rate", self.inflation_rate.to_string()),
+            world("fuel_price", self.fuel_price.to_string()),
+            world("active_demands", self.active_demands.to_string()),
+            world("sun_brightness", self.sun_brightness.to_string()),
+            world("wind_speed", self.wind_speed.to_string()),
+            pp("fuel", pp_sample.fuel.to_string()),
+            pp("fuel_capacity", pp_sample.fuel_capacity.to_string()),
+            pp("production_capacity", pp_sample.production_capacity.to_string()),
+            pp("balance", pp_sample.balance.to_string()),
+            pp("is_bankrupt", (pp_sample.is_bankrupt as u8).to_string()),
+        ];
+        if let Some(exchange_price) = self.exchange_price {
+            rows.insert(2, world("exchange_price", exchange_price.to_string()));
+        }
+        for factory in &self.factories {
+            let subject = format!("factory_{}", factory.id);
+            rows.extend([
+                (subject.clone(), "balance", factory.balance.to_string()),
+                (subject.clone(), "available_energy", factory.available_energy.to_string()),
+                (subject.clone(), "stored_energy", factory.stored_energy.to_string()),
+                (subject.clone(), "stock_units", factory.stock_units.to_string()),
+                (subject.clone(), "solar_panels", factory.solar_panels.to_string()),
+                (subject.clone(), "wind_turbines", factory.wind_turbines.to_string()),
+                (subject.clone(), "batteries", factory.batteries.to_string()),
+                (subject.clone(), "energy_from_factories", factory.energy_from_factories.to_string()),
+                (subject.clone(), "energy_to_factories", factory.energy_to_factories.to_string()),
+                (subject, "is_bankrupt", (factory.is_bankrupt as u8).to_string()),
+            ]);
+        }
+
+        rows
+    }
+}
+
+// As many samples as a year has hours are kept in memory, the metrics file gets them all.
+const KEPT_SAMPLES: usize = 24 * 366;
+
+// Takes a sample on every interval and keeps the latest ones. With a path every sample is also written out
+// as it comes, so a run that dies on the way still leaves its metrics behind.
+pub struct MetricsRecorder {
+    options: MetricsOptions,
+    samples: Vec<MetricsSample>,
+    writer: Option<BufWriter<File>>,
+}
+
+impl MetricsRecorder {
+    pub fn new(options: MetricsOptions) -> io::Result<Self> {
+        let writer = match &options.path {
+            Some(path) => {
+                let mut writer = BufWriter::new(File::create(path)?);
+                if options.format == MetricsFormat::Csv {
+                    writeln!(writer, "timestamp,date,subject,metric,value")?;
+                }
+                Some(writer)
+            },
+            None => None,
+        };
+
+        Ok(Self {
+            options,
+            samples: Vec::new(),
+            writer,
+        })
+    }
+
+    pub fn maybe_sample(&mut self, event: &TimerEvent, state: &StatePayload) {
+        if !self.options.interval.is_due(event) {
+            return;
+        }
+
+        let sample = MetricsSample::take(state);
+        if let Err(e) = self.write(&sample) {
+            eprintln!("METRICS: Could not write to {}: {e}. Keeping the samples in memory only.", self.path_display());
+            self.writer = None;
+        }
+        if self.samples.len() >= KEPT_SAMPLES {
+            self.samples.remove(0);
+        }
+        self.samples.push(sample);
+    }
+
+    fn write(&mut self, sample: &MetricsSample) -> io::Result<()> {
+        let Some(writer) = self.writer.as_mut() else {
+            return Ok(());
+        };
+
+        match self.options.format {
+            MetricsFormat::Csv => {
+                for (subject, metric, value) in sample.csv_rows() {
+                    writeln!(writer, "{},{},{subject},{metric},{value}", sample.timestamp, sample.date)?;
+                }
+            },
+            MetricsFormat::JsonLines => {
+                serde_json::to_writer(&mut *writer, sample)?;
+                writeln!(writer)?;
+            },
+        }
+
+        Ok(())
+    }
+
+    pub fn flush(&mut self) {
+        if let Some(writer) = self.writer.as_mut()
+            && let Err(e) = writer.flush()
+        {
+            eprintln!("METRICS: Could not write to {}: {e}", self.path_display());
+        }
+    }
+
+    pub fn samples(&self) -> &[MetricsSample] {
+        &self.samples
+    }
+
+    fn path_display(&self) -> String {
+        self.options.path.as_ref().map(|path| path.display().to_string()).unwrap_or_default()
+    }
+}
+
+// Reads back the samples of a JSON Lines metrics file.
+pub fn read_json_lines(path: &Path) -> io::Result<Vec<MetricsSample>> {
+    BufReader::new(File::open(path)?)
+        .lines()
+        .filter(|line| line.as_ref().map_or(true, |line| !line.trim().is_empty()))
+        .map(|line| serde_json::from_str(&line?).map_err(io::Error::from))
+        .collect()
+}
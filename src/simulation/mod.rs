@@ -1,5 +1,7 @@
 use std::{
-    sync::{Arc, Mutex},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+    thread,
 };
 
 use tokio::sync::broadcast as tokio_broadcast;
@@ -8,29 +10,49 @@ pub mod speed;
 use speed::SPEEDS_ARRAY;
 
 pub mod hub;
-use hub::TheHub;
+use hub::{TheHub, HubLinks};
 pub mod hub_comms;
 mod hub_events;
-mod hub_jobs;
+pub mod hub_jobs;
+use hub_jobs::HubJob;
+pub mod scheduler;
+use scheduler::ScheduledJob;
+pub mod stepper;
+use stepper::Stepper;
+pub mod snapshot;
+use snapshot::{Snapshot, SnapshotError, FactoryEntry, SNAPSHOT_VERSION};
+pub mod autosave;
+use autosave::{Autosave, AutosaveOptions};
+pub mod metrics;
+pub mod exchange;
+pub mod batch;
+pub mod sweep;
+use metrics::{MetricsRecorder, MetricsOptions, MetricsSample};
+use exchange::{ExchangeRecorder, ClearingRound};
+pub mod scenario;
+use scenario::{Scenario, ScenarioError, FactoryScenario, FactoryStrategy};
 pub mod sim_constants;
+pub mod calendar;
 
 pub mod timer;
 pub mod sim_types;
-mod test_factories;
 
 pub use sim_types::*;
 
 use timer::{Timer, TimerEvent};
 
 use crate::{
-    app_state::{AppState, Misc, MiscStateData},
+    app_state::{AppState, Misc, MiscStateData, StatePayload, FactoryStateData},
     economy::Economy,
     environment::Environment,
-    ui_controller::{Date, UIController, UIFlag},
+    headless_controller::HeadlessController,
     utils_data::ReadOnlyRwLock,
+    utils_random::{SeedBank, RngStream},
     logger::LogMessage,
-    simulation::test_factories::TEST_INDUSTRIES,
+    config::{Config, ConfigError},
 };
+#[cfg(feature = "gui")]
+use crate::ui_controller::UIController;
 
 #[derive(Debug, Clone)]
 pub enum EconUpdate {
@@ -42,182 +64,788 @@ pub enum EconUpdate {
 pub enum StateAction {
     Timer(TimerEvent),
     SpeedChange(TickDuration),
+    ClockModeChange(ClockMode),
     EconUpdate(EconUpdate),
     Env,
+    // A factory was added or retired.
+    Factories,
     Misc,
     Quit
 }
 
+pub enum UIFlag {
+    Pause,
+    Quit,
+    SpeedChange(SimInt),
+    // A new factory opens, or one closes down by its id.
+    AddFactory(FactoryScenario),
+    RetireFactory(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RunMode {
+    #[cfg(feature = "gui")]
+    Gui,
+    Headless,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LogOutput {
+    Stdout,
+    File(PathBuf),
+}
+
+// A run that didn't end well. Names the threads that went down on the way.
+#[derive(Debug)]
+pub struct RunError {
+    pub crashed_threads: Vec<String>,
+}
+
+impl std::fmt::Display for RunError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "threads crashed during the run: {}", self.crashed_threads.join(", "))
+    }
+}
+
+impl std::error::Error for RunError {}
+
+// Why a simulation couldn't be built.
+#[derive(Debug)]
+pub enum SetupError {
+    Config(ConfigError),
+    Scenario(ScenarioError),
+    // A file the results go into couldn't be created.
+    Output(PathBuf, std::io::Error),
+}
+
+impl std::fmt::Display for SetupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Config(e) => write!(f, "can't start with this config: {e}"),
+            Self::Scenario(e) => write!(f, "can't start with this scenario: {e}"),
+            Self::Output(path, e) => write!(f, "can't write to {}: {e}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for SetupError {}
+
+impl From<ConfigError> for SetupError {
+    fn from(e: ConfigError) -> Self {
+        Self::Config(e)
+    }
+}
+
+impl From<ScenarioError> for SetupError {
+    fn from(e: ScenarioError) -> Self {
+        Self::Scenario(e)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SimOptions {
+    pub run_mode: RunMode,
+    // Where the headless mode writes its progress reports and log messages.
+    pub log_output: LogOutput,
+    // Master seed of every random number stream. Falls back to the scenario's seed,
+    // and a random one is picked if neither has it.
+    pub seed: Option<u64>,
+    // Stepped clock makes every step handle exactly one simulated minute, independent of wall time.
+    pub clock_mode: ClockMode,
+    // Index into SPEEDS_ARRAY to start with. The last one is the max speed.
+    pub speed_index: usize,
+    // Periodic snapshots of the world into rotating files. Off if not given.
+    pub autosave: Option<AutosaveOptions>,
+    // Samples of the agents, the economy and the weather over time. Off if not given.
+    pub metrics: Option<MetricsOptions>,
+    // File every clearing round of the energy exchange is written to as JSON Lines.
+    // The rounds are only kept in memory without one.
+    pub exchange_log: Option<PathBuf>,
+    // Balance knobs of the economy and the agents.
+    pub config: Config,
+    // Factories, the Povver Plant and the start date. It must pass `Scenario::validate` with the config.
+    pub scenario: Scenario,
+    // `run` quits by itself after this many simulated days. Runs until quit if not given.
+    pub run_days: Option<u32>,
+    // With the stepped clock, steps jump over the minutes where nothing can happen.
+    // The world turns out the same as going minute by minute, only faster.
+    pub skip_quiet_minutes: bool,
+}
+
+impl Default for SimOptions {
+    fn default() -> Self {
+        Self {
+            #[cfg(feature = "gui")]
+            run_mode: RunMode::Gui,
+            #[cfg(not(feature = "gui"))]
+            run_mode: RunMode::Headless,
+            log_output: LogOutput::Stdout,
+            seed: None,
+            clock_mode: ClockMode::RealTime,
+            speed_index: 0,
+            autosave: None,
+            metrics: None,
+            exchange_log: None,
+            config: Config::default(),
+            scenario: Scenario::default(),
+            run_days: None,
+            skip_quiet_minutes: false,
+        }
+    }
+}
+
 pub struct Simulation {
+    options: SimOptions,
+    seeds: SeedBank,
     app_state: AppState,
     timer: Timer,
     env: Environment,
     economy: Economy,
+    #[cfg(feature = "gui")]
     ui_controller: UIController,
     the_hub: Arc<Mutex<TheHub>>,
+    stepper: Stepper,
+    // Clock mode the actors are in right now. Max speed switches them to the stepped clock.
+    clock_mode: ClockMode,
+    join_handles: Vec<thread::JoinHandle<()>>,
     is_running: bool,
+    // Restored from a snapshot, so the world is already on its way.
+    is_restored: bool,
+    autosave: Option<Autosave>,
+    metrics: Option<MetricsRecorder>,
+    // Threads that panicked, found out while joining them on shutdown.
+    crashed_threads: Vec<String>,
     ui_log_channel: (tokio_broadcast::Sender<LogMessage>, tokio_broadcast::Receiver<LogMessage>),
+    wakeup_channel: (tokio_broadcast::Sender<StateAction>, tokio_broadcast::Receiver<StateAction>),
+    ui_flag_channel: (crossbeam_channel::Sender<UIFlag>, crossbeam_channel::Receiver<UIFlag>),
 }
 
 impl Simulation {
-    pub fn new() -> Self {
+    /// Builds the timer, environment, economy and the hub with its factories and Povver Plant.
+    /// Nothing runs until `start` or `run` is called. Fails if the config or the scenario doesn't hold up.
+    pub fn new(options: SimOptions) -> Result<Self, SetupError> {
         let ui_log_channel = tokio_broadcast::channel(128);
+        let wakeup_channel = tokio_broadcast::channel::<StateAction>(64);
+        let ui_flag_channel = crossbeam_channel::bounded::<UIFlag>(16);
+        #[cfg(feature = "gui")]
         let ui_controller = UIController::new();
 
-        let speed_index = 0;
-        let init_date = Date {
-            minute: 0,
-            hour: 12,
-            day: 1,
-            month: 7,
-            year: 2025,
-        };
+        options.config.validate()?;
+        options.scenario.validate(&options.config)?;
+        let config = Arc::new(options.config.clone());
+
+        let seeds = options.seed.or(options.scenario.seed).map(SeedBank::new).unwrap_or_else(SeedBank::from_entropy);
+
+        let speed_index = options.speed_index.min(SPEEDS_ARRAY.len() - 1);
+        let clock_mode = Self::clock_mode_for(&options, speed_index);
+        let init_date = options.scenario.start_date.clone();
         let is_paused = true;
 
-        let (mut timer, timer_state) = Timer::new(SPEEDS_ARRAY[speed_index].get_tick_duration(), init_date);
+        let (mut timer, timer_state) = Timer::new(SPEEDS_ARRAY[speed_index].get_tick_duration(), options.config.calendar, init_date);
         timer.tick(is_paused);
 
-        let (mut env, env_state) = Environment::new(Arc::clone(&timer_state));
+        let (mut env, env_state) = Environment::new(Arc::clone(&timer_state), seeds.stream(RngStream::Environment));
         env.update();
 
-        let (economy, economy_state) = Economy::new();
+        let (economy, economy_state) = Economy::new(seeds.stream(RngStream::Economy), Arc::clone(&config));
+
+        let mut stepper = Stepper::new();
 
         let misc_state = Arc::new(Mutex::new(MiscStateData {
             is_paused,
             speed_index,
         }));
 
+        let exchange_recorder = ExchangeRecorder::new(options.exchange_log.clone())
+            .map_err(|e| SetupError::Output(options.exchange_log.clone().unwrap_or_default(), e))?;
+
+        let hub_links = HubLinks {
+            econ_state: Arc::clone(&economy_state),
+            timer_state_ro: ReadOnlyRwLock::from(timer_state.clone()),
+            env_state_ro: ReadOnlyRwLock::from(env_state.clone()),
+            ui_log_sender: ui_log_channel.0.clone(),
+        };
         let (the_hub, hub_state) = TheHub::new(
-            Arc::clone(&economy_state),
-            ReadOnlyRwLock::from(timer_state.clone()),
-            ReadOnlyRwLock::from(env_state.clone()),
-            ui_log_channel.0.clone()
+            hub_links,
+            &seeds,
+            &options.scenario,
+            exchange_recorder,
+            config,
+            clock_mode,
+            &mut stepper,
         );
         let the_hub = Arc::new(Mutex::new(the_hub));
 
         let app_state = AppState::new(timer_state, env_state, economy_state, hub_state, misc_state);
-
-        Self {
+        let autosave = options.autosave.clone().map(Autosave::new);
+        let metrics = options.metrics.clone()
+            .map(|metrics_options| {
+                let path = metrics_options.path.clone().unwrap_or_default();
+                MetricsRecorder::new(metrics_options).map_err(|e| SetupError::Output(path, e))
+            })
+            .transpose()?;
+
+        Ok(Self {
+            options,
+            seeds,
             app_state,
             timer,
             env,
             economy,
+            #[cfg(feature = "gui")]
             ui_controller,
             the_hub,
+            stepper,
+            clock_mode,
+            join_handles: Vec::new(),
             is_running: false,
+            is_restored: false,
+            autosave,
+            metrics,
+            crashed_threads: Vec::new(),
             ui_log_channel,
-        }
+            wakeup_channel,
+            ui_flag_channel,
+        })
+    }
+}
+
+// Channels for consumers other than the UI, like headless runners and tools.
+impl Simulation {
+    /// Every `StateAction` the simulation broadcasts to the hub and the UI.
+    pub fn subscribe_state_actions(&self) -> tokio_broadcast::Receiver<StateAction> {
+        self.wakeup_channel.1.resubscribe()
+    }
+
+    /// Log messages from the hub, the Povver Plant and the factories.
+    pub fn subscribe_log_messages(&self) -> tokio_broadcast::Receiver<LogMessage> {
+        self.ui_log_channel.1.resubscribe()
+    }
+
+    /// Sender for pause, speed change and quit requests, as the UI uses.
+    pub fn clone_flag_sender(&self) -> crossbeam_channel::Sender<UIFlag> {
+        self.ui_flag_channel.0.clone()
     }
 }
 
 impl Simulation {
+    fn clock_mode_for(options: &SimOptions, speed_index: usize) -> ClockMode {
+        // Without any wall clock pacing the actors could never keep up in real time.
+        if SPEEDS_ARRAY[speed_index].is_max() {
+            ClockMode::Stepped
+        } else {
+            options.clock_mode
+        }
+    }
+
     fn change_speed(&mut self, speed_index: SimInt) {
         self.app_state.set_misc(Misc::SpeedIndex(speed_index as usize));
         self.timer.set_tick_duration(SPEEDS_ARRAY[speed_index as usize].get_tick_duration());
     }
+
+    fn maybe_change_clock_mode(&mut self, speed_index: SimInt) {
+        let clock_mode = Self::clock_mode_for(&self.options, speed_index as usize);
+        if clock_mode == self.clock_mode {
+            return;
+        }
+
+        self.clock_mode = clock_mode;
+        self.broadcast_action(StateAction::ClockModeChange(clock_mode));
+        // The actors are waiting for their turns. One more round lets them see
+        // they're back on their own. Switching to stepped is settled by the next step.
+        if clock_mode == ClockMode::RealTime {
+            self.stepper.round();
+        }
+    }
+}
+
+impl Simulation {
+    // Only the real-time clock pauses. It's the clock mode of the moment that counts,
+    // since changing to max speed or back switches between the two.
+    fn is_paused(&self) -> bool {
+        self.clock_mode == ClockMode::RealTime
+            && self.app_state.misc.lock().unwrap().is_paused
+    }
+
+    fn is_skipping_quiet_minutes(&self) -> bool {
+        self.options.skip_quiet_minutes && self.clock_mode == ClockMode::Stepped
+    }
+
+    // The first minute after now where anything but the clock moves: a hub job is due, factories
+    // make their decisions, the energy exchange takes orders, or the hour changes. The environment, the demands and every hourly chore
+    // of the agents wait for the hour, so it never goes past that. Everyone has settled by now,
+    // so there is nothing on its way between the agents either.
+    fn next_eventful_minute(&self, now: u128) -> u128 {
+        let next_hour = (now / 60 + 1) * 60;
+        let next_job = self.the_hub.lock().unwrap().scheduler.next_due().unwrap_or(next_hour);
+
+        // Factories only produce for and sell to demands, which change on the hour or with their own deals.
+        let factories_decide = !self.app_state.economy.read().unwrap().product_demands.is_empty()
+            && self.app_state.hub.factories.read().unwrap().iter().any(|factory| !factory.read().unwrap().is_bankrupt);
+        let next_decision = if factories_decide {
+            (now + 1..next_hour).find(|minute| minute % 5 == 0 || minute % 6 == 0).unwrap_or(next_hour)
+        } else {
+            next_hour
+        };
+
+        let exchange = &self.options.config.exchange;
+        let next_exchange_call = if exchange.enabled {
+            let interval = exchange.clearing_interval as u128;
+            (now / interval + 1) * interval
+        } else {
+            next_hour
+        };
+
+        next_hour.min(next_job).min(next_decision).min(next_exchange_call).max(now + 1)
+    }
+
+    // Gives the actors turns until they're all quiet. The world can't go on without
+    // an actor that crashed, so the run ends and reports it once the threads are joined.
+    fn settle_actors(&mut self) {
+        self.stepper.settle();
+        if !self.stepper.crashed().is_empty() && self.is_running {
+            eprintln!("SIM: An agent crashed. Ending the run.");
+            self.quit();
+        }
+    }
+
+    fn maybe_autosave(&mut self) {
+        if !self.autosave.as_mut().is_some_and(|autosave| autosave.day_passed()) {
+            return;
+        }
+
+        // We'd rather keep the last good autosave than write one of a broken world.
+        let snapshot = match self.snapshot() {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                eprintln!("SIM: Skipping the autosave, {e}.");
+                return;
+            }
+        };
+
+        match self.autosave.as_ref().unwrap().save(&snapshot) {
+            Ok(path) => println!("SIM: Autosaved to {}", path.display()),
+            Err(e) => eprintln!("SIM: Autosave failed: {e}"),
+        }
+    }
+
+    fn broadcast_action(&self, action: StateAction) {
+        if let Err(e) = self.wakeup_channel.0.send(action) {
+            eprintln!("SIM: Could not deliver wakeup message to recipient: {e}");
+        };
+    }
+
+    fn handle_ui_flags(&mut self) {
+        if let Ok(flag) = self.ui_flag_channel.1.try_recv() {
+            match flag {
+                UIFlag::Pause => self.toggle_paused(),
+                UIFlag::SpeedChange(speed_index) => {
+                    self.change_speed(speed_index);
+                    self.broadcast_action(StateAction::SpeedChange(self.timer.get_tick_duration()));
+                    self.maybe_change_clock_mode(speed_index);
+                },
+                UIFlag::Quit => self.quit(),
+                UIFlag::AddFactory(factory) => {
+                    if let Err(e) = self.add_factory(&factory) {
+                        eprintln!("SIM: Could not add the factory. {e}");
+                    }
+                },
+                UIFlag::RetireFactory(factory_id) => {
+                    if self.remove_factory(factory_id).is_none() {
+                        eprintln!("SIM: There's no factory No. {factory_id} to retire.");
+                    }
+                },
+            }
+        }
+    }
 }
 
 impl Simulation {
-    pub fn run(&mut self) {
+    /// Launches the UI or headless controller, the hub and its factory and Povver Plant threads.
+    /// Call this once before stepping the simulation.
+    pub fn start(&mut self) {
         self.is_running = true;
 
         self.app_state.set_misc(Misc::IsPaused(false));
 
-        let (ui_flag_sender, ui_flag_receiver) = crossbeam_channel::bounded::<UIFlag>(16);
-        let (wakeup_sender, wakeup_receiver) = tokio_broadcast::channel::<StateAction>(64);
         let log_receiver = self.ui_log_channel.1.resubscribe();
         let state_payload = self.app_state.get_state_payload();
 
-        let mut join_handles = vec![
-            self.ui_controller.run(
-                ui_flag_sender,
-                wakeup_receiver.resubscribe(),
-                log_receiver,
-                Arc::clone(&state_payload),
-                //TODO: User defined dynamic factory count
-                TEST_INDUSTRIES.len(),
-            ),
-        ];
-
-        // Let's give the UI enough time to initialize
-        // before we launch the hub.
-        std::thread::sleep(std::time::Duration::from_secs(1));
-        join_handles.push(TheHub::start(Arc::clone(&self.the_hub), wakeup_receiver));
-
-        let broadcast_action = |action: StateAction| {
-            if let Err(e) = wakeup_sender.send(action.clone()) {
-                eprintln!("SIM: Could not deliver wakeup message to recipient: {e}");
-            };
+        match self.options.run_mode {
+            #[cfg(feature = "gui")]
+            RunMode::Gui => {
+                self.join_handles.push(self.ui_controller.run(
+                    self.ui_flag_channel.0.clone(),
+                    self.wakeup_channel.1.resubscribe(),
+                    log_receiver,
+                    Arc::clone(&state_payload),
+                ));
+
+                // Let's give the UI enough time to initialize
+                // before we launch the hub.
+                std::thread::sleep(std::time::Duration::from_secs(1));
+            },
+            RunMode::Headless => {
+                self.join_handles.push(HeadlessController::new(
+                    self.options.log_output.clone(),
+                    self.seeds.master_seed(),
+                    self.options.config.to_toml(),
+                ).run(
+                    self.wakeup_channel.1.resubscribe(),
+                    log_receiver,
+                    Arc::clone(&state_payload),
+                ));
+            },
+        }
+        self.join_handles.push(TheHub::start(Arc::clone(&self.the_hub), self.wakeup_channel.1.resubscribe()));
+
+        // The actors start with the normal speed in mind.
+        self.broadcast_action(StateAction::SpeedChange(self.timer.get_tick_duration()));
+        if self.is_restored {
+            // A restored world continues where it was saved. Waking the agents up
+            // with a month change would make them do this minute's work twice.
+            self.broadcast_action(StateAction::Timer(TimerEvent::Paused));
+        } else {
+            self.broadcast_action(StateAction::Timer(TimerEvent::MonthChange));
+        }
+        self.broadcast_action(StateAction::Env);
+        self.broadcast_action(StateAction::Misc);
+        self.broadcast_action(StateAction::EconUpdate(EconUpdate::Macro));
+        if !self.is_restored {
+            self.economy.maybe_new_product_demands();
+        }
+        self.broadcast_action(StateAction::EconUpdate(EconUpdate::Demands));
+
+        self.app_state.get_misc_state_updates();
+
+        if self.clock_mode == ClockMode::Stepped {
+            self.settle_actors();
+        }
+    }
+
+    /// Advances the simulation clock by one tick and wakes up every recipient
+    /// with the resulting `StateAction`s. Nothing happens but a short wait when paused.
+    ///
+    /// With `ClockMode::Stepped`, which max speed always runs with, every call advances exactly one simulated minute,
+    /// pause or not, and returns only after the hub, the Povver Plant and all factories have handled it.
+    /// With `SimOptions::skip_quiet_minutes` it first jumps over the minutes where nothing would happen.
+    pub fn step(&mut self) -> TimerEvent {
+        self.step_until(None)
+    }
+
+    // Steps without skipping past the given timestamp.
+    fn step_until(&mut self, until: Option<u128>) -> TimerEvent {
+        let is_paused = self.is_paused();
+        if !is_paused && self.is_skipping_quiet_minutes() {
+            let now = self.app_state.timer.read().unwrap().timestamp;
+            let mut target = self.next_eventful_minute(now);
+            if let Some(until) = until {
+                target = target.min(until);
+            }
+            if target > now + 1 {
+                self.timer.skip_to(target - 1);
+            }
+        }
+        let timer_event = self.timer.tick(is_paused);
+        let date = self.app_state.timer.read().unwrap().date.clone();
+        if timer_event.at_least_minute() && date.minute % 4 == 0 {
+            // This is mainly to update ui for product demands in economy panel
+            self.broadcast_action(StateAction::EconUpdate(EconUpdate::Demands));
+        }
+        if timer_event.at_least_hour() {
+            self.env.update();
+            self.broadcast_action(StateAction::Env);
+
+            self.economy.update_product_demands();
+            self.broadcast_action(StateAction::EconUpdate(EconUpdate::Demands));
+
+            if date.hour % 6 == 0 {
+                self.economy.maybe_new_product_demands();
+            }
+        }
+        if timer_event.at_least_month() {
+            self.economy.update_macroeconomics();
+            self.broadcast_action(StateAction::EconUpdate(EconUpdate::Macro));
+        }
+
+        // Send timer signal to recipients to wake them up for timed jobs.
+        self.broadcast_action(StateAction::Timer(timer_event.clone()));
+
+        if self.app_state.get_misc_state_updates().is_some() {
+            self.broadcast_action(StateAction::Misc);
+        }
+
+        if self.clock_mode == ClockMode::Stepped {
+            self.settle_actors();
+        }
+
+        if let Some(metrics) = self.metrics.as_mut() {
+            metrics.maybe_sample(&timer_event, &self.app_state.get_state_payload());
+        }
+
+        // Only now has everyone done the new day's work, just like before a save on quit.
+        if timer_event.at_least_day() {
+            self.maybe_autosave();
+        }
+
+        timer_event
+    }
+
+    /// Steps the simulation the given number of times. In stepped clock mode that's as many minutes,
+    /// which takes fewer steps when quiet minutes are skipped.
+    pub fn advance(&mut self, minutes: u32) {
+        if self.is_paused() || !self.is_skipping_quiet_minutes() {
+            for _ in 0..minutes {
+                self.step();
+            }
+            return;
+        }
+
+        let until = self.app_state.timer.read().unwrap().timestamp + minutes as u128;
+        while self.app_state.timer.read().unwrap().timestamp < until {
+            self.step_until(Some(until));
+        }
+    }
+
+    /// Read-only handles to the timer, environment, economy, Povver Plant and factory states.
+    pub fn state(&self) -> Arc<StatePayload> {
+        self.app_state.get_state_payload()
+    }
+
+    /// The latest metrics samples, oldest first. Empty if `SimOptions::metrics` is off.
+    /// Only as many as a year has hours are kept, the metrics file has all of them.
+    pub fn metrics(&self) -> &[MetricsSample] {
+        self.metrics.as_ref().map(|metrics| metrics.samples()).unwrap_or_default()
+    }
+
+    /// The latest clearing rounds of the energy exchange, oldest first. Empty unless `exchange.enabled` is set in the config.
+    /// Only as many as a year has hours are kept, the exchange log has all of them.
+    pub fn exchange_rounds(&self) -> Vec<ClearingRound> {
+        self.the_hub.lock().unwrap().exchange_recorder.rounds().to_vec()
+    }
+
+    /// Deliveries the hub has scheduled but not done yet, in the order they are due.
+    pub fn pending_jobs(&self) -> Vec<ScheduledJob<HubJob>> {
+        self.the_hub.lock().unwrap().scheduler.pending().into_iter().cloned().collect()
+    }
+
+    /// Builds a factory from a scenario entry and puts it to work, with a thread of its own
+    /// if the simulation has started. Returns the id it got. Ids are never given out twice.
+    pub fn add_factory(&mut self, factory: &FactoryScenario) -> Result<usize, ScenarioError> {
+        let problems = factory.check("factory", &self.options.config);
+        if !problems.is_empty() {
+            return Err(ScenarioError::Invalid(problems));
+        }
+
+        let step_turn = self.stepper.add_actor();
+        let factory_id = {
+            let mut hub = self.the_hub.lock().unwrap();
+            let factory_id = hub.next_factory_id();
+            hub.add_factory(factory.state(factory_id, &self.options.config), &factory.strategy, step_turn);
+
+            factory_id
         };
+        self.broadcast_action(StateAction::Factories);
 
-        broadcast_action(StateAction::Timer(TimerEvent::MonthChange));
-        broadcast_action(StateAction::Env);
-        wakeup_sender.send(StateAction::Misc).unwrap();
-        wakeup_sender.send(StateAction::EconUpdate(EconUpdate::Macro)).unwrap();
-        self.economy.maybe_new_product_demands();
-        broadcast_action(StateAction::EconUpdate(EconUpdate::Demands));
+        Ok(factory_id)
+    }
 
-        let mut misc = self.app_state.get_misc_state_updates().unwrap();
+    /// Takes a factory out of the simulation and waits for its thread to finish.
+    /// Returns the state it left with, or None if there's no factory with the id.
+    pub fn remove_factory(&mut self, factory_id: usize) -> Option<FactoryStateData> {
+        let retired = self.the_hub.lock().unwrap().retire_factory(factory_id)?;
+
+        // A factory waiting for its turn in stepped mode sees it will get no more and quits.
+        self.stepper.remove_actor(retired.step_turn_id);
+        if let Some(handle) = retired.thread {
+            let name = handle.thread().name().unwrap_or("unnamed").to_string();
+            if let Err(e) = handle.join() {
+                eprintln!("SIM: Could not join thread: {:?}", e);
+                self.crashed_threads.push(name);
+            }
+        }
+        self.broadcast_action(StateAction::Factories);
+
+        Some(retired.state)
+    }
+
+    /// Sends the quit signal to every recipient and waits for their threads to finish.
+    pub fn shutdown(&mut self) {
+        self.is_running = false;
+
+        // Send quit signal to every recipient for cleanups
+        self.broadcast_action(StateAction::Quit);
+        // In stepped mode everyone needs a last turn to see the quit signal.
+        if self.clock_mode == ClockMode::Stepped {
+            self.stepper.round();
+        }
+        // Join all handles
+        for handle in self.join_handles.drain(..) {
+            let name = handle.thread().name().unwrap_or("unnamed").to_string();
+            if let Err(e) = handle.join() {
+                eprintln!("SIM: Could not join thread: {:?}", e);
+                self.crashed_threads.push(name);
+            }
+        }
+
+        // A crashed hub leaves its lock poisoned, but the exchange rounds it recorded are still good.
+        let mut hub = self.the_hub.lock().unwrap_or_else(PoisonError::into_inner);
+        self.crashed_threads.append(&mut hub.crashed_threads);
+        hub.exchange_recorder.flush();
+        drop(hub);
+
+        if let Some(metrics) = self.metrics.as_mut() {
+            metrics.flush();
+        }
+    }
+
+    /// Runs the simulation in real time until the UI or a flag sender asks it to quit,
+    /// or until `SimOptions::run_days` are over. Fails if any of the threads crashed on the way.
+    pub fn run(&mut self) -> Result<(), RunError> {
+        self.start();
 
+        let mut days_left = self.options.run_days;
         while self.timer.ticker.recv().is_ok() {
             if !self.is_running {
-                // Send quit signal to every recipient for cleanups
-                broadcast_action(StateAction::Quit);
-                // Join all handles
-                for handle in join_handles {
-                    if let Err(e) = handle.join() {
-                        eprintln!("SIM: Could not join thread: {:?}", e);
-                    }
-                }
+                self.shutdown();
                 // Break out of main loop
                 break;
             }
 
-            let timer_event = self.timer.tick(misc.is_paused);
-            let date = self.app_state.timer.read().unwrap().date.clone();
-            if timer_event.at_least_minute() {
-                if date.minute % 4 == 0 {
-                    // This is mainly to update ui for product demands in economy panel
-                    broadcast_action(StateAction::EconUpdate(EconUpdate::Demands));
+            if self.step().at_least_day()
+                && let Some(days) = days_left.as_mut()
+            {
+                *days = days.saturating_sub(1);
+                if *days == 0 {
+                    println!("SIM: Ran for {} days as asked. Quitting.", self.options.run_days.unwrap());
+                    self.quit();
                 }
             }
-            if timer_event.at_least_hour() {
-                self.env.update();
-                broadcast_action(StateAction::Env);
+            self.handle_ui_flags();
+        }
 
-                self.economy.update_product_demands();
-                broadcast_action(StateAction::EconUpdate(EconUpdate::Demands));
+        println!("SIM: This simulation ended. Now yours continue.");
 
-                if date.hour % 6 == 0 {
-                    self.economy.maybe_new_product_demands();
-                }
-            }
-            if timer_event.at_least_month() {
-                self.economy.update_macroeconomics();
-                broadcast_action(StateAction::EconUpdate(EconUpdate::Macro));
-            }
+        if self.crashed_threads.is_empty() {
+            Ok(())
+        } else {
+            Err(RunError { crashed_threads: self.crashed_threads.clone() })
+        }
+    }
 
-            // Send timer signal to recipients to wake them up for timed jobs.
-            broadcast_action(StateAction::Timer(timer_event));
+    /// Captures the whole world. Take it between steps. With the real-time clock,
+    /// signals still on their way between the agents are not part of it.
+    /// Fails with `SnapshotError::Broken` if an agent crashed and left its state half done.
+    pub fn snapshot(&self) -> Result<Snapshot, SnapshotError> {
+        let hub = self.the_hub.lock()?;
+        let factories = hub.factories.lock()?
+            .iter()
+            .zip(hub.factories_state.read()?.iter())
+            .map(|(factory, state)| Ok(FactoryEntry {
+                state: state.read()?.clone(),
+                agent: factory.lock()?.snapshot(),
+            }))
+            .collect::<Result<_, SnapshotError>>()?;
+
+        Ok(Snapshot {
+            version: SNAPSHOT_VERSION,
+            seed: self.seeds.master_seed(),
+            timer: self.app_state.timer.read()?.clone(),
+            env: self.app_state.env.read()?.clone(),
+            env_rng: self.env.clone_rng(),
+            economy: self.app_state.economy.read()?.clone(),
+            economy_rng: self.economy.clone_rng(),
+            povver_plant: hub.povver_plant_state.read()?.clone(),
+            povver_plant_agent: hub.povver_plant.lock()?.snapshot(),
+            factories,
+            hub: hub.snapshot(),
+        })
+    }
 
-            if let Some(new_misc) = self.app_state.get_misc_state_updates() {
-                misc = new_misc;
-                wakeup_sender.send(StateAction::Misc).unwrap();
-            }
+    /// Puts the world of a snapshot in place of the current one.
+    /// Only works before `start` since the agents must not be running.
+    pub fn restore(&mut self, snapshot: Snapshot) -> Result<(), SnapshotError> {
+        if self.is_running {
+            return Err(SnapshotError::Incompatible("the simulation is already running".to_string()));
+        }
 
-            if let Ok(flag) = ui_flag_receiver.try_recv() {
-                match flag {
-                    UIFlag::Pause => self.toggle_paused(),
-                    UIFlag::SpeedChange(speed_index) => {
-                        self.change_speed(speed_index);
-                        wakeup_sender.send(StateAction::SpeedChange(self.timer.get_tick_duration())).unwrap();
-                    },
-                    UIFlag::Quit => self.quit(),
-                }
+        let mut hub = self.the_hub.lock().unwrap();
+
+        // Timestamps only mean the same date in the calendar they were taken with.
+        if self.timer.calendar().to_timestamp(&snapshot.timer.date) != snapshot.timer.timestamp {
+            return Err(SnapshotError::Incompatible(format!(
+                "its date {} doesn't fall on its timestamp with {} leap years",
+                snapshot.timer.date, if self.timer.calendar().leap_years { "the" } else { "no" }
+            )));
+        }
+
+        self.timer.restore(snapshot.timer);
+        self.env.restore(snapshot.env, snapshot.env_rng);
+        self.economy.restore(snapshot.economy, snapshot.economy_rng);
+
+        *hub.povver_plant_state.write().unwrap() = snapshot.povver_plant;
+        hub.povver_plant.lock().unwrap().restore(snapshot.povver_plant_agent);
+
+        // Factories may have been added or retired since the world started from its scenario.
+        let retired_ids = hub.factories_state.read().unwrap()
+            .iter()
+            .map(|state| state.read().unwrap().id)
+            .filter(|id| !snapshot.factories.iter().any(|entry| entry.state.id == *id))
+            .collect::<Vec<_>>();
+        for factory_id in retired_ids {
+            if let Some(retired) = hub.retire_factory(factory_id) {
+                self.stepper.remove_actor(retired.step_turn_id);
+            }
+        }
+        for entry in snapshot.factories {
+            let factory_id = entry.state.id;
+            if hub.get_factory_state(factory_id).is_none() {
+                // The strategy is part of the agent snapshot restored below.
+                hub.add_factory(entry.state.clone(), &FactoryStrategy::default(), self.stepper.add_actor());
             }
+            *hub.get_factory_state(factory_id).unwrap().write().unwrap() = entry.state;
+            hub.get_factory(factory_id).unwrap().lock().unwrap().restore(entry.agent);
         }
+        // Jobs go last, the retirements above would cancel the ones for the factories coming back.
+        hub.restore(snapshot.hub);
+        self.is_restored = true;
 
-        println!("SIM: This simulation ended. Now yours continue.");
+        Ok(())
+    }
+
+    /// Writes a snapshot of the world to a file.
+    pub fn save(&self, path: &Path) -> Result<(), SnapshotError> {
+        self.snapshot()?.save(path)
+    }
+
+    /// A simulation that continues where the saved one left off, with the same master seed.
+    pub fn load(options: SimOptions, path: &Path) -> Result<Self, SnapshotError> {
+        Self::from_snapshot(options, Snapshot::load(path)?)
+    }
+
+    /// A simulation that continues from the given snapshot, with its master seed.
+    pub fn from_snapshot(options: SimOptions, snapshot: Snapshot) -> Result<Self, SnapshotError> {
+        let mut sim = Self::new(SimOptions {
+            seed: Some(snapshot.seed),
+            ..options
+        })?;
+        sim.restore(snapshot)?;
+
+        Ok(sim)
+    }
+
+    /// Master seed of this run. Pass it back in `SimOptions` to reproduce the run.
+    pub fn seed(&self) -> u64 {
+        self.seeds.master_seed()
+    }
+
+    /// The effective config of this run, with the config file and the overrides applied.
+    pub fn config(&self) -> &Config {
+        &self.options.config
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.is_running
     }
 
     pub fn quit(&mut self) {
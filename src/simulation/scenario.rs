@@ -0,0 +1,465 @@
+use std::{
+    fmt,
+    fs,
+    io,
+    path::Path,
+};
+use serde::{Serialize, Deserialize};
+
+use crate::{
+    app_state::{FactoryStateData, PovverPlantStateData},
+    config::{Config, PovverPlantConfig},
+    economy::{
+        economy_types::{EnergyUnit, Money},
+        industries::Industry,
+        products::Product,
+        solarpanel::SolarPanel,
+        windturbine::WindTurbine,
+        battery::Battery,
+    },
+    simulation::{
+        SimFlo,
+        SimInt,
+        timer::Date,
+    },
+};
+
+// Factories of the default scenario, one for each of these industries.
+const DEFAULT_INDUSTRIES: [Industry; 5] = [
+    Industry::SEMICONDUCTORS,
+    Industry::COSMETICS,
+    Industry::PROCESSED_FOODS,
+    Industry::ARMS,
+    Industry::BANK,
+];
+
+#[derive(Debug)]
+pub enum ScenarioError {
+    Io(io::Error),
+    Format(toml::de::Error),
+    Invalid(Vec<String>),
+}
+
+impl fmt::Display for ScenarioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "scenario file error: {e}"),
+            Self::Format(e) => write!(f, "malformed scenario: {e}"),
+            Self::Invalid(problems) => {
+                write!(f, "invalid scenario:")?;
+                for problem in problems {
+                    write!(f, "\n  - {problem}")?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+impl std::error::Error for ScenarioError {}
+
+impl From<io::Error> for ScenarioError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for ScenarioError {
+    fn from(e: toml::de::Error) -> Self {
+        Self::Format(e)
+    }
+}
+
+// How a factory runs its business. Both are percentages and default to the factory config.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FactoryStrategy {
+    // Added on top of the unit cost when selling products.
+    pub profit_margin: Option<SimFlo>,
+    // Demands are only considered when they pay at least this much above the unit cost.
+    pub product_demand_sell_threshold: Option<SimFlo>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FactoryScenario {
+    pub industry: String,
+    pub balance: SimFlo,
+    // Product names. Left empty, the factory starts with the product of its industry
+    // that is the cheapest to research.
+    #[serde(default)]
+    pub product_portfolio: Vec<String>,
+    #[serde(default)]
+    pub solar_panels: usize,
+    #[serde(default)]
+    pub wind_turbines: usize,
+    #[serde(default)]
+    pub batteries: usize,
+    #[serde(default)]
+    pub strategy: FactoryStrategy,
+}
+
+impl FactoryScenario {
+    // Problems of this factory, each starting with `at` to tell where it is.
+    pub fn check(&self, at: &str, config: &Config) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        let industry = Industry::by_name(&self.industry);
+        match industry {
+            None => problems.push(format!("{at}: unknown industry \"{}\"", self.industry)),
+            Some(industry) => {
+                if Product::by_industry(industry).is_empty() {
+                    problems.push(format!("{at}: the product catalog has no products for the {} industry", industry.name));
+                }
+            }
+        }
+        if !self.balance.is_finite() || self.balance < 0.0 {
+            problems.push(format!("{at}: balance must not be negative, got {}", self.balance));
+        }
+        for (i, name) in self.product_portfolio.iter().enumerate() {
+            if self.product_portfolio[..i].contains(name) {
+                problems.push(format!("{at}: product \"{name}\" is listed more than once"));
+                continue;
+            }
+            match Product::by_name(name) {
+                None => problems.push(format!("{at}: unknown product \"{name}\"")),
+                Some(product) => {
+                    if industry.is_some_and(|industry| &product.industry != industry) {
+                        problems.push(format!("{at}: product \"{name}\" is not made in the {} industry", self.industry));
+                    }
+                }
+            }
+        }
+        let max_solar_panels = config.factory.max_solar_panels;
+        if self.solar_panels > max_solar_panels {
+            problems.push(format!("{at}: {} solar panels is more than the maximum of {max_solar_panels}", self.solar_panels));
+        }
+        let max_wind_turbines = config.factory.max_wind_turbines;
+        if self.wind_turbines > max_wind_turbines {
+            problems.push(format!("{at}: {} wind turbines is more than the maximum of {max_wind_turbines}", self.wind_turbines));
+        }
+        let max_batteries = config.factory.max_batteries;
+        if self.batteries > max_batteries {
+            problems.push(format!("{at}: {} batteries is more than the maximum of {max_batteries}", self.batteries));
+        }
+        let strategy = &self.strategy;
+        if let Some(profit_margin) = strategy.profit_margin
+            && (!profit_margin.is_finite() || profit_margin < 0.0)
+        {
+            problems.push(format!("{at}: strategy.profit_margin must not be negative, got {profit_margin}"));
+        }
+        if let Some(threshold) = strategy.product_demand_sell_threshold
+            && !(0.0..=100.0).contains(&threshold)
+        {
+            problems.push(format!(
+                "{at}: strategy.product_demand_sell_threshold must be between 0 and 100, got {threshold}"
+            ));
+        }
+
+        problems
+    }
+}
+
+// Whatever is left out comes from the Povver Plant config. The tank starts full by default.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PovverPlantScenario {
+    pub fuel: Option<SimInt>,
+    pub fuel_capacity: Option<SimInt>,
+    pub production_capacity: Option<SimInt>,
+    // Money before the initial fuel is paid for at the market price.
+    pub money: Option<SimFlo>,
+}
+
+struct PovverPlantStart {
+    fuel: SimInt,
+    fuel_capacity: SimInt,
+    production_capacity: SimInt,
+    money: SimFlo,
+}
+
+impl PovverPlantScenario {
+    fn start(&self, config: &PovverPlantConfig) -> PovverPlantStart {
+        let fuel_capacity = self.fuel_capacity.unwrap_or(config.init_fuel_capacity);
+
+        PovverPlantStart {
+            fuel: self.fuel.unwrap_or(fuel_capacity),
+            fuel_capacity,
+            production_capacity: self.production_capacity.unwrap_or(config.init_production_capacity),
+            money: self.money.unwrap_or(config.init_money),
+        }
+    }
+}
+
+// The world a simulation starts with.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    // Used when no seed is given on the command line.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[serde(default = "Scenario::default_start_date")]
+    pub start_date: Date,
+    #[serde(default)]
+    pub povver_plant: PovverPlantScenario,
+    pub factories: Vec<FactoryScenario>,
+}
+
+impl Default for Scenario {
+    fn default() -> Self {
+        Self::from_config(&Config::default())
+    }
+}
+
+impl Scenario {
+    // The default world with the initial money of the config.
+    pub fn from_config(config: &Config) -> Self {
+        // The initial product's research is paid for out of the initial money.
+        // With a catalog that has nothing for an industry, validation points it out.
+        let factories = DEFAULT_INDUSTRIES.iter().map(|industry| {
+            let product = Self::cheapest_rnd_product(industry);
+            FactoryScenario {
+                industry: industry.name.to_string(),
+                balance: config.factory.init_money - product.map_or(0.0, |product| product.rnd_cost),
+                product_portfolio: product.map(|product| product.name.to_string()).into_iter().collect(),
+                solar_panels: 0,
+                wind_turbines: 0,
+                batteries: 0,
+                strategy: FactoryStrategy::default(),
+            }
+        }).collect();
+
+        Self {
+            seed: None,
+            start_date: Self::default_start_date(),
+            povver_plant: PovverPlantScenario::default(),
+            factories,
+        }
+    }
+
+    fn default_start_date() -> Date {
+        Date {
+            minute: 0,
+            hour: 12,
+            day: 1,
+            month: 7,
+            year: 2025,
+        }
+    }
+
+    fn cheapest_rnd_product(industry: &Industry) -> Option<&'static Product> {
+        Product::by_industry(industry)
+            .into_iter()
+            .min_by(|prod_a, prod_b| prod_a.rnd_cost.total_cmp(&prod_b.rnd_cost))
+    }
+
+    // Reads a scenario file and validates it against the config it will run with.
+    pub fn load(path: &Path, config: &Config) -> Result<Self, ScenarioError> {
+        let scenario: Self = toml::from_str(&fs::read_to_string(path)?)?;
+        scenario.validate(config)?;
+
+        Ok(scenario)
+    }
+
+    // Collects every problem at once, so a scenario can be fixed in one go.
+    pub fn validate(&self, config: &Config) -> Result<(), ScenarioError> {
+        let mut problems = Vec::new();
+
+        for problem in config.calendar.check(&self.start_date) {
+            problems.push(format!("start_date: {problem}"));
+        }
+
+        let pp = self.povver_plant.start(&config.povver_plant);
+        if pp.fuel_capacity <= 0 {
+            problems.push(format!("povver_plant: fuel_capacity must be positive, got {}", pp.fuel_capacity));
+        }
+        if pp.fuel < 0 || pp.fuel > pp.fuel_capacity {
+            problems.push(format!("povver_plant: fuel {} doesn't fit in the fuel capacity of {}", pp.fuel, pp.fuel_capacity));
+        }
+        if pp.production_capacity <= 0 {
+            problems.push(format!("povver_plant: production_capacity must be positive, got {}", pp.production_capacity));
+        }
+        if !pp.money.is_finite() || pp.money < 0.0 {
+            problems.push(format!("povver_plant: money must not be negative, got {}", pp.money));
+        }
+
+        if self.factories.is_empty() {
+            problems.push("factories: there must be at least one factory".to_string());
+        }
+        for (id, factory) in self.factories.iter().enumerate() {
+            problems.extend(factory.check(&format!("factories[{id}]"), config));
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ScenarioError::Invalid(problems))
+        }
+    }
+}
+
+// Builders for the initial states. They expect a validated scenario.
+impl Scenario {
+    pub fn povver_plant_state(&self, config: &Config, fuel_price: SimFlo) -> PovverPlantStateData {
+        let pp = self.povver_plant.start(&config.povver_plant);
+
+        PovverPlantStateData {
+            fuel: pp.fuel,
+            fuel_capacity: pp.fuel_capacity,
+            production_capacity: EnergyUnit::new(pp.production_capacity),
+            committed_energy: 0,
+            balance: Money::new(pp.money - (fuel_price * pp.fuel as SimFlo)),
+            is_awaiting_fuel: false,
+            is_awaiting_fuel_capacity: false,
+            is_awaiting_production_capacity: false,
+            is_bankrupt: false,
+        }
+    }
+}
+
+impl FactoryScenario {
+    // The state of a new factory with the given id. Expects a checked factory.
+    pub fn state(&self, id: usize, config: &Config) -> FactoryStateData {
+        let industry = Industry::by_name(&self.industry).unwrap();
+        let product_portfolio = if self.product_portfolio.is_empty() {
+            vec![Scenario::cheapest_rnd_product(industry).unwrap()]
+        } else {
+            self.product_portfolio.iter().map(|name| Product::by_name(name).unwrap()).collect()
+        };
+
+        let mut solarpanels = Vec::with_capacity(config.factory.max_solar_panels);
+        solarpanels.extend((0..self.solar_panels).map(|_| SolarPanel::new()));
+        let mut windturbines = Vec::with_capacity(config.factory.max_wind_turbines);
+        windturbines.extend((0..self.wind_turbines).map(|_| WindTurbine::new()));
+        let mut batteries = Vec::with_capacity(config.factory.max_batteries);
+        batteries.extend((0..self.batteries).map(|_| Battery::new(&config.battery)));
+
+        FactoryStateData {
+            balance: Money::new(self.balance),
+            available_energy: EnergyUnit::default(),
+            product_stocks: Vec::new(),
+            solarpanels,
+            windturbines,
+            batteries,
+            industry: industry.clone(),
+            product_portfolio,
+            id,
+            is_bankrupt: false,
+            is_awaiting_solarpanels: false,
+            is_awaiting_windturbines: false,
+            is_awaiting_batteries: false,
+            energy_from_pp_total: 0,
+            energy_from_solar_total: 0,
+            energy_from_wind_total: 0,
+            energy_from_factories_total: 0,
+            energy_to_factories_total: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(name: &str, text: &str) -> Result<Scenario, ScenarioError> {
+        let path = std::env::temp_dir().join(format!("povver-scenario-{name}.toml"));
+        fs::write(&path, text).unwrap();
+        let scenario = Scenario::load(&path, &Config::default());
+        fs::remove_file(&path).unwrap();
+
+        scenario
+    }
+
+    fn invalid(result: Result<Scenario, ScenarioError>) -> Vec<String> {
+        match result {
+            Err(ScenarioError::Invalid(problems)) => problems,
+            other => panic!("expected an invalid scenario, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn example_scenario_is_valid() {
+        let scenario = Scenario::load(Path::new("scenarios/example.toml"), &Config::default()).unwrap();
+        assert_eq!(scenario.seed, Some(42));
+        assert_eq!(scenario.factories.len(), 3);
+        assert!(Scenario::default().validate(&Config::default()).is_ok());
+    }
+
+    #[test]
+    fn malformed_file_is_a_format_error() {
+        let result = load("malformed", "[[factories]\nindustry = \"Bank\"");
+        assert!(matches!(result, Err(ScenarioError::Format(_))), "{result:?}");
+
+        // Factories can't be left out.
+        let result = load("no-factories", "seed = 1");
+        assert!(matches!(&result, Err(ScenarioError::Format(e)) if e.to_string().contains("missing field `factories`")), "{result:?}");
+
+        let result = Scenario::load(Path::new("scenarios/does-not-exist.toml"), &Config::default());
+        assert!(matches!(result, Err(ScenarioError::Io(_))), "{result:?}");
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        let result = load("unknown-top", "sed = 1\n[[factories]]\nindustry = \"Bank\"\nbalance = 1.0");
+        assert!(matches!(&result, Err(ScenarioError::Format(e)) if e.to_string().contains("unknown field `sed`")), "{result:?}");
+
+        let result = load("unknown-strategy", "[[factories]]\nindustry = \"Bank\"\nbalance = 1.0\n[factories.strategy]\nmargin = 5.0");
+        assert!(matches!(&result, Err(ScenarioError::Format(e)) if e.to_string().contains("unknown field `margin`")), "{result:?}");
+    }
+
+    #[test]
+    fn every_problem_is_reported_at_once() {
+        let problems = invalid(load("out-of-range", r#"
+            [start_date]
+            minute = 0
+            hour = 25
+            day = 1
+            month = 3
+            year = 2025
+
+            [povver_plant]
+            fuel = 60
+            fuel_capacity = 50
+            money = -1.0
+
+            [[factories]]
+            industry = "Bank"
+            balance = 1000.0
+            solar_panels = 101
+            [factories.strategy]
+            product_demand_sell_threshold = 150.0
+
+            [[factories]]
+            industry = "Steel"
+            balance = -5.0
+            product_portfolio = ["SaaS", "SaaS"]
+        "#));
+
+        let expected = [
+            "start_date: ",
+            "povver_plant: fuel 60 doesn't fit in the fuel capacity of 50",
+            "povver_plant: money must not be negative, got -1",
+            "factories[0]: 101 solar panels is more than the maximum of 100",
+            "factories[0]: strategy.product_demand_sell_threshold must be between 0 and 100, got 150",
+            "factories[1]: unknown industry \"Steel\"",
+            "factories[1]: balance must not be negative, got -5",
+            "factories[1]: product \"SaaS\" is listed more than once",
+        ];
+        for start in expected {
+            assert!(problems.iter().any(|problem| problem.starts_with(start)), "no \"{start}\" in {problems:#?}");
+        }
+        assert_eq!(problems.len(), expected.len(), "{problems:#?}");
+    }
+
+    #[test]
+    fn products_must_fit_the_industry() {
+        let problems = invalid(load("wrong-industry", "[[factories]]\nindustry = \"Bank\"\nbalance = 1.0\nproduct_portfolio = [\"SaaS\", \"Unobtainium\"]"));
+        assert_eq!(problems, vec![
+            "factories[0]: product \"SaaS\" is not made in the Bank industry".to_string(),
+            "factories[0]: unknown product \"Unobtainium\"".to_string(),
+        ]);
+
+        let problems = invalid(load("empty-factories", "factories = []"));
+        assert_eq!(problems, vec!["factories: there must be at least one factory".to_string()]);
+    }
+}
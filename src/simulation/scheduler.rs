@@ -0,0 +1,210 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BinaryHeap},
+};
+use serde::{Serialize, Deserialize};
+
+pub type JobId = u64;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScheduledJob<T> {
+    pub id: JobId,
+    // Absolute timer timestamp in minutes
+    pub due: u128,
+    pub job: T,
+}
+
+// Jobs keyed by the minute they are due. Jobs due on the same minute come out in the order
+// they were scheduled in. Cancelled jobs stay in the queue until they reach the top and are skipped there.
+#[derive(Debug)]
+pub struct Scheduler<T> {
+    queue: BinaryHeap<Reverse<(u128, JobId)>>,
+    jobs: BTreeMap<JobId, ScheduledJob<T>>,
+    next_id: JobId,
+}
+
+// Pending jobs in due order, and the id the next job will get.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SchedulerSnapshot<T> {
+    next_id: JobId,
+    jobs: Vec<ScheduledJob<T>>,
+}
+
+impl<T> Default for Scheduler<T> {
+    fn default() -> Self {
+        Self {
+            queue: BinaryHeap::new(),
+            jobs: BTreeMap::new(),
+            next_id: 0,
+        }
+    }
+}
+
+impl<T> Scheduler<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn schedule(&mut self, due: u128, job: T) -> JobId {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        self.queue.push(Reverse((due, id)));
+        self.jobs.insert(id, ScheduledJob { id, due, job });
+
+        id
+    }
+
+    pub fn cancel(&mut self, id: JobId) -> Option<ScheduledJob<T>> {
+        self.jobs.remove(&id)
+    }
+
+    // Takes the next job that is due at `now` or before.
+    pub fn pop_due(&mut self, now: u128) -> Option<ScheduledJob<T>> {
+        while let Some(&Reverse((due, id))) = self.queue.peek() {
+            if due > now {
+                return None;
+            }
+            self.queue.pop();
+            if let Some(job) = self.jobs.remove(&id) {
+                return Some(job);
+            }
+        }
+
+        None
+    }
+
+    // When the earliest pending job is due.
+    pub fn next_due(&mut self) -> Option<u128> {
+        self.drop_cancelled();
+
+        self.queue.peek().map(|&Reverse((due, _))| due)
+    }
+
+    // Pending jobs in the order they will run.
+    pub fn pending(&self) -> Vec<&ScheduledJob<T>> {
+        let mut jobs = self.jobs.values().collect::<Vec<_>>();
+        jobs.sort_by_key(|job| (job.due, job.id));
+
+        jobs
+    }
+
+    pub fn len(&self) -> usize {
+        self.jobs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    fn drop_cancelled(&mut self) {
+        while let Some(&Reverse((_, id))) = self.queue.peek() {
+            if self.jobs.contains_key(&id) {
+                break;
+            }
+            self.queue.pop();
+        }
+    }
+}
+
+impl<T: Clone> Scheduler<T> {
+    pub fn snapshot(&self) -> SchedulerSnapshot<T> {
+        SchedulerSnapshot {
+            next_id: self.next_id,
+            jobs: self.pending().into_iter().cloned().collect(),
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: SchedulerSnapshot<T>) {
+        self.queue = snapshot.jobs.iter().map(|job| Reverse((job.due, job.id))).collect();
+        self.jobs = snapshot.jobs.into_iter().map(|job| (job.id, job)).collect();
+        self.next_id = snapshot.next_id;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pop_all_due(scheduler: &mut Scheduler<&'static str>, now: u128) -> Vec<&'static str> {
+        std::iter::from_fn(|| scheduler.pop_due(now)).map(|scheduled| scheduled.job).collect()
+    }
+
+    #[test]
+    fn jobs_come_out_in_due_order() {
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule(30, "c");
+        scheduler.schedule(10, "a");
+        scheduler.schedule(20, "b");
+
+        assert_eq!(scheduler.next_due(), Some(10));
+        assert_eq!(pop_all_due(&mut scheduler, 5), Vec::<&str>::new());
+        assert_eq!(pop_all_due(&mut scheduler, 20), vec!["a", "b"]);
+        assert_eq!(scheduler.next_due(), Some(30));
+        assert_eq!(pop_all_due(&mut scheduler, 100), vec!["c"]);
+        assert!(scheduler.is_empty());
+        assert_eq!(scheduler.next_due(), None);
+    }
+
+    #[test]
+    fn jobs_of_the_same_minute_keep_their_order() {
+        let mut scheduler = Scheduler::new();
+        for job in ["first", "second", "third", "fourth"] {
+            scheduler.schedule(7, job);
+        }
+        scheduler.schedule(3, "earlier");
+
+        assert_eq!(pop_all_due(&mut scheduler, 7), vec!["earlier", "first", "second", "third", "fourth"]);
+    }
+
+    #[test]
+    fn cancelled_jobs_are_skipped() {
+        let mut scheduler = Scheduler::new();
+        let a = scheduler.schedule(10, "a");
+        scheduler.schedule(10, "b");
+        let c = scheduler.schedule(20, "c");
+
+        assert_eq!(scheduler.cancel(a).map(|scheduled| scheduled.job), Some("a"));
+        assert_eq!(scheduler.cancel(a), None);
+        assert_eq!(scheduler.len(), 2);
+        assert_eq!(scheduler.pending().iter().map(|scheduled| scheduled.job).collect::<Vec<_>>(), vec!["b", "c"]);
+
+        scheduler.cancel(c);
+        assert_eq!(pop_all_due(&mut scheduler, 100), vec!["b"]);
+        assert!(scheduler.is_empty());
+    }
+
+    #[test]
+    fn next_due_looks_past_cancelled_jobs() {
+        let mut scheduler = Scheduler::new();
+        let a = scheduler.schedule(10, "a");
+        let b = scheduler.schedule(15, "b");
+        scheduler.schedule(40, "c");
+
+        scheduler.cancel(a);
+        assert_eq!(scheduler.next_due(), Some(15));
+        scheduler.cancel(b);
+        assert_eq!(scheduler.next_due(), Some(40));
+
+        // A job scheduled after the cancels still goes first if it's due earlier.
+        scheduler.schedule(12, "d");
+        assert_eq!(scheduler.next_due(), Some(12));
+        assert_eq!(pop_all_due(&mut scheduler, 40), vec!["d", "c"]);
+        assert_eq!(scheduler.next_due(), None);
+    }
+
+    #[test]
+    fn restored_scheduler_goes_on_where_it_left_off() {
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule(10, "a");
+        let b = scheduler.schedule(10, "b");
+        scheduler.schedule(5, "c");
+        scheduler.cancel(b);
+
+        let mut restored = Scheduler::new();
+        restored.restore(scheduler.snapshot());
+        assert_eq!(restored.schedule(10, "d"), scheduler.schedule(10, "d"));
+        assert_eq!(pop_all_due(&mut restored, 10), vec!["c", "a", "d"]);
+        assert_eq!(pop_all_due(&mut scheduler, 10), vec!["c", "a", "d"]);
+    }
+}
@@ -1,10 +1,32 @@
 use crate::simulation::{SimFlo, SimInt};
 
+// Built-in defaults of the Config. Change them at runtime with a config file or overrides.
+
+// CALENDAR
+pub const CALENDAR_LEAP_YEARS: bool = true;
+
 // SOLAR PANELS
 pub const SOLAR_PANEL_PRICE: SimFlo = 1200.0;
 pub const SOLAR_PANEL_MAX_AGE: SimInt = 10;
 pub const SOLAR_PANEL_AGE_MODIFIER: SimInt = 10;
 
+// WIND TURBINES
+pub const WIND_TURBINE_PRICE: SimFlo = 7500.0;
+pub const WIND_TURBINE_MAX_AGE: SimInt = 24;
+pub const WIND_TURBINE_AGE_MODIFIER: SimInt = 5;
+pub const WIND_TURBINE_RATED_ENERGY: SimInt = 300;
+pub const WIND_TURBINE_CUT_IN_SPEED: SimInt = 5;
+pub const WIND_TURBINE_RATED_SPEED: SimInt = 40;
+pub const WIND_TURBINE_CUT_OUT_SPEED: SimInt = 80;
+
+// BATTERIES
+pub const BATTERY_PRICE: SimFlo = 5000.0;
+pub const BATTERY_CAPACITY: SimInt = 2000;
+pub const BATTERY_CHARGE_RATE: SimInt = 500;
+pub const BATTERY_DISCHARGE_RATE: SimInt = 500;
+pub const BATTERY_ROUND_TRIP_EFFICIENCY: SimFlo = 85.0;
+pub const BATTERY_DEGRADATION: SimFlo = 2.0;
+
 // POVVER PLANT
 pub const PP_INIT_MONEY: SimFlo = 66000.0;
 pub const PP_INIT_FUEL_CAPACITY: SimInt = 50;
@@ -15,7 +37,30 @@ pub const PP_FUEL_CAPACITY_INCREASE: SimInt = 25;
 pub const PP_PRODUCTION_CAPACITY_INCREASE_COST: SimFlo = 25000.0;
 pub const PP_PRODUCTION_CAPACITY_INCREASE: SimInt = 12000;
 pub const PP_ENERGY_PER_FUEL: SimInt = 1000;
+pub const PP_PROFIT_MARGIN: SimFlo = 50.0;
 
 // FACTORY
 pub const FACTORY_INIT_MONEY: SimFlo = 33000.0;
-pub const FACTORY_MAX_SOLAR_PANELS: usize = 100;
\ No newline at end of file
+pub const FACTORY_MAX_SOLAR_PANELS: usize = 100;
+pub const FACTORY_SOLAR_PANEL_RESERVE: SimFlo = 50000.0;
+pub const FACTORY_MAX_WIND_TURBINES: usize = 10;
+pub const FACTORY_WIND_TURBINE_RESERVE: SimFlo = 60000.0;
+pub const FACTORY_MAX_BATTERIES: usize = 5;
+pub const FACTORY_BATTERY_RESERVE: SimFlo = 55000.0;
+pub const FACTORY_PROFIT_MARGIN: SimFlo = 20.0;
+pub const FACTORY_PRODUCT_DEMAND_SELL_THRESHOLD: SimFlo = 0.0;
+pub const FACTORY_ENERGY_RESALE_DISCOUNT: SimFlo = 10.0;
+
+// ENERGY EXCHANGE
+pub const EXCHANGE_ENABLED: bool = false;
+pub const EXCHANGE_CLEARING_INTERVAL: SimInt = 60;
+pub const EXCHANGE_BID_MARKUP: SimFlo = 60.0;
+
+// TIME-OF-USE TARIFF
+pub const TARIFF_ENABLED: bool = false;
+pub const TARIFF_PEAK_HOURS: [SimInt; 5] = [17, 18, 19, 20, 21];
+pub const TARIFF_OFF_PEAK_HOURS: [SimInt; 7] = [0, 1, 2, 3, 4, 5, 23];
+pub const TARIFF_PEAK_MULTIPLIER: SimFlo = 1.4;
+pub const TARIFF_SHOULDER_MULTIPLIER: SimFlo = 1.0;
+pub const TARIFF_OFF_PEAK_MULTIPLIER: SimFlo = 0.7;
+pub const TARIFF_LOAD_MARKUP: SimFlo = 30.0;
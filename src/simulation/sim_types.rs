@@ -1,5 +1,6 @@
 use std::ops::{Add, MulAssign};
 use num_traits::FromPrimitive;
+use serde::{Serialize, Deserialize};
 use crate::utils_traits::{AsFactor, HundredPercentable};
 
 pub type SimInt = i32;
@@ -8,7 +9,16 @@ pub type SimFlo = f32;
 pub type TickDuration = u64;
 pub const DEFAULT_TICK_DURATION: TickDuration = 128;
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum ClockMode {
+    // Minutes tick by on wall time and every actor polls its channels in its own rhythm.
+    #[default]
+    RealTime,
+    // Every Simulation::step advances one minute and returns only when all actors are done with it.
+    Stepped,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Percentage(SimFlo);
 impl Default for Percentage {
     fn default() -> Self {
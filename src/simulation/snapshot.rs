@@ -0,0 +1,117 @@
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufReader, BufWriter, Write},
+    path::Path,
+    sync::PoisonError,
+};
+use serde::{Serialize, Deserialize};
+
+use crate::{
+    app_state::{TimerStateData, EnvStateData, EconomyStateData, PovverPlantStateData, FactoryStateData},
+    economy::{
+        factory::FactorySnapshot,
+        povver_plant::PovverPlantSnapshot,
+    },
+    simulation::{hub::HubSnapshot, SetupError},
+    utils_random::SimRng,
+};
+
+// Bump this whenever the layout of the snapshot changes.
+pub const SNAPSHOT_VERSION: u64 = 10;
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    Format(serde_json::Error),
+    Version(Option<u64>),
+    Incompatible(String),
+    Setup(SetupError),
+    // An agent panicked and left the world half done.
+    Broken,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "snapshot file error: {e}"),
+            Self::Format(e) => write!(f, "malformed snapshot: {e}"),
+            Self::Version(Some(version)) => write!(f, "snapshot version {version} is not supported, expected {SNAPSHOT_VERSION}"),
+            Self::Version(None) => write!(f, "snapshot has no version"),
+            Self::Incompatible(reason) => write!(f, "snapshot doesn't fit this simulation: {reason}"),
+            Self::Setup(e) => write!(f, "{e}"),
+            Self::Broken => write!(f, "the world is in a broken state after an agent crashed"),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl From<io::Error> for SnapshotError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<SetupError> for SnapshotError {
+    fn from(e: SetupError) -> Self {
+        Self::Setup(e)
+    }
+}
+
+// A panicked agent leaves its locks poisoned.
+impl<T> From<PoisonError<T>> for SnapshotError {
+    fn from(_: PoisonError<T>) -> Self {
+        Self::Broken
+    }
+}
+
+impl From<serde_json::Error> for SnapshotError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Format(e)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FactoryEntry {
+    pub state: FactoryStateData,
+    pub agent: FactorySnapshot,
+}
+
+/// The whole simulated world at one point in time: shared states, the private memory of
+/// the agents, pending hub jobs and the random number generators.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u64,
+    pub seed: u64,
+    pub timer: TimerStateData,
+    pub env: EnvStateData,
+    pub env_rng: SimRng,
+    pub economy: EconomyStateData,
+    pub economy_rng: SimRng,
+    pub povver_plant: PovverPlantStateData,
+    pub povver_plant_agent: PovverPlantSnapshot,
+    pub factories: Vec<FactoryEntry>,
+    pub hub: HubSnapshot,
+}
+
+impl Snapshot {
+    pub fn save(&self, path: &Path) -> Result<(), SnapshotError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, SnapshotError> {
+        let value: serde_json::Value = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+
+        // Check the version before anything else so that old files get a clear error
+        // instead of whatever field happens to be missing.
+        match value.get("version").and_then(|v| v.as_u64()) {
+            Some(SNAPSHOT_VERSION) => Ok(serde_json::from_value(value)?),
+            version => Err(SnapshotError::Version(version)),
+        }
+    }
+}
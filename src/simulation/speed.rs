@@ -11,6 +11,8 @@ impl Speed {
     pub const FASTER: Self = Self(DEFAULT_TICK_DURATION / 8);
     pub const FAST: Self = Self(DEFAULT_TICK_DURATION / 16);
     pub const FASTEST: Self = Self(DEFAULT_TICK_DURATION / 32);
+    // No wall clock pacing at all. The actors switch to the stepped clock at this speed.
+    pub const MAX: Self = Self(0);
 }
 
 impl Default for Speed {
@@ -23,14 +25,19 @@ impl Speed {
     pub fn get_tick_duration(&self) -> TickDuration {
         self.0
     }
+
+    pub fn is_max(&self) -> bool {
+        self.0 == 0
+    }
 }
 
-pub const SPEEDS_ARRAY: [Speed; 7] = [
+pub const SPEEDS_ARRAY: [Speed; 8] = [
     Speed::SLOWEST,
     Speed::SLOW,
     Speed::SLOWER,
     Speed::NORMAL,
     Speed::FASTER,
     Speed::FAST,
-    Speed::FASTEST
+    Speed::FASTEST,
+    Speed::MAX,
 ];
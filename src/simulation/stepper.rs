@@ -0,0 +1,132 @@
+use crossbeam_channel::{unbounded, Receiver, Sender};
+
+// Safety net for message ping-pong between actors that never calms down.
+const MAX_SETTLE_ROUNDS: usize = 1000;
+
+// Actor side of the stepping protocol. An actor in stepped clock mode blocks until it gets
+// its turn, handles everything in its inboxes and acknowledges with the number of
+// messages it handled.
+#[derive(Debug, Clone)]
+pub struct StepTurn {
+    id: usize,
+    turn_receiver: Receiver<()>,
+    ack_sender: Sender<usize>,
+}
+
+impl StepTurn {
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    // Returns false if the stepper is gone and there will be no more turns.
+    pub fn wait(&self) -> bool {
+        self.turn_receiver.recv().is_ok()
+    }
+
+    pub fn ack(&self, handled: usize) {
+        if let Err(e) = self.ack_sender.send(handled) {
+            eprintln!("STEPPER: Could not acknowledge the turn: {e}");
+        }
+    }
+
+    // Waits for the turn and keeps calling `poll` until the inboxes are empty, then acknowledges.
+    // `poll` returns the number of messages it handled and whether the actor should quit.
+    // Returns true if the actor should quit.
+    pub fn take(&self, mut poll: impl FnMut() -> (usize, bool)) -> bool {
+        if !self.wait() {
+            return true;
+        }
+
+        let mut handled = 0;
+        let quit = loop {
+            let (count, quit) = poll();
+            handled += count;
+            if count == 0 || quit {
+                break quit;
+            }
+        };
+        self.ack(handled);
+
+        quit
+    }
+}
+
+// Gives turns to the hub, the Povver Plant and the factories one at a time in the order they
+// were added. Only one actor runs at a time so a step always plays out the same way.
+#[derive(Debug, Default)]
+pub struct Stepper {
+    actors: Vec<(usize, Sender<()>, Receiver<usize>)>,
+    next_id: usize,
+    // Actors that stopped answering their turns without being removed.
+    crashed: Vec<usize>,
+}
+
+impl Stepper {
+    pub fn new() -> Self {
+        Self {
+            actors: Vec::new(),
+            next_id: 0,
+            crashed: Vec::new(),
+        }
+    }
+
+    pub fn add_actor(&mut self) -> StepTurn {
+        let (turn_sender, turn_receiver) = unbounded();
+        let (ack_sender, ack_receiver) = unbounded();
+        let id = self.next_id;
+        self.next_id += 1;
+        self.actors.push((id, turn_sender, ack_receiver));
+
+        StepTurn {
+            id,
+            turn_receiver,
+            ack_sender,
+        }
+    }
+
+    // The actor gets no more turns. One that is waiting for its turn sees that it should quit.
+    pub fn remove_actor(&mut self, id: usize) {
+        self.actors.retain(|(actor_id, _, _)| *actor_id != id);
+    }
+
+    // One turn for every actor. Returns the total number of messages handled.
+    // A thread takes its end of the turn with it when it goes, so an actor that can't be given
+    // its turn, or drops it without an answer, has crashed. It gets no more turns.
+    pub fn round(&mut self) -> usize {
+        let mut handled = 0;
+        let mut crashed = Vec::new();
+        for (id, turn_sender, ack_receiver) in self.actors.iter() {
+            let ack = turn_sender.send(()).ok().and_then(|_| ack_receiver.recv().ok());
+            match ack {
+                Some(count) => handled += count,
+                None => crashed.push(*id),
+            }
+        }
+
+        if !crashed.is_empty() {
+            eprintln!("STEPPER: Actors {crashed:?} are gone without quitting.");
+            self.actors.retain(|(id, _, _)| !crashed.contains(id));
+            self.crashed.extend(crashed);
+        }
+
+        handled
+    }
+
+    // Ids of the actors that crashed so far.
+    pub fn crashed(&self) -> &[usize] {
+        &self.crashed
+    }
+
+    // Runs rounds until a whole round goes by without any actor handling a message.
+    // Actors only send messages in response to the ones they handle,
+    // so nothing is left in flight after a quiet round.
+    pub fn settle(&mut self) {
+        for _ in 0..MAX_SETTLE_ROUNDS {
+            if self.round() == 0 {
+                return;
+            }
+        }
+
+        eprintln!("STEPPER: Actors didn't settle down after {MAX_SETTLE_ROUNDS} rounds. Moving on.");
+    }
+}
@@ -0,0 +1,495 @@
+use std::{
+    fmt::{self, Write},
+    fs,
+    io,
+    path::Path,
+};
+use rand::{Rng, SeedableRng, seq::SliceRandom};
+use serde::Deserialize;
+
+use crate::{
+    config::Config,
+    simulation::batch::{FailedRun, OutcomeValue, RunOutcome},
+    utils_random::SimRng,
+};
+
+#[derive(Debug)]
+pub enum SweepError {
+    Io(io::Error),
+    Format(toml::de::Error),
+    Invalid(Vec<String>),
+}
+
+impl fmt::Display for SweepError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "sweep file error: {e}"),
+            Self::Format(e) => write!(f, "malformed sweep: {e}"),
+            Self::Invalid(problems) => {
+                write!(f, "invalid sweep:")?;
+                for problem in problems {
+                    write!(f, "\n  - {problem}")?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+impl std::error::Error for SweepError {}
+
+impl From<io::Error> for SweepError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for SweepError {
+    fn from(e: toml::de::Error) -> Self {
+        Self::Format(e)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SweepMode {
+    // Every combination of the parameter values
+    #[default]
+    Grid,
+    // Points picked at random from the parameter ranges
+    Random,
+}
+
+// A config key and the values it takes in the sweep. Either the values are listed, or they are
+// taken from a range: `steps` evenly spaced ones on a grid, anything in it with random sampling.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SweepParameter {
+    pub key: String,
+    #[serde(default)]
+    pub values: Vec<toml::Value>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub steps: Option<u32>,
+}
+
+impl SweepParameter {
+    // Values go in the type of the config value, so a range over an integer key gives integers.
+    fn typed(&self, value: f64) -> toml::Value {
+        match Config::default_value(&self.key) {
+            Some(toml::Value::Integer(_)) => toml::Value::Integer(value.round() as i64),
+            _ => toml::Value::Float(value),
+        }
+    }
+
+    fn listed(&self, value: &toml::Value) -> toml::Value {
+        match value {
+            toml::Value::Integer(int) => self.typed(*int as f64),
+            toml::Value::Float(float) => self.typed(*float),
+            _ => value.clone(),
+        }
+    }
+
+    fn grid_values(&self) -> Vec<toml::Value> {
+        if !self.values.is_empty() {
+            return self.values.iter().map(|value| self.listed(value)).collect();
+        }
+
+        let (min, max) = (self.min.unwrap(), self.max.unwrap());
+        let steps = self.steps.unwrap_or(2).max(1);
+        let mut values = (0..steps)
+            .map(|step| {
+                let value = if steps == 1 { min } else { min + (max - min) * step as f64 / (steps - 1) as f64 };
+                self.typed(value)
+            })
+            .collect::<Vec<_>>();
+        values.dedup();
+
+        values
+    }
+
+    fn random_value(&self, rng: &mut SimRng) -> toml::Value {
+        if !self.values.is_empty() {
+            return self.listed(self.values.choose(rng).unwrap());
+        }
+
+        self.typed(rng.gen_range(self.min.unwrap()..=self.max.unwrap()))
+    }
+}
+
+// The parameter values of one point of a sweep, in the order of the parameters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SweepPoint {
+    pub values: Vec<(String, toml::Value)>,
+}
+
+impl SweepPoint {
+    // In the `section.key=value` form of the config overrides.
+    pub fn overrides(&self) -> Vec<String> {
+        self.values.iter().map(|(key, value)| format!("{key}={value}")).collect()
+    }
+}
+
+// Config parameters to vary, read from a sweep file:
+//
+//     mode = "grid"
+//     seeds_per_point = 4
+//
+//     [[parameter]]
+//     key = "solar_panel.price"
+//     values = [400, 800, 1200]
+//
+//     [[parameter]]
+//     key = "factory.profit_margin"
+//     min = 10
+//     max = 40
+//     steps = 4
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Sweep {
+    #[serde(default)]
+    pub mode: SweepMode,
+    // Points drawn with random sampling. A grid has as many as there are combinations.
+    #[serde(default = "Sweep::default_points")]
+    pub points: u32,
+    // Every point runs with the same seeds, so that the points only differ in their parameters.
+    #[serde(default = "Sweep::default_seeds_per_point")]
+    pub seeds_per_point: u32,
+    #[serde(rename = "parameter")]
+    pub parameters: Vec<SweepParameter>,
+}
+
+impl Sweep {
+    fn default_points() -> u32 {
+        10
+    }
+
+    fn default_seeds_per_point() -> u32 {
+        3
+    }
+
+    pub fn load(path: &Path) -> Result<Self, SweepError> {
+        let sweep: Self = toml::from_str(&fs::read_to_string(path)?)?;
+        sweep.validate()?;
+
+        Ok(sweep)
+    }
+
+    // Collects every problem at once. Whether the values suit the config is up to the config itself.
+    pub fn validate(&self) -> Result<(), SweepError> {
+        let mut problems = Vec::new();
+
+        if self.parameters.is_empty() {
+            problems.push("there are no parameters to sweep".to_string());
+        }
+        if self.seeds_per_point == 0 {
+            problems.push("seeds_per_point must be at least 1".to_string());
+        }
+        if self.mode == SweepMode::Random && self.points == 0 {
+            problems.push("points must be at least 1".to_string());
+        }
+        for parameter in &self.parameters {
+            let key = &parameter.key;
+            match Config::default_value(key) {
+                None => problems.push(format!("{key}: not a config key")),
+                Some(toml::Value::Table(_)) => problems.push(format!("{key}: a section, not a key")),
+                Some(_) => (),
+            }
+            if self.parameters.iter().filter(|other| &other.key == key).count() > 1 {
+                problems.push(format!("{key}: swept more than once"));
+            }
+
+            let has_range = parameter.min.is_some() || parameter.max.is_some() || parameter.steps.is_some();
+            match (parameter.values.is_empty(), parameter.min, parameter.max) {
+                (false, _, _) if has_range => problems.push(format!("{key}: give either values or a range, not both")),
+                (false, _, _) => (),
+                (true, Some(min), Some(max)) if min > max => problems.push(format!("{key}: min {min} is above max {max}")),
+                (true, Some(_), Some(_)) => (),
+                (true, _, _) => problems.push(format!("{key}: give either values or a min and a max")),
+            }
+            if parameter.steps == Some(0) {
+                problems.push(format!("{key}: steps must be at least 1"));
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(SweepError::Invalid(problems))
+        }
+    }
+
+    // The seed only matters for random sampling.
+    pub fn points(&self, seed: u64) -> Vec<SweepPoint> {
+        match self.mode {
+            SweepMode::Grid => {
+                let mut points = vec![SweepPoint { values: Vec::new() }];
+                for parameter in &self.parameters {
+                    let values = parameter.grid_values();
+                    points = points.into_iter()
+                        .flat_map(|point| values.iter().map(move |value| {
+                            let mut point = point.clone();
+                            point.values.push((parameter.key.clone(), value.clone()));
+                            point
+                        }))
+                        .collect();
+                }
+
+                points
+            },
+            SweepMode::Random => {
+                let mut rng = SimRng::seed_from_u64(seed);
+                (0..self.points)
+                    .map(|_| SweepPoint {
+                        values: self.parameters.iter()
+                            .map(|parameter| (parameter.key.clone(), parameter.random_value(&mut rng)))
+                            .collect(),
+                    })
+                    .collect()
+            },
+        }
+    }
+}
+
+// Outcomes of every run of a sweep, along with the point each of them ran.
+#[derive(Debug, Clone, Default)]
+pub struct SweepSummary {
+    pub points: Vec<SweepPoint>,
+    pub outcomes: Vec<(usize, RunOutcome)>,
+    pub failed: Vec<(usize, FailedRun)>,
+}
+
+impl SweepSummary {
+    fn keys(&self) -> Vec<&str> {
+        self.points.first()
+            .map(|point| point.values.iter().map(|(key, _)| key.as_str()).collect())
+            .unwrap_or_default()
+    }
+
+    // Tidy table with one row per run: the point, its parameter values, then the outcomes.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("point");
+        for key in self.keys() {
+            let _ = write!(csv, ",{key}");
+        }
+        let _ = writeln!(csv, ",{}", RunOutcome::CSV_HEADER);
+
+        for (point, outcome) in &self.outcomes {
+            csv += &point.to_string();
+            for (_, value) in &self.points[*point].values {
+                let _ = write!(csv, ",{value}");
+            }
+            let _ = writeln!(csv, ",{}", outcome.csv_row());
+        }
+
+        csv
+    }
+
+    // Means of the outcomes over the seeds of every point.
+    pub fn table(&self) -> String {
+        let keys = self.keys();
+        let columns: [(&str, OutcomeValue); 5] = [
+            ("bankrupt", |outcome| outcome.bankrupt_factories as f64),
+            ("pp balance", |outcome| outcome.pp_balance as f64),
+            ("factory balance", |outcome| outcome.factory_balance_mean as f64),
+            ("renewable %", |outcome| outcome.renewable_share as f64),
+            ("fulfilment %", |outcome| outcome.demand_fulfilment as f64),
+        ];
+
+        let mut table = format!(
+            "{} points, {} runs finished, {} failed. Outcomes are means over the seeds of each point.\n",
+            self.points.len(), self.outcomes.len(), self.failed.len()
+        );
+        let _ = write!(table, "{:>6}", "point");
+        for key in &keys {
+            let _ = write!(table, "{:>width$}", key, width = key.len().max(10) + 2);
+        }
+        for (name, _) in &columns {
+            let _ = write!(table, "{:>16}", name);
+        }
+        table.push('\n');
+
+        for (index, point) in self.points.iter().enumerate() {
+            let outcomes = self.outcomes.iter()
+                .filter(|(point, _)| *point == index)
+                .map(|(_, outcome)| outcome)
+                .collect::<Vec<_>>();
+
+            let _ = write!(table, "{:>6}", index);
+            for ((key, value), _) in point.values.iter().zip(&keys) {
+                let _ = write!(table, "{:>width$}", value.to_string(), width = key.len().max(10) + 2);
+            }
+            for (_, value) in &columns {
+                if outcomes.is_empty() {
+                    let _ = write!(table, "{:>16}", "-");
+                } else {
+                    let mean = outcomes.iter().map(|outcome| value(outcome)).sum::<f64>() / outcomes.len() as f64;
+                    let _ = write!(table, "{:>16.2}", mean);
+                }
+            }
+            table.push('\n');
+        }
+        for (point, failed) in &self.failed {
+            let _ = writeln!(table, "Run of point {point} with seed {} failed: {}", failed.seed, failed.reason);
+        }
+
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sweep(text: &str) -> Sweep {
+        toml::from_str(text).unwrap()
+    }
+
+    fn invalid(text: &str) -> Vec<String> {
+        match sweep(text).validate() {
+            Err(SweepError::Invalid(problems)) => problems,
+            other => panic!("expected an invalid sweep, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn grid_has_every_combination() {
+        let sweep = sweep(r#"
+            [[parameter]]
+            key = "solar_panel.price"
+            values = [400.0, 800.0]
+
+            [[parameter]]
+            key = "factory.profit_margin"
+            min = 10
+            max = 40
+            steps = 3
+        "#);
+        sweep.validate().unwrap();
+
+        let overrides = sweep.points(1).iter().map(SweepPoint::overrides).collect::<Vec<_>>();
+        let expected = [
+            ["solar_panel.price=400.0", "factory.profit_margin=10.0"],
+            ["solar_panel.price=400.0", "factory.profit_margin=25.0"],
+            ["solar_panel.price=400.0", "factory.profit_margin=40.0"],
+            ["solar_panel.price=800.0", "factory.profit_margin=10.0"],
+            ["solar_panel.price=800.0", "factory.profit_margin=25.0"],
+            ["solar_panel.price=800.0", "factory.profit_margin=40.0"],
+        ];
+        assert_eq!(overrides, expected.map(|point| point.map(String::from).to_vec()).to_vec());
+    }
+
+    #[test]
+    fn integer_keys_get_rounded_integers() {
+        let sweep = sweep(r#"
+            [[parameter]]
+            key = "battery.capacity"
+            min = 1000
+            max = 2000
+            steps = 4
+
+            [[parameter]]
+            key = "factory.max_batteries"
+            values = [2.4, 2.6]
+        "#);
+        sweep.validate().unwrap();
+
+        assert_eq!(
+            sweep.parameters[0].grid_values(),
+            [1000, 1333, 1667, 2000].map(toml::Value::Integer).to_vec(),
+        );
+        // Values that round to the same integer only come once.
+        let close = SweepParameter { min: Some(1.0), max: Some(2.0), steps: Some(5), ..sweep.parameters[0].clone() };
+        assert_eq!(close.grid_values(), [1, 2].map(toml::Value::Integer).to_vec());
+
+        assert_eq!(sweep.parameters[1].grid_values(), [2, 3].map(toml::Value::Integer).to_vec());
+        let mut rng = SimRng::seed_from_u64(5);
+        for _ in 0..20 {
+            assert!(sweep.parameters[0].random_value(&mut rng).is_integer());
+        }
+    }
+
+    #[test]
+    fn random_points_follow_the_seed() {
+        let sweep = sweep(r#"
+            mode = "random"
+            points = 5
+
+            [[parameter]]
+            key = "solar_panel.price"
+            min = 200
+            max = 2000
+
+            [[parameter]]
+            key = "battery.capacity"
+            values = [1000, 2000, 3000]
+        "#);
+        sweep.validate().unwrap();
+
+        let points = sweep.points(42);
+        assert_eq!(points.len(), 5);
+        assert_eq!(points, sweep.points(42));
+        assert_ne!(points, sweep.points(43));
+        for point in &points {
+            let price = point.values[0].1.as_float().unwrap();
+            assert!((200.0..=2000.0).contains(&price), "{price}");
+            assert!([1000, 2000, 3000].contains(&point.values[1].1.as_integer().unwrap()));
+        }
+    }
+
+    #[test]
+    fn values_and_a_range_together_are_rejected() {
+        let problems = invalid(r#"
+            [[parameter]]
+            key = "solar_panel.price"
+            values = [400.0]
+            min = 200
+            max = 2000
+        "#);
+        assert_eq!(problems, vec!["solar_panel.price: give either values or a range, not both".to_string()]);
+    }
+
+    #[test]
+    fn every_problem_is_reported_at_once() {
+        let problems = invalid(r#"
+            mode = "random"
+            points = 0
+            seeds_per_point = 0
+
+            [[parameter]]
+            key = "solar_panel.price"
+            min = 2000
+            max = 200
+
+            [[parameter]]
+            key = "factory.profit_margin"
+            min = 10
+
+            [[parameter]]
+            key = "factory.no_such_key"
+            values = [1]
+
+            [[parameter]]
+            key = "battery"
+            values = [1]
+
+            [[parameter]]
+            key = "battery.capacity"
+            min = 1
+            max = 2
+            steps = 0
+        "#);
+        assert_eq!(problems, [
+            "seeds_per_point must be at least 1",
+            "points must be at least 1",
+            "solar_panel.price: min 2000 is above max 200",
+            "factory.profit_margin: give either values or a min and a max",
+            "factory.no_such_key: not a config key",
+            "battery: a section, not a key",
+            "battery.capacity: steps must be at least 1",
+        ].map(String::from).to_vec());
+
+        let problems = invalid("parameter = []");
+        assert_eq!(problems, vec!["there are no parameters to sweep".to_string()]);
+    }
+}
@@ -1,16 +1,80 @@
 use std::{
+    fmt,
+    str::FromStr,
     sync::{Arc, RwLock},
     thread,
     time::{Duration, Instant},
 };
 use crossbeam_channel::{tick, Receiver};
+use serde::{Serialize, Deserialize};
 
 use crate::{
     app_state::TimerStateData,
-    simulation::{SimInt, TickDuration},
-    ui_controller::Date,
+    simulation::{SimInt, TickDuration, calendar::Calendar},
     environment::months::get_month_data,
 };
+#[cfg(feature = "gui")]
+use crate::ui_controller::Date as UIDate;
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Date {
+    pub minute: SimInt,
+    pub hour: SimInt,
+    pub day: SimInt,
+    pub month: SimInt,
+    pub year: SimInt,
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02} {:02}:{:02}", self.year, self.month, self.day, self.hour, self.minute)
+    }
+}
+
+// Reads dates in the same `YYYY-MM-DD HH:MM` form they are displayed in. The time can be
+// left out for midnight, and a `T` works in place of the space.
+impl FromStr for Date {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad = || format!("\"{s}\" is not a date like 2025-07-01 or 2025-07-01 12:00");
+        let number = |part: &str| part.parse::<SimInt>().map_err(|_| bad());
+
+        let (date, time) = match s.trim().split_once([' ', 'T']) {
+            Some((date, time)) => (date, Some(time)),
+            None => (s.trim(), None),
+        };
+        let [year, month, day] = date.split('-').collect::<Vec<_>>()[..] else {
+            return Err(bad());
+        };
+        let (hour, minute) = match time.map(|time| time.split_once(':')) {
+            None => (0, 0),
+            Some(Some((hour, minute))) => (number(hour)?, number(minute)?),
+            Some(None) => return Err(bad()),
+        };
+
+        Ok(Self {
+            minute,
+            hour,
+            day: number(day)?,
+            month: number(month)?,
+            year: number(year)?,
+        })
+    }
+}
+
+#[cfg(feature = "gui")]
+impl From<Date> for UIDate {
+    fn from(date: Date) -> Self {
+        Self {
+            minute: date.minute,
+            hour: date.hour,
+            day: date.day,
+            month: date.month,
+            year: date.year,
+        }
+    }
+}
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum TimerEvent {
@@ -53,6 +117,7 @@ impl TimerEvent {
 pub struct Timer
 {
     tick_duration: TickDuration,
+    calendar: Calendar,
     tick_count: u128,
     timer_state: Arc<RwLock<TimerStateData>>,
     pub ticker: Receiver<Instant>,
@@ -60,18 +125,8 @@ pub struct Timer
 
 // Constructor
 impl Timer {
-    pub fn new(tick_duration: TickDuration, init_date: Date) -> (Self, Arc<RwLock<TimerStateData>>) {
-        let tick_count =
-        (
-            (
-                (
-                    init_date.hour +
-                    ((init_date.day - 1) * 24) +
-                    ((init_date.month - 1) * 30 * 24) +
-                    (init_date.year * 12 * 30 * 24)
-                ) * 60
-            ) + init_date.minute
-        ) as u128;
+    pub fn new(tick_duration: TickDuration, calendar: Calendar, init_date: Date) -> (Self, Arc<RwLock<TimerStateData>>) {
+        let tick_count = calendar.to_timestamp(&init_date);
 
         let month_data = get_month_data(init_date.month as usize);
         let timer_state = Arc::new(RwLock::new(TimerStateData {
@@ -83,6 +138,7 @@ impl Timer {
         (
             Self {
                 tick_duration,
+                calendar,
                 tick_count,
                 timer_state: Arc::clone(&timer_state),
                 ticker: tick(Duration::from_millis(tick_duration)),
@@ -95,26 +151,7 @@ impl Timer {
 // Private methods
 impl Timer {
     fn get_updated_date(&self) -> Date {
-        let minute = (self.tick_count % 60) as SimInt;
-
-        let total_hours = self.tick_count / 60;
-        let hour = (total_hours % 24) as SimInt;
-
-        let total_days = total_hours / 24;
-        let day = (total_days % 30) as SimInt + 1;
-
-        let total_months = total_days / 30;
-        let month = (total_months % 12) as SimInt + 1;
-
-        let year = (total_months / 12) as SimInt;
-
-        Date {
-            minute,
-            hour,
-            day,
-            month,
-            year,
-        }
+        self.calendar.from_timestamp(self.tick_count)
     }
 }
 
@@ -128,12 +165,15 @@ impl Timer {
             event = TimerEvent::NothingUnusual;
 
             let mut ts_lock = self.timer_state.write().unwrap();
+            // A new year starts with a new month too.
+            if date.month != ts_lock.date.month {
+                ts_lock.month_data = get_month_data(date.month as usize);
+            }
             let prev_date = &ts_lock.date;
 
             if date.year != prev_date.year {
                 event = TimerEvent::YearChange;
             } else if date.month != prev_date.month {
-                ts_lock.month_data = get_month_data(date.month as usize);
                 event = TimerEvent::MonthChange;
             } else if date.day != prev_date.day {
                 event = TimerEvent::DayChange;
@@ -152,6 +192,27 @@ impl Timer {
         event
     }
 
+    // Moves the clock silently to the given minute, so that the next tick goes on from there.
+    // Only month changes update the month data, so it must stay within the same hour.
+    pub fn skip_to(&mut self, timestamp: u128) {
+        self.tick_count = timestamp;
+        let date = self.get_updated_date();
+
+        let mut ts_lock = self.timer_state.write().unwrap();
+        ts_lock.date = date;
+        ts_lock.timestamp = self.tick_count;
+    }
+
+    pub fn calendar(&self) -> &Calendar {
+        &self.calendar
+    }
+
+    // Picks up the clock from a snapshot. The timestamp is the tick count.
+    pub fn restore(&mut self, state: TimerStateData) {
+        self.tick_count = state.timestamp;
+        *self.timer_state.write().unwrap() = state;
+    }
+
     pub fn set_tick_duration(&mut self, duration_ms: TickDuration) {
         self.tick_duration = duration_ms;
         self.ticker = tick(Duration::from_millis(duration_ms));
@@ -161,3 +222,19 @@ impl Timer {
         self.tick_duration
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_year_brings_january_month_data() {
+        let (mut timer, timer_state) = Timer::new(1000, Calendar::default(), "2024-12-31 23:59".parse().unwrap());
+        assert_eq!(timer_state.read().unwrap().month_data.number, 12);
+
+        assert_eq!(timer.tick(false), TimerEvent::YearChange);
+        let state = timer_state.read().unwrap();
+        assert_eq!(state.date, "2025-01-01".parse().unwrap());
+        assert_eq!(state.month_data, get_month_data(1));
+    }
+}
@@ -1,8 +1,9 @@
 use std::{
+    rc::Rc,
     sync::Arc,
     thread,
 };
-use tokio::sync::broadcast as tokio_broadcast;
+use tokio::sync::broadcast::{self as tokio_broadcast, error::RecvError};
 
 use slint::{ModelRc, CloseRequestResponse, SharedString, Model, VecModel, FilterModel, ToSharedString};
 
@@ -13,17 +14,13 @@ use crate::{
         StateAction,
         timer::TimerEvent,
         EconUpdate,
+        UIFlag,
         hub_comms::MessageEntity,
     },
     logger::LogMessage as LoggerMessage,
+    environment::WindSpeedLevel as EnvWindSpeedLevel,
 };
 
-pub enum UIFlag {
-    Pause,
-    Quit,
-    SpeedChange(SimInt),
-}
-
 slint::include_modules!();
 
 pub struct UIController {}
@@ -41,7 +38,6 @@ impl UIController {
         mut wakeup_receiver: tokio_broadcast::Receiver<StateAction>,
         mut log_receiver: tokio_broadcast::Receiver<LoggerMessage>,
         state: Arc<StatePayload>,
-        factory_count: usize,
     ) -> thread::JoinHandle<()> {
         let flag_sender_close = flag_sender.clone();
         let flag_sender_speed = flag_sender.clone();
@@ -79,31 +75,55 @@ impl UIController {
                 let hub_filtered = FilterModel::from(messages_rc.clone().filter(|msg| msg.source == MessageSource::Hub));
                 let pp_filtered = FilterModel::from(messages_rc.clone().filter(|msg| msg.source == MessageSource::PP));
 
-                let factory_ids = (0..factory_count).map(|i| i as SimInt).collect::<Vec<SimInt>>();
-                let factory_model_empty = factory_ids.iter().map(|_| ModelRc::from(VecModel::from_slice(&[]))).collect::<Vec<_>>();
-                let factory_filtered: ModelRc<ModelRc<LogMessage>> = ModelRc::from(VecModel::from_slice(&factory_model_empty));
+                // Factory messages are kept by factory id. Ids of retired factories are not given out again,
+                // so their rows just stay behind.
+                let factory_filtered_model = Rc::new(VecModel::<ModelRc<LogMessage>>::default());
+                let factory_filtered: ModelRc<ModelRc<LogMessage>> = ModelRc::from(Rc::clone(&factory_filtered_model));
 
                 // This is necessary gymnastics in order to prevent rerender of factories on the map
-                // Every time factory states change. We use a simple integer that doesn't change
-                // to prevent the rerenders.
-                appw.set_factory_count(factory_count as SimInt);
+                // Every time factory states change. We use a list of ids that only changes
+                // when factories are added or retired to prevent the rerenders.
+                let show_factories = || {
+                    let factory_ids = state.factories.read().unwrap()
+                        .iter()
+                        .map(|fs| fs.read().unwrap().id as SimInt)
+                        .collect::<Vec<SimInt>>();
+                    let rows = factory_ids.iter().max().map_or(0, |id| *id as usize + 1);
+                    while factory_filtered_model.row_count() < rows {
+                        factory_filtered_model.push(ModelRc::from(VecModel::from_slice(&[])));
+                    }
+                    appw.set_factory_ids(ModelRc::from(factory_ids.as_slice()));
+                };
+                show_factories();
+
+                // Month panel is refreshed whenever the month changes.
+                // A simulation restored from a snapshot doesn't start with a month change event.
+                let mut shown_month = 0;
 
                 // Main UI loop. This will update UI state when signals from the outside pour in
-                while let Ok(action) = wakeup_receiver.recv().await {
+                loop {
+                    let action = match wakeup_receiver.recv().await {
+                        Ok(action) => action,
+                        // At max speed the simulation easily outruns the UI. We just skip what we missed.
+                        Err(RecvError::Lagged(_)) => continue,
+                        Err(RecvError::Closed) => break,
+                    };
+
                     match action {
                         StateAction::Timer(event) => {
                             {
                                 let timer_lock = state.timer.read().unwrap();
                                 appw.set_timer(
                                     TimerData {
-                                        date: timer_lock.date.clone(),
+                                        date: timer_lock.date.clone().into(),
                                     }
                                 );
                             }
 
-                            if event.at_least_month() {
-                                {
-                                    let timer_lock = state.timer.read().unwrap();
+                            {
+                                let timer_lock = state.timer.read().unwrap();
+                                if timer_lock.month_data.number != shown_month {
+                                    shown_month = timer_lock.month_data.number;
                                     appw.set_month(
                                         MonthData {
                                             day_start: timer_lock.month_data.day_start,
@@ -152,12 +172,17 @@ impl UIController {
                                                             id: fstate.id as SimInt,
                                                             balance: fstate.balance.val(),
                                                             available_energy: fstate.available_energy.val(),
+                                                            stored_energy: fstate.stored_energy(),
                                                             product_stocks,
                                                             solarpanels: fstate.solarpanels.len() as SimInt,
+                                                            windturbines: fstate.windturbines.len() as SimInt,
+                                                            batteries: fstate.batteries.len() as SimInt,
                                                             industry: fstate.industry.name.to_shared_string(),
                                                             product_portfolio,
                                                             is_bankrupt: fstate.is_bankrupt,
                                                             is_awaiting_solarpanels: fstate.is_awaiting_solarpanels,
+                                                            is_awaiting_windturbines: fstate.is_awaiting_windturbines,
+                                                            is_awaiting_batteries: fstate.is_awaiting_batteries,
                                                         }
                                                     }).collect::<Vec<FactoryState>>().as_slice(),
                                                 )
@@ -206,10 +231,16 @@ impl UIController {
                             appw.set_env(
                                 EnvData {
                                     the_sun: (&env_lock.the_sun).into(),
-                                    wind_direction: env_lock.wind_direction,
+                                    wind_direction: env_lock.wind_direction.into(),
                                     wind_speed: env_lock.wind_speed.val(),
-                                    wind_speed_level: WindSpeedLevel::from(&env_lock.wind_speed),
-                                    clouds: ModelRc::from(env_lock.clouds.as_slice()),
+                                    wind_speed_level: EnvWindSpeedLevel::from(&env_lock.wind_speed).into(),
+                                    clouds: ModelRc::from(
+                                        env_lock.clouds
+                                            .iter()
+                                            .map(|cloud| cloud.into())
+                                            .collect::<Vec<Cloud>>()
+                                            .as_slice()
+                                    ),
                                 }
                             );
                         },
@@ -239,6 +270,9 @@ impl UIController {
                                 }
                             }
                         }
+                        StateAction::Factories => {
+                            show_factories();
+                        },
                         StateAction::Misc => {
                             let misc_lock = state.misc.lock().unwrap();
                             appw.set_misc(UIMisc {
@@ -2,8 +2,9 @@ use std::{
     collections::VecDeque,
     sync::{Arc, RwLock, RwLockReadGuard},
 };
+use serde::{Serialize, Deserialize};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SlidingWindow<T> {
     data: VecDeque<T>,
     capacity: usize,
@@ -57,7 +58,17 @@ impl<T> ReadOnlyRwLock<T> {
         Self(from.0.clone())
     }
 
-    pub fn read(&self) -> std::sync::LockResult<RwLockReadGuard<T>> {
+    pub fn read(&self) -> std::sync::LockResult<RwLockReadGuard<'_, T>> {
         self.0.read()
     }
 }
+
+// Our floats are f32 and they'd come out as 11.399999618530273 instead of 11.4 otherwise.
+pub fn tidy_toml_floats(value: &mut toml::Value) {
+    match value {
+        toml::Value::Float(float) => *float = (*float as f32).to_string().parse().unwrap(),
+        toml::Value::Array(values) => values.iter_mut().for_each(tidy_toml_floats),
+        toml::Value::Table(table) => table.iter_mut().for_each(|(_, value)| tidy_toml_floats(value)),
+        _ => (),
+    }
+}
@@ -1,5 +1,69 @@
 use num_traits::{SaturatingAdd, SaturatingSub, Signed, Unsigned};
-use rand::{distributions::uniform::SampleUniform, Rng};
+use rand::{distributions::uniform::SampleUniform, Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+
+/// Random number generator used throughout the simulation.
+/// It's always derived from a `SeedBank` so that runs can be reproduced.
+/// Same algorithm as rand's `StdRng`, but its state can be saved in snapshots.
+pub type SimRng = ChaCha12Rng;
+
+/// Independent random number streams of the simulation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RngStream {
+    Economy,
+    Environment,
+    PovverPlant,
+    Factory(usize),
+}
+
+impl RngStream {
+    fn id(&self) -> u64 {
+        match self {
+            Self::Economy => 1,
+            Self::Environment => 2,
+            Self::PovverPlant => 3,
+            Self::Factory(id) => 0x100 + *id as u64,
+        }
+    }
+}
+
+/// Holds the master seed of a run and derives a reproducible `SimRng` for each `RngStream`.
+/// Every stream gets its own generator, so adding a factory doesn't shift the random numbers
+/// the economy or the environment get.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SeedBank {
+    master_seed: u64,
+}
+
+impl SeedBank {
+    pub fn new(master_seed: u64) -> Self {
+        Self {
+            master_seed,
+        }
+    }
+
+    /// A seed bank with a fresh master seed from the OS entropy.
+    pub fn from_entropy() -> Self {
+        Self::new(rand::random())
+    }
+
+    pub fn master_seed(&self) -> u64 {
+        self.master_seed
+    }
+
+    pub fn stream(&self, stream: RngStream) -> SimRng {
+        SimRng::seed_from_u64(splitmix64(self.master_seed ^ splitmix64(stream.id())))
+    }
+}
+
+// SplitMix64 finalizer. Scatters neighbouring seeds and stream ids far apart.
+fn splitmix64(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+
+    z ^ (z >> 31)
+}
 
 /// Pick a random number between `lower_modifier` and `upper_modifier` for a given value
 /// that also clamps between a `min` and `max`.
@@ -0,0 +1,58 @@
+// Skipping quiet minutes must not change how the world turns out.
+// The same seed is run minute by minute and with skipping, and both have to end up the same.
+
+use povver::{
+    Simulation, SimOptions, RunMode, ClockMode, LogOutput, Config,
+    simulation::metrics::{MetricsOptions, MetricsInterval, MetricsFormat},
+};
+
+const SEED: u64 = 7;
+const DAYS: u32 = 3;
+
+fn run(name: &str, config: Config, skip_quiet_minutes: bool) -> (serde_json::Value, serde_json::Value) {
+    let log_path = std::env::temp_dir().join(format!("povver-skip-{name}-{skip_quiet_minutes}.log"));
+    let mut sim = Simulation::new(SimOptions {
+        run_mode: RunMode::Headless,
+        log_output: LogOutput::File(log_path),
+        seed: Some(SEED),
+        clock_mode: ClockMode::Stepped,
+        metrics: Some(MetricsOptions {
+            interval: MetricsInterval::Hourly,
+            path: None,
+            format: MetricsFormat::JsonLines,
+        }),
+        config,
+        skip_quiet_minutes,
+        ..SimOptions::default()
+    }).unwrap();
+
+    sim.start();
+    sim.advance(DAYS * 24 * 60);
+    let snapshot = serde_json::to_value(sim.snapshot().unwrap()).unwrap();
+    let metrics = serde_json::to_value(sim.metrics()).unwrap();
+    sim.shutdown();
+
+    (snapshot, metrics)
+}
+
+fn assert_same_with_skipping(name: &str, config: Config) {
+    let (step_snapshot, step_metrics) = run(name, config.clone(), false);
+    let (skip_snapshot, skip_metrics) = run(name, config, true);
+
+    assert_eq!(step_snapshot, skip_snapshot, "snapshots differ");
+    assert_eq!(step_metrics, skip_metrics, "metrics differ");
+}
+
+#[test]
+fn same_world_with_the_default_config() {
+    assert_same_with_skipping("default", Config::default());
+}
+
+#[test]
+fn same_world_with_the_exchange_and_tariffs() {
+    let mut config = Config::default();
+    config.exchange.enabled = true;
+    config.tariff.enabled = true;
+
+    assert_same_with_skipping("exchange", config);
+}